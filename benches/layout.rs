@@ -0,0 +1,73 @@
+//! Compares the pixel layouts of [`Image`]: run this once as is, for rows,
+//! and once with `--features morton`, for Z-order, saving a baseline the
+//! first time to compare against the second:
+//!
+//! ```sh
+//! cargo bench --bench layout -- --save-baseline rows
+//! cargo bench --bench layout --features morton -- --baseline rows
+//! ```
+
+use circlez::{Approximator, Config, Image, SquaredError};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use image::RgbImage;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How many shapes each loss evaluation sums over, spread around the image
+/// so that they don't all stay in the caches.
+const SHAPES: usize = 64;
+
+fn layout(c: &mut Criterion) {
+    // Large enough for rows of the image to be far apart in memory
+    let target = Image::from(RgbImage::from_fn(2048, 2048, |x, y| {
+        [(x * 7 + y) as u8, (x ^ y) as u8, (y * 3) as u8].into()
+    }));
+    let approx = Image::blank(&target);
+    let mut rng = StdRng::seed_from_u64(1);
+
+    println!(
+        "layout: {}",
+        if cfg!(feature = "morton") { "Z-order" } else { "rows" }
+    );
+
+    let mut group = c.benchmark_group("layout");
+
+    for radius in [4, 30, 150] {
+        let shapes: Vec<Vec<_>> = (0..SHAPES)
+            .map(|_| {
+                let [x, y] = [0, 1].map(|_| rng.random_range(radius..2048 - radius) as isize);
+
+                circlez::generate_filled_circle_points(x, y, radius as isize)
+                    .into_iter()
+                    .map(|[x, y]| ([x as u32, y as u32], [200, 100, 50], 1.0))
+                    .collect()
+            })
+            .collect();
+
+        group.bench_with_input(BenchmarkId::new("loss_delta", radius), &shapes, |b, shapes| {
+            b.iter(|| {
+                shapes
+                    .iter()
+                    .map(|changes| Image::loss_delta(&target, &approx, changes, &SquaredError))
+                    .sum::<f64>()
+            })
+        });
+    }
+
+    for fill in [false, true] {
+        let config = Config {
+            fill,
+            ..Config::default()
+        };
+        let mut approximator =
+            Approximator::new(target.clone(), config, StdRng::seed_from_u64(1));
+        let name = if fill { "tick/filled" } else { "tick/outline" };
+
+        group.bench_function(name, |b| b.iter(|| approximator.tick()));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, layout);
+criterion_main!(benches);
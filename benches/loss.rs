@@ -0,0 +1,49 @@
+use circlez::{Color, Image, Loss, SquaredError};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use image::RgbImage;
+use std::hint::black_box;
+
+/// The squared error without the SIMD [`Loss::delta`], for comparison.
+struct ScalarSquaredError;
+
+impl Loss for ScalarSquaredError {
+    fn pixel(&self, a: Color, b: Color) -> f32 {
+        Image::pixel_loss(a, b)
+    }
+}
+
+fn loss_delta(c: &mut Criterion) {
+    let target = Image::from(RgbImage::from_fn(512, 512, |x, y| {
+        [(x * 7 + y) as u8, (x ^ y) as u8, (y * 3) as u8].into()
+    }));
+    let approx = Image::blank(&target);
+
+    let mut group = c.benchmark_group("loss_delta");
+
+    for radius in [30, 150] {
+        let changes: Vec<_> = circlez::generate_filled_circle_points(256, 256, radius)
+            .into_iter()
+            .map(|[x, y]| ([x as u32, y as u32], [200, 100, 50], 1.0))
+            .collect();
+
+        group.bench_with_input(BenchmarkId::new("scalar", radius), &changes, |b, changes| {
+            b.iter(|| {
+                Image::loss_delta(&target, &approx, changes, &ScalarSquaredError)
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("simd", radius), &changes, |b, changes| {
+            b.iter(|| Image::loss_delta(&target, &approx, changes, &SquaredError))
+        });
+
+        assert_eq!(
+            Image::loss_delta(&target, &approx, &changes, &ScalarSquaredError),
+            Image::loss_delta(&target, &approx, black_box(&changes), &SquaredError)
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, loss_delta);
+criterion_main!(benches);
@@ -0,0 +1,85 @@
+//! The building blocks every tick spends its time in, on fixed inputs so
+//! that numbers compare across runs and changes: the loss of a single
+//! pixel, rasterizing circles (whole, and clipped to the canvas), and
+//! compositing a shape onto the canvas.
+//! The loss of a whole shape is in `benches/loss.rs`.
+
+use circlez::{Circle, Color, Image, Primitive, SquaredError};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use image::RgbImage;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How many color pairs the loss of a pixel is summed over.
+const PAIRS: usize = 4096;
+
+fn pixel_loss(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(1);
+    let pairs: Vec<(Color, Color)> = (0..PAIRS).map(|_| (rng.random(), rng.random())).collect();
+
+    c.bench_function("pixel_loss", |b| {
+        b.iter(|| pairs.iter().map(|&(a, b)| Image::pixel_loss(a, b)).sum::<f32>())
+    });
+}
+
+fn circle_points(c: &mut Criterion) {
+    let mut group = c.benchmark_group("circle_points");
+
+    // Outlines are only worked out once per radius, so this is the cached path
+    for radius in [4, 30, 150] {
+        group.bench_with_input(BenchmarkId::new("outline", radius), &radius, |b, &radius| {
+            b.iter(|| circlez::generate_circle_points(256, 256, radius))
+        });
+
+        group.bench_with_input(BenchmarkId::new("filled", radius), &radius, |b, &radius| {
+            b.iter(|| circlez::generate_filled_circle_points(256, 256, radius))
+        });
+
+        // Three quarters of a circle on a corner of the canvas are off it;
+        // ticks reuse the buffer like this, so these don't allocate
+        for filled in [false, true] {
+            let circle = Circle { center: [0, 0], radius, filled };
+            let name = if filled { "filled_corner" } else { "outline_corner" };
+            let mut points = Vec::new();
+
+            group.bench_with_input(BenchmarkId::new(name, radius), &circle, |b, circle| {
+                b.iter(|| {
+                    circle.points_into((512, 512), &mut points);
+                    points.len()
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn compose(c: &mut Criterion) {
+    let target = Image::from(RgbImage::from_fn(512, 512, |x, y| {
+        [(x * 7 + y) as u8, (x ^ y) as u8, (y * 3) as u8].into()
+    }));
+    let approx = Image::blank(&target);
+
+    let mut group = c.benchmark_group("compose");
+
+    // Opaque shapes overwrite pixels, translucent ones blend with them
+    for opacity in [1.0, 0.5] {
+        let changes: Vec<_> = circlez::generate_filled_circle_points(256, 256, 150)
+            .into_iter()
+            .map(|[x, y]| ([x as u32, y as u32], [200, 100, 50], opacity))
+            .collect();
+
+        group.bench_with_input(BenchmarkId::new("apply", opacity), &changes, |b, changes| {
+            b.iter_batched_ref(
+                || approx.clone(),
+                |approx| approx.apply(&target, changes, &SquaredError),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, pixel_loss, circle_points, compose);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+use circlez::{Approximator, Config, Image};
+use criterion::{criterion_group, criterion_main, Criterion};
+use image::RgbImage;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts every allocation, to see how many a tick makes.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// How many ticks allocations are counted over.
+const COUNTED_TICKS: usize = 10_000;
+
+fn tick(c: &mut Criterion) {
+    let target = Image::from(RgbImage::from_fn(256, 256, |x, y| {
+        [(x * 7 + y) as u8, (x ^ y) as u8, (y * 3) as u8].into()
+    }));
+
+    let mut group = c.benchmark_group("tick");
+
+    for (name, fill) in [("outline", false), ("filled", true)] {
+        let config = Config {
+            fill,
+            ..Config::default()
+        };
+        let mut approximator =
+            Approximator::new(target.clone(), config, StdRng::seed_from_u64(1));
+
+        // Let the buffers and caches grow to their working size first
+        approximator.run(1000);
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        approximator.run(COUNTED_TICKS);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+        println!(
+            "tick/{name}: {:.3} allocations per tick",
+            allocations as f64 / COUNTED_TICKS as f64
+        );
+
+        group.bench_function(name, |b| b.iter(|| approximator.tick()));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, tick);
+criterion_main!(benches);
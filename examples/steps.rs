@@ -0,0 +1,60 @@
+//! Draws a synthetic target one shape at a time with
+//! [`Approximator::steps`], printing how close the approximation gets after
+//! each shape, then hands the loop over to [`Approximator::run_with`].
+//!
+//! Run with `cargo run --release --example steps [shapes]`.
+
+use circlez::{psnr, Approximator, Config, Image};
+use image::RgbImage;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::ops::ControlFlow;
+
+fn main() {
+    let shapes = match std::env::args().nth(1) {
+        Some(arg) => arg.parse().expect("the number of shapes should be a number"),
+        None => 20,
+    };
+
+    let target = Image::from(RgbImage::from_fn(128, 128, |x, y| {
+        [(x * 2) as u8, (y * 2) as u8, ((x + y) / 2) as u8].into()
+    }));
+
+    let mut approximator = Approximator::new(target, Config::default(), StdRng::seed_from_u64(1));
+    let mut steps = approximator.steps();
+
+    for step in 1..=shapes {
+        let Some(drawn) = steps.next() else {
+            println!("Gave up after {} shapes", step - 1);
+            break;
+        };
+
+        let approximator = steps.approximator();
+        println!(
+            "shape {step:>3} at tick {:>5}: {:?}, PSNR {:.2} dB",
+            drawn.tick,
+            drawn.placed.shape,
+            psnr(approximator.approx(), approximator.target())
+        );
+    }
+
+    // Or just collect them, carrying on from where the loop stopped
+    let more: Vec<_> = approximator.steps().take(shapes).collect();
+    println!(
+        "{} more shapes by tick {}",
+        more.len(),
+        more.last().map_or(0, |drawn| drawn.tick)
+    );
+
+    // Or let the approximator own the loop, stopping once it's close enough
+    approximator.run_with(100_000, 1000, |approximator| {
+        let psnr = psnr(approximator.approx(), approximator.target());
+        println!("tick {:>6}: PSNR {psnr:.2} dB", approximator.ticks());
+
+        if psnr > 20.0 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+}
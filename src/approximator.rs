@@ -0,0 +1,1220 @@
+use crate::error_map::ErrorMap;
+use crate::spacing::RecentCenters;
+use crate::{
+    sample, with_scratch, Checkpoint, Color, Config, Image, PlacedShape, Placement, Point,
+    Primitive, RecordedShape, Recording, Scratch, Symmetry, SCRATCH,
+};
+use log::{debug, trace};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+
+/// How many ticks each tile gets in a round of a parallel run, after which
+/// the tile grid moves, often enough for shapes to cross its edges.
+const ROUND_TICKS: usize = 256;
+
+/// How many ticks in a row [`Steps`] tries to draw a shape in before giving
+/// up.
+const MAX_TICKS_PER_STEP: usize = 10_000;
+
+/// Keeps improving an approximation of a target image, carrying the state
+/// that spans ticks (such as the annealing temperature).
+pub struct Approximator<R = StdRng> {
+    target: Image,
+    approx: Image,
+    config: Config,
+    /// The color the canvas started out as.
+    background: Color,
+    rng: R,
+    temperature: f32,
+    shapes: Vec<PlacedShape>,
+    /// The tick each of `shapes` was accepted in.
+    accepted_at: Vec<usize>,
+    ticks: usize,
+    /// The last tick that lowered the loss.
+    improved_at: usize,
+    budget: Option<usize>,
+    /// The error map and the tick it was built at.
+    error_map: Option<(ErrorMap, usize)>,
+    /// The centers of the latest shapes, for [`Config::min_spacing`].
+    recent: RecentCenters,
+    /// How many shapes [`Approximator::prune`] has removed.
+    pruned: usize,
+    /// How many ticks drew or refined a shape, and how much they changed
+    /// the loss by in total.
+    accepted: (usize, f64),
+    /// Only sample shapes that fit entirely on the canvas, for tiles of a
+    /// parallel run.
+    contained: bool,
+    /// The round of [`Approximator::run_parallel`] the last call stopped
+    /// partway through, if any.
+    round: Option<Round>,
+}
+
+impl<R: Rng> Approximator<R> {
+    /// Starts approximating `target` from a canvas filled as
+    /// [`Config::background`] says, apart from the pixels the target's
+    /// [mask](Image::set_mask) freezes, which are copied from it.
+    pub fn new(target: Image, config: Config, rng: R) -> Self {
+        let background = config.background(&target);
+        let (width, height) = (target.width, target.height);
+        let mut approx = Image::filled(width, height, target.is_grayscale(), background);
+        approx.copy_frozen(&target);
+
+        Self::resume(target, approx, background, config, rng)
+    }
+
+    /// Continues approximating `target` from `approx`, which started out as
+    /// `background`.
+    fn resume(target: Image, mut approx: Image, background: Color, config: Config, rng: R) -> Self {
+        approx.set_linear(config.linear);
+        approx.reset_loss(&target, config.metric());
+
+        let temperature = config.temperature;
+
+        Self {
+            target,
+            approx,
+            config,
+            background,
+            rng,
+            temperature,
+            shapes: Vec::new(),
+            accepted_at: Vec::new(),
+            ticks: 0,
+            improved_at: 0,
+            budget: None,
+            error_map: None,
+            recent: RecentCenters::default(),
+            pruned: 0,
+            accepted: (0, 0.0),
+            contained: false,
+            round: None,
+        }
+    }
+
+    /// Continues the run `checkpoint` was taken from, approximating `target`
+    /// as `config` says.
+    ///
+    /// With the same target and config as the run started with, it goes on
+    /// exactly like the run would have without stopping; the budget (see
+    /// [`Approximator::set_budget`]) needs setting again. Fails if the
+    /// checkpoint was taken for a different size or kind of image, or
+    /// doesn't add up.
+    pub fn from_checkpoint(
+        target: Image,
+        config: Config,
+        checkpoint: Checkpoint<R>,
+    ) -> Result<Self, String> {
+        if (checkpoint.width, checkpoint.height, checkpoint.grayscale)
+            != (target.width, target.height, target.is_grayscale())
+        {
+            let kind = |grayscale| if grayscale { "grayscale" } else { "color" };
+
+            return Err(format!(
+                "the checkpoint is of a {}x{} {} image, but the target is {}x{} {}",
+                checkpoint.width,
+                checkpoint.height,
+                kind(checkpoint.grayscale),
+                target.width,
+                target.height,
+                kind(target.is_grayscale())
+            ));
+        }
+
+        let (width, height) = (target.width, target.height);
+
+        if checkpoint.round.as_ref().is_some_and(|round| !round.fits(width, height)) {
+            return Err("the checkpoint's parallel round doesn't fit the image".to_string());
+        }
+
+        let mut approx = checkpoint.approx()?;
+        approx.set_linear(config.linear);
+        approx.copy_frozen(&target);
+
+        // Checkpoints don't store which pixels were drawn onto, but the
+        // shapes tell
+
+        for recorded in &checkpoint.shapes {
+            let points = recorded.placed.shape.points((width, height));
+            let changes = recorded.placed.changes(points, &config, width, height);
+            approx.touch(changes.into_iter().map(|(pos, ..)| pos));
+        }
+
+        Ok(Self {
+            target,
+            approx,
+            config,
+            background: checkpoint.background,
+            rng: checkpoint.rng,
+            temperature: checkpoint.temperature,
+            shapes: checkpoint.shapes.iter().map(|recorded| recorded.placed).collect(),
+            accepted_at: checkpoint.shapes.iter().map(|recorded| recorded.tick).collect(),
+            ticks: checkpoint.ticks,
+            improved_at: checkpoint.improved_at,
+            budget: None,
+            error_map: checkpoint.error_map,
+            recent: checkpoint.recent,
+            pruned: checkpoint.pruned,
+            accepted: (0, 0.0),
+            contained: false,
+            round: checkpoint.round,
+        })
+    }
+
+    /// Switches to approximating `target` instead, starting from the
+    /// approximation and shapes so far, such as for the next frame of an
+    /// animation.
+    ///
+    /// The run starts over otherwise: the ticks count from zero again, the
+    /// temperature goes back to [`Config::temperature`] and the shapes so
+    /// far count as drawn before the first tick, so that the budget and the
+    /// early stopping conditions apply to the new target alone. Fails if
+    /// `target` is of a different size or kind of image.
+    pub fn retarget(&mut self, target: Image) -> Result<(), String> {
+        if (target.width, target.height, target.is_grayscale())
+            != (self.target.width, self.target.height, self.target.is_grayscale())
+        {
+            return Err(format!(
+                "the new target is {}x{}, but the approximation is of a {}x{} image",
+                target.width, target.height, self.target.width, self.target.height
+            ));
+        }
+
+        self.approx.copy_frozen(&target);
+        self.approx.reset_loss(&target, self.config.metric());
+        self.target = target;
+        self.temperature = self.config.temperature;
+        self.accepted_at.fill(0);
+        self.ticks = 0;
+        self.improved_at = 0;
+        self.budget = None;
+        self.error_map = None;
+        self.round = None;
+
+        Ok(())
+    }
+
+    /// Carries on approximating `target`, `factor` times as wide and as tall
+    /// as the current one (rounding the current size up), as `config` says,
+    /// for coarse-to-fine runs that lay down the structure of an image on a
+    /// smaller copy first.
+    ///
+    /// The shapes so far are scaled up (see [`Primitive::scale`]) and drawn
+    /// again onto a new canvas, so they stay sharp; the ticks, temperature
+    /// and budget carry on. Fails if the sizes or image kinds don't match.
+    pub fn upscale(&mut self, target: Image, config: Config, factor: u32) -> Result<(), String> {
+        let (width, height) = (target.width, target.height);
+
+        if (width.div_ceil(factor), height.div_ceil(factor), target.is_grayscale())
+            != (self.target.width, self.target.height, self.target.is_grayscale())
+        {
+            return Err(format!(
+                "a {}x{} image can't be scaled up {factor} times into a {width}x{height} one",
+                self.target.width, self.target.height
+            ));
+        }
+
+        let mut approx = Image::filled(width, height, target.is_grayscale(), self.background);
+        approx.set_linear(config.linear);
+
+        for placed in &mut self.shapes {
+            placed.shape = placed.shape.scale(factor);
+
+            let points = placed.shape.points((width, height));
+            approx.paint(placed.changes(points, &config, width, height));
+        }
+
+        approx.copy_frozen(&target);
+        approx.reset_loss(&target, config.metric());
+        self.target = target;
+        self.approx = approx;
+        self.config = config;
+        self.error_map = None;
+        self.recent = RecentCenters::default();
+        self.round = None;
+
+        Ok(())
+    }
+
+    /// Tells the approximator how many ticks the whole run will take, so
+    /// schedules like [`Config::radius_schedule`] can progress.
+    pub fn set_budget(&mut self, ticks: usize) {
+        self.budget = Some(ticks);
+    }
+
+    /// Returns how much of the budget has been spent, from `0.0` to `1.0`;
+    /// always `0.0` without a budget.
+    pub fn progress(&self) -> f32 {
+        match self.budget {
+            Some(budget) if budget > 0 => (self.ticks as f32 / budget as f32).min(1.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Returns how many ticks have run so far.
+    pub fn ticks(&self) -> usize {
+        self.ticks
+    }
+
+    /// Returns how many ticks have run since the loss last went down, or
+    /// since the start if it never has.
+    pub fn ticks_since_improvement(&self) -> usize {
+        self.ticks - self.improved_at
+    }
+
+    /// Samples a single random shape and draws it if it brings the
+    /// approximation closer to the target.
+    ///
+    /// While the temperature is above zero, shapes that make things worse
+    /// are drawn too, with probability `exp(-loss_delta / temperature)`;
+    /// the temperature then cools by [`Config::cooling`].
+    ///
+    /// With [`Config::refine`], once a shape has been drawn every other tick
+    /// (on average) instead perturbs one of the drawn shapes and keeps the
+    /// result only if it lowers the loss. Adding and refining share the
+    /// tick budget, so refining trades new shapes for better placed ones.
+    ///
+    /// With [`Config::error_guided`], every other new shape (on average) is
+    /// centered on a pixel picked in proportion to the loss around it, as of
+    /// the last refresh of the error map.
+    ///
+    /// With [`Config::min_spacing`], new shapes centered too close to one of
+    /// the last 64 drawn are given up on straight away, and the tick is
+    /// spent. Refined shapes don't count as drawn, and aren't held to it.
+    ///
+    /// With [`Config::symmetry`], every new shape is drawn along with its
+    /// copies, which are kept as shapes of their own right after it. Refining
+    /// would break the symmetry, so [`Config::refine`] is ignored.
+    ///
+    /// With [`Config::prune_every`], every that many ticks end with a call to
+    /// [`Approximator::prune`].
+    ///
+    /// Returns whether the approximation or its shapes changed.
+    pub fn tick(&mut self) -> bool {
+        // Rounds of a parallel run only carry on from one call to the next
+        self.round = None;
+
+        let before = self.approx.total_loss();
+        let changed = self.add_or_refine();
+
+        if changed {
+            self.accepted.0 += 1;
+            self.accepted.1 += self.approx.total_loss() - before;
+        }
+
+        self.prune_if_due(self.ticks - 1) | changed
+    }
+
+    /// Does the work of a tick, apart from pruning.
+    fn add_or_refine(&mut self) -> bool {
+        let progress = self.progress();
+        self.ticks += 1;
+
+        let refine = self.config.refine && self.config.symmetry == Symmetry::None;
+
+        if refine && !self.shapes.is_empty() && self.rng.random_bool(0.5) {
+            return self.refine();
+        }
+
+        let center = if self.config.error_guided && self.rng.random_bool(0.5) {
+            self.refresh_error_map();
+
+            let (error_map, _) = self.error_map.as_ref().unwrap();
+            error_map.sample(&mut self.rng)
+        } else {
+            None
+        };
+
+        with_scratch(&SCRATCH, |scratch| {
+            let placement = Placement {
+                progress,
+                center,
+                contained: self.contained,
+                recent: Some(&self.recent),
+            };
+
+            let candidate = sample(
+                &self.target,
+                &self.approx,
+                &self.config,
+                placement,
+                scratch,
+                &mut self.rng,
+            );
+
+            let temperature = self.temperature;
+            self.temperature *= self.config.cooling;
+
+            let Some(candidate) = candidate else {
+                trace!("tick {}: no shape sampled", self.ticks);
+                return false;
+            };
+
+            // Only the sign needs the full precision of the delta
+            let odds = (-candidate.loss_delta as f32 / temperature).exp();
+            let accept = candidate.loss_delta < 0.0
+                || (temperature > 0.0 && self.rng.random::<f32>() < odds);
+
+            trace!(
+                "tick {}: {} {:?}, loss {:+.1}",
+                self.ticks,
+                if accept { "drew" } else { "rejected" },
+                candidate.placed,
+                candidate.loss_delta
+            );
+
+            if !accept {
+                return false;
+            }
+
+            if candidate.loss_delta < 0.0 {
+                self.improved_at = self.ticks;
+            }
+
+            self.approx.apply(&self.target, &scratch.changes, self.config.metric());
+
+            let bounds = (self.target.width, self.target.height);
+            let copies = self.config.symmetry.copies(candidate.placed.shape, bounds);
+
+            for shape in std::iter::once(candidate.placed.shape).chain(copies) {
+                self.recent.push(shape.center());
+                self.shapes.push(PlacedShape { shape, ..candidate.placed });
+                self.accepted_at.push(self.ticks);
+            }
+            true
+        })
+    }
+
+    /// Rebuilds the error map if it's missing or at least
+    /// [`Config::error_refresh`] ticks old.
+    fn refresh_error_map(&mut self) {
+        let stale = match self.error_map {
+            Some((_, built)) => self.ticks - built >= self.config.error_refresh,
+            None => true,
+        };
+
+        if stale {
+            let loss = self.config.metric();
+            let error_map = ErrorMap::new(&self.target, &self.approx, loss);
+            self.error_map = Some((error_map, self.ticks));
+            trace!("tick {}: rebuilt the error map", self.ticks);
+        }
+    }
+
+    /// Perturbs a random drawn shape, keeping the change if it lowers the
+    /// loss.
+    ///
+    /// Shapes drawn later may overlap the perturbed one, so the area both
+    /// versions cover is re-rendered from scratch (see
+    /// [`Approximator::redraw`]).
+    fn refine(&mut self) -> bool {
+        let (width, height) = (self.target.width, self.target.height);
+        let index = self.rng.random_range(0..self.shapes.len());
+        let old = self.shapes[index];
+        let new = old.perturb(&self.config, width, height, &mut self.rng);
+
+        let [old_x0, old_y0, old_x1, old_y1] = old.shape.bounding_box();
+        let [new_x0, new_y0, new_x1, new_y1] = new.shape.bounding_box();
+        let region = [
+            old_x0.min(new_x0),
+            old_y0.min(new_y0),
+            old_x1.max(new_x1),
+            old_y1.max(new_y1),
+        ];
+
+        let Some(changes) = self.redraw(region, index, Some(&new)) else {
+            return false;
+        };
+
+        let loss_delta =
+            Image::loss_delta(&self.target, &self.approx, &changes, self.config.metric());
+
+        trace!(
+            "tick {}: {} shape {index} into {:?}, loss {loss_delta:+.1}",
+            self.ticks,
+            if loss_delta < 0.0 { "refined" } else { "failed to refine" },
+            new
+        );
+
+        if loss_delta >= 0.0 {
+            return false;
+        }
+
+        self.approx.apply(&self.target, &changes, self.config.metric());
+        self.shapes[index] = new;
+        self.improved_at = self.ticks;
+        true
+    }
+
+    /// Removes every drawn shape whose removal doesn't raise the loss, such
+    /// as shapes later ones have covered up entirely, and returns how many
+    /// were removed.
+    ///
+    /// Shapes are tried in drawing order, re-rendering the area each one
+    /// covers from every other shape that touches it (see
+    /// [`Approximator::redraw`]), so a pass costs about as much as that many
+    /// refining ticks. With a [`Config::symmetry`], only shapes whose removal
+    /// leaves every pixel as it was go, so that the result stays symmetric.
+    pub fn prune(&mut self) -> usize {
+        let mut pruned = 0;
+        let mut index = 0;
+
+        while index < self.shapes.len() {
+            let region = self.shapes[index].shape.bounding_box();
+
+            let prune = match self.redraw(region, index, None) {
+                // Entirely off the canvas
+                None => true,
+                Some(changes) if changes.is_empty() => true,
+                Some(_) if self.config.symmetry != Symmetry::None => false,
+                Some(changes) => {
+                    let loss = self.config.metric();
+
+                    if Image::loss_delta(&self.target, &self.approx, &changes, loss) <= 0.0 {
+                        self.approx.apply(&self.target, &changes, loss);
+                        true
+                    } else {
+                        false
+                    }
+                }
+            };
+
+            if prune {
+                self.shapes.remove(index);
+                self.accepted_at.remove(index);
+                pruned += 1;
+            } else {
+                index += 1;
+            }
+        }
+
+        self.pruned += pruned;
+        debug!(
+            "tick {}: pruned {pruned} shapes, leaving {}",
+            self.ticks,
+            self.shapes.len()
+        );
+        pruned
+    }
+
+    /// Prunes if the ticks since `before` crossed a multiple of
+    /// [`Config::prune_every`], returning whether any shapes were removed.
+    fn prune_if_due(&mut self, before: usize) -> bool {
+        let every = self.config.prune_every;
+
+        every > 0 && self.ticks / every > before / every && self.prune() > 0
+    }
+
+    /// Returns how many shapes pruning has removed so far (see
+    /// [`Config::prune_every`]).
+    pub fn pruned(&self) -> usize {
+        self.pruned
+    }
+
+    /// Returns how many ticks so far drew or refined a shape (those
+    /// [`Approximator::tick`] returned `true` for, leaving pruning aside),
+    /// and how much they changed the loss by in total, for keeping an eye
+    /// on how often shapes still get accepted and how much they help.
+    ///
+    /// Counts start from zero when resuming from a checkpoint.
+    pub fn accepted(&self) -> (usize, f64) {
+        self.accepted
+    }
+
+    /// Re-renders the part of `region` (an inclusive `[x0, y0, x1, y1]` box)
+    /// on the canvas from the background up, with the shape at `index`
+    /// swapped for `replacement`, or left out without one.
+    ///
+    /// Returns the changes that would bring the canvas in line with the
+    /// re-rendered region, or `None` if the region is entirely off the canvas.
+    fn redraw(
+        &self,
+        region: [isize; 4],
+        index: usize,
+        replacement: Option<&PlacedShape>,
+    ) -> Option<Vec<(Point, Color, f32)>> {
+        let (width, height) = (self.target.width, self.target.height);
+        let [x0, y0, x1, y1] = region;
+        let [x0, y0] = [x0.max(0), y0.max(0)];
+        let [x1, y1] = [x1.min(width as isize - 1), y1.min(height as isize - 1)];
+
+        if x0 > x1 || y0 > y1 {
+            return None;
+        }
+
+        let region_width = (x1 - x0 + 1) as usize;
+        let mut region_pixels = vec![self.background; region_width * (y1 - y0 + 1) as usize];
+
+        for (i, placed) in self.shapes.iter().enumerate() {
+            let placed = if i == index {
+                match replacement {
+                    Some(replacement) => replacement,
+                    None => continue,
+                }
+            } else {
+                placed
+            };
+            let [bx0, by0, bx1, by1] = placed.shape.bounding_box();
+
+            if bx1 < x0 || by1 < y0 || bx0 > x1 || by0 > y1 {
+                continue;
+            }
+
+            with_scratch(&SCRATCH, |Scratch { points, changes, .. }| {
+                placed.shape.points_into((width, height), points);
+                placed.changes_into(points, &self.config, width, height, changes);
+
+                for &([x, y], color, alpha) in changes.iter() {
+                    let [x, y] = [x as isize, y as isize];
+
+                    if x < x0 || y < y0 || x > x1 || y > y1 {
+                        continue;
+                    }
+
+                    let pixel =
+                        &mut region_pixels[(y - y0) as usize * region_width + (x - x0) as usize];
+                    *pixel = self.approx.composite(*pixel, color, alpha);
+                }
+            });
+        }
+
+        let changes = region_pixels
+            .into_iter()
+            .enumerate()
+            .map(|(i, color)| {
+                let pos = [
+                    (x0 + (i % region_width) as isize) as u32,
+                    (y0 + (i / region_width) as isize) as u32,
+                ];
+
+                (pos, color, 1.0)
+            })
+            .filter(|&(pos, color, _)| {
+                self.target.is_editable(pos) && self.approx.pixel(pos) != color
+            })
+            .collect();
+
+        Some(changes)
+    }
+
+    /// Runs `iterations` ticks, returning whether any of them drew a shape.
+    pub fn run(&mut self, iterations: usize) -> bool {
+        let mut got_improvement = false;
+
+        for _ in 0..iterations {
+            got_improvement |= self.tick();
+        }
+
+        got_improvement
+    }
+
+    /// Runs up to `iterations` ticks like [`Approximator::run`], calling
+    /// `callback` after every `every` of them (and after the last), for
+    /// code that can't own the loop, such as a UI reporting progress.
+    ///
+    /// The callback runs synchronously on the calling thread, in between
+    /// batches of ticks, and sees the approximator as it stands: its
+    /// [`ticks`](Approximator::ticks), the canvas from
+    /// [`approx`](Approximator::approx) and its
+    /// [`total_loss`](Image::total_loss). Returning
+    /// [`ControlFlow::Break`] stops the run there. Returns whether any tick
+    /// drew a shape.
+    pub fn run_with<F>(&mut self, iterations: usize, every: usize, mut callback: F) -> bool
+    where
+        F: FnMut(&Self) -> ControlFlow<()>,
+    {
+        let mut got_improvement = false;
+        let mut remaining = iterations;
+
+        while remaining > 0 {
+            let batch = remaining.min(every.max(1));
+            got_improvement |= self.run(batch);
+            remaining -= batch;
+
+            if callback(self).is_break() {
+                break;
+            }
+        }
+
+        got_improvement
+    }
+
+    /// Runs `iterations` ticks spread over `threads` threads, returning
+    /// whether any of them drew a shape.
+    ///
+    /// The canvas is split into a grid of about `threads` tiles, which are
+    /// approximated on rayon's global thread pool in rounds of 256 ticks per
+    /// tile, only placing shapes that fit entirely inside a tile so they
+    /// never touch the same pixels. After every round the grid moves by a
+    /// random offset, so later rounds can place shapes across the old tile
+    /// edges.
+    ///
+    /// Tiles get ticks in proportion to their area and sample radii up to a
+    /// quarter of their smaller side. [`Config::refine`] is ignored, and the
+    /// annealing temperature cools once per tick of a tile. Each tile gets
+    /// its own generator seeded from this one, so runs are reproducible.
+    /// With a single thread, or with a [`Config::symmetry`] (whose copies
+    /// would land in other tiles), this is just [`Approximator::run`].
+    ///
+    /// The tiles are merged back into the canvas at the end of every call,
+    /// but a round the call stops partway through carries on where it left
+    /// off in the next one (and in checkpoints), so splitting a run into
+    /// more calls doesn't change it. Any other kind of tick ends the round
+    /// there.
+    pub fn run_parallel(&mut self, iterations: usize, threads: usize) -> bool {
+        if threads <= 1 || self.config.symmetry != Symmetry::None {
+            return self.run(iterations);
+        }
+
+        let mut got_improvement = false;
+        let mut remaining = iterations;
+
+        while remaining > 0 {
+            let mut round = match self.round.take() {
+                Some(round) => round,
+                None => self.start_round(threads),
+            };
+            let ticks = remaining.min(round.ticks - round.done);
+
+            got_improvement |= self.run_round(&mut round, ticks);
+            remaining -= ticks;
+
+            if round.done < round.ticks {
+                self.round = Some(round);
+            }
+        }
+
+        got_improvement
+    }
+
+    /// Splits the canvas into a grid of about `threads` tiles, shifted by a
+    /// random offset, as `[x, y, width, height]`.
+    fn tiles(&mut self, threads: usize) -> Vec<[u32; 4]> {
+        let (width, height) = (self.target.width, self.target.height);
+
+        // The squarest grid, with more tiles along the longer side
+        let short = (1..=threads)
+            .filter(|&n| threads.is_multiple_of(n) && n * n <= threads)
+            .max()
+            .unwrap_or(1) as u32;
+        let long = threads as u32 / short;
+        let (columns, rows) = if width >= height { (long, short) } else { (short, long) };
+
+        let tile = [width.div_ceil(columns), height.div_ceil(rows)];
+        let offset = [self.rng.random_range(0..tile[0]), self.rng.random_range(0..tile[1])];
+
+        let spans = |size: u32, tile: u32, offset: u32| {
+            let mut starts = vec![0];
+            starts.extend((offset..size).step_by(tile as usize).filter(|&start| start > 0));
+
+            (0..starts.len())
+                .map(|i| (starts[i], starts.get(i + 1).copied().unwrap_or(size) - starts[i]))
+                .collect::<Vec<_>>()
+        };
+
+        let columns = spans(width, tile[0], offset[0]);
+
+        spans(height, tile[1], offset[1])
+            .into_iter()
+            .flat_map(|(y, height)| columns.iter().map(move |&(x, width)| [x, y, width, height]))
+            .collect()
+    }
+
+    /// Lays out the tiles of a new round over about `threads` threads.
+    fn start_round(&mut self, threads: usize) -> Round {
+        let (width, height) = (self.target.width, self.target.height);
+        let radii = self
+            .config
+            .scheduled_radius_range(width, height, self.progress());
+
+        let rects = self.tiles(threads);
+        let ticks = ROUND_TICKS * rects.len();
+        let area = (width as usize * height as usize) as f64;
+        let mut covered = 0;
+
+        let tiles = rects
+            .into_iter()
+            .map(|[x, y, tile_width, tile_height]| {
+                // Hand out ticks by area, rounding so they add up to `ticks`
+                let before = (ticks as f64 * covered as f64 / area).round() as usize;
+                covered += tile_width as usize * tile_height as usize;
+                let tile_ticks = (ticks as f64 * covered as f64 / area).round() as usize - before;
+
+                let max_radius = (*radii.end()).min(tile_width.min(tile_height) / 4);
+
+                Tile {
+                    rect: [x, y, tile_width, tile_height],
+                    ticks: tile_ticks,
+                    max_radius: max_radius.max(*radii.start()),
+                    rng: ChaCha12Rng::seed_from_u64(self.rng.random()),
+                    temperature: self.temperature,
+                    ran: 0,
+                    improved_at: 0,
+                    error_map: None,
+                    recent: self.recent.translate([-(x as isize), -(y as isize)]),
+                }
+            })
+            .collect();
+
+        Round {
+            start: self.ticks,
+            ticks,
+            done: 0,
+            tiles,
+        }
+    }
+
+    /// Runs the next `ticks` ticks of `round` over its tiles in parallel,
+    /// then merges the tiles back into the canvas.
+    fn run_round(&mut self, round: &mut Round, ticks: usize) -> bool {
+        let done = round.done + ticks;
+
+        let mut jobs: Vec<_> = round
+            .tiles
+            .iter_mut()
+            .map(|state| {
+                let [x, y, tile_width, tile_height] = state.rect;
+                let config = Config {
+                    max_radius: Some(state.max_radius),
+                    radius_schedule: Default::default(),
+                    refine: false,
+                    prune_every: 0,
+                    ..self.config.clone()
+                };
+
+                let mut tile = Approximator::resume(
+                    self.target.crop([x, y], tile_width, tile_height),
+                    self.approx.crop([x, y], tile_width, tile_height),
+                    self.background,
+                    config,
+                    state.rng.clone(),
+                );
+                tile.temperature = state.temperature;
+                tile.ticks = state.ran;
+                tile.improved_at = state.improved_at;
+                tile.error_map = state.error_map.take();
+                tile.recent = std::mem::take(&mut state.recent);
+                tile.contained = true;
+
+                // The tile's share of the round so far, less what earlier
+                // calls already ran
+                let due = state.ticks * done / round.ticks - state.ran;
+
+                (tile, due)
+            })
+            .collect();
+
+        jobs.par_iter_mut().for_each(|(tile, due)| {
+            tile.run(*due);
+        });
+
+        // Shapes in different tiles don't overlap, so they can be drawn in
+        // any order; spread the ticks they were accepted in over the round
+        let mut shapes = Vec::new();
+        let mut got_improvement = false;
+
+        for (state, (tile, _)) in round.tiles.iter_mut().zip(jobs) {
+            let [x, y, ..] = state.rect;
+            let at = |tick: usize| round.start + (tick * round.ticks).div_ceil(state.ticks);
+
+            self.approx.paste(&tile.approx, [x, y]);
+            self.temperature = self.temperature.min(tile.temperature);
+            self.accepted.0 += tile.accepted.0;
+            self.accepted.1 += tile.accepted.1;
+
+            if tile.improved_at > 0 {
+                self.improved_at = self.improved_at.max(at(tile.improved_at));
+            }
+
+            for (placed, &tick) in tile.shapes.iter().zip(&tile.accepted_at) {
+                let placed = PlacedShape {
+                    shape: placed.shape.translate([x as isize, y as isize]),
+                    ..*placed
+                };
+
+                shapes.push((at(tick), placed));
+                got_improvement = true;
+            }
+
+            state.rng = tile.rng;
+            state.temperature = tile.temperature;
+            state.ran = tile.ticks;
+            state.improved_at = tile.improved_at;
+            state.error_map = tile.error_map;
+            state.recent = tile.recent;
+        }
+
+        // The sort is stable, so shapes accepted at the same tick stay in tile
+        // order and the recording doesn't depend on how threads were scheduled
+        shapes.sort_by_key(|&(tick, _)| tick);
+
+        for (tick, placed) in shapes {
+            self.recent.push(placed.shape.center());
+            self.shapes.push(placed);
+            self.accepted_at.push(tick);
+        }
+
+        round.done = done;
+        self.ticks += ticks;
+
+        // Losses over neighbourhoods (like SSIM) don't add up across tiles
+        self.approx.reset_loss(&self.target, self.config.metric());
+
+        let pruned = round.done == round.ticks && self.prune_if_due(round.start);
+
+        pruned | got_improvement
+    }
+
+    /// Returns an iterator that ticks until a shape is drawn, then yields it,
+    /// for driving the run one shape at a time (say, to render every step).
+    ///
+    /// The iterator borrows the approximator, which
+    /// [`Steps::approximator`] gives access to in between shapes. It ends
+    /// once 10000 ticks in a row have drawn nothing; calling this again
+    /// carries on from there.
+    pub fn steps(&mut self) -> Steps<'_, R> {
+        Steps {
+            approximator: self,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn target(&self) -> &Image {
+        &self.target
+    }
+
+    pub fn approx(&self) -> &Image {
+        &self.approx
+    }
+
+    /// Writes the pixels of the approximation that changed since the last
+    /// call into `buf`, as [`Image::encode_changes`] does.
+    pub fn encode_changes(&mut self, buf: &mut [u32]) {
+        self.approx.encode_changes(buf);
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns every shape drawn so far, in drawing order.
+    pub fn shapes(&self) -> &[PlacedShape] {
+        &self.shapes
+    }
+
+    /// Returns the shapes drawn so far along with what's needed to redraw
+    /// them, for saving.
+    pub fn recording(&self) -> Recording {
+        let shapes = self
+            .shapes
+            .iter()
+            .zip(&self.accepted_at)
+            .map(|(&placed, &tick)| RecordedShape { placed, tick })
+            .collect();
+
+        Recording {
+            width: self.target.width,
+            height: self.target.height,
+            grayscale: self.target.is_grayscale(),
+            opacity: self.config.opacity,
+            antialias: self.config.antialias,
+            linear: self.config.linear,
+            background: self.background,
+            shapes,
+            frozen: self.target.frozen(),
+        }
+    }
+
+    /// Returns everything needed to continue the run later with
+    /// [`Approximator::from_checkpoint`].
+    pub fn checkpoint(&self) -> Checkpoint<R>
+    where
+        R: Clone,
+    {
+        Checkpoint {
+            width: self.approx.width,
+            height: self.approx.height,
+            grayscale: self.approx.is_grayscale(),
+            pixels: self.approx.rows(),
+            total_loss: self.approx.total_loss(),
+            background: self.background,
+            rng: self.rng.clone(),
+            temperature: self.temperature,
+            shapes: self.recording().shapes,
+            ticks: self.ticks,
+            improved_at: self.improved_at,
+            error_map: self.error_map.clone(),
+            recent: self.recent.clone(),
+            pruned: self.pruned,
+            round: self.round.clone(),
+        }
+    }
+
+    /// Returns the current annealing temperature.
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    pub fn into_approx(self) -> Image {
+        self.approx
+    }
+}
+
+/// A round of [`Approximator::run_parallel`], which can carry over from one
+/// call to the next.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Round {
+    /// The tick the round started at.
+    start: usize,
+    /// How many ticks the round lasts, and how many of them have run.
+    ticks: usize,
+    done: usize,
+    tiles: Vec<Tile>,
+}
+
+impl Round {
+    /// Returns whether the round is partway through and its tiles lie on a
+    /// `width`x`height` canvas.
+    fn fits(&self, width: u32, height: u32) -> bool {
+        self.done < self.ticks
+            && self.tiles.iter().all(|tile| {
+                let [x, y, tile_width, tile_height] = tile.rect;
+                x.checked_add(tile_width).is_some_and(|right| right <= width)
+                    && y.checked_add(tile_height).is_some_and(|bottom| bottom <= height)
+            })
+    }
+}
+
+/// What a tile of a [`Round`] carries from one call to the next; its pixels
+/// are on the canvas.
+#[derive(Clone, Serialize, Deserialize)]
+struct Tile {
+    /// Where the tile is, as `[x, y, width, height]`.
+    rect: [u32; 4],
+    /// How many of the round's ticks go to the tile.
+    ticks: usize,
+    max_radius: u32,
+    rng: ChaCha12Rng,
+    temperature: f32,
+    /// How many ticks the tile has run so far.
+    ran: usize,
+    improved_at: usize,
+    error_map: Option<(ErrorMap, usize)>,
+    recent: RecentCenters,
+}
+
+/// The shapes an [`Approximator`] draws, one at a time, along with the tick
+/// each was drawn in; see [`Approximator::steps`].
+///
+/// With a [`Config::symmetry`], a shape and its copies are drawn in the same
+/// tick, so the approximation already shows the copies when the shape is
+/// yielded, and they're yielded next without further ticks.
+pub struct Steps<'a, R> {
+    approximator: &'a mut Approximator<R>,
+    /// Shapes drawn in the last tick that are yet to be yielded.
+    pending: VecDeque<RecordedShape>,
+}
+
+impl<R> Steps<'_, R> {
+    /// Returns the approximator, showing every shape yielded so far.
+    pub fn approximator(&self) -> &Approximator<R> {
+        self.approximator
+    }
+}
+
+impl<R: Rng> Iterator for Steps<'_, R> {
+    type Item = RecordedShape;
+
+    fn next(&mut self) -> Option<RecordedShape> {
+        if let Some(shape) = self.pending.pop_front() {
+            return Some(shape);
+        }
+
+        let approximator = &mut *self.approximator;
+
+        for _ in 0..MAX_TICKS_PER_STEP {
+            approximator.tick();
+
+            // Shapes drawn this tick are at the end; older ones may have
+            // been refined or pruned, but never get this tick
+            let tick = approximator.ticks;
+            let drawn = approximator.accepted_at.iter().rev().take_while(|&&at| at == tick).count();
+            let start = approximator.shapes.len() - drawn;
+
+            self.pending.extend(
+                approximator.shapes[start..]
+                    .iter()
+                    .map(|&placed| RecordedShape { placed, tick }),
+            );
+
+            if let Some(shape) = self.pending.pop_front() {
+                return Some(shape);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Init;
+    use image::{GrayImage, RgbImage};
+
+    #[test]
+    fn annealing_gets_closer_than_greedy() {
+        // Sharp squares, which greedy runs get stuck on
+        let target = Image::from(RgbImage::from_fn(16, 12, |x, y| {
+            if (x / 4 + y / 4) % 2 == 0 { [250, 240, 230] } else { [20, 30, 60] }.into()
+        }));
+        let loss_after = |temperature| {
+            let config = Config {
+                fill: true,
+                temperature,
+                cooling: 0.9993,
+                ..Config::default()
+            };
+
+            let mut approximator =
+                Approximator::new(target.clone(), config, StdRng::seed_from_u64(1));
+            approximator.run(4000);
+            approximator.approx().total_loss()
+        };
+
+        let (greedy, annealed) = (loss_after(0.0), loss_after(1e5));
+        assert!(annealed < greedy, "{annealed} >= {greedy}");
+    }
+
+    /// Returns the loss after every 50 of the first 300 ticks of
+    /// approximating a light gradient from a canvas filled as `init` says.
+    fn loss_curve(init: Init) -> Vec<f64> {
+        let target = Image::from(RgbImage::from_fn(64, 48, |x, y| {
+            [200 + x as u8 / 2, 170 + y as u8, 140].into()
+        }));
+        let config = Config {
+            init,
+            ..Config::default()
+        };
+
+        let mut approximator = Approximator::new(target, config, StdRng::seed_from_u64(3));
+        let mut curve = vec![approximator.approx().total_loss()];
+
+        for _ in 0..6 {
+            approximator.run(50);
+            curve.push(approximator.approx().total_loss());
+        }
+
+        curve
+    }
+
+    #[test]
+    fn average_init_starts_and_stays_ahead() {
+        let (average, black) = (loss_curve(Init::Average), loss_curve(Init::Black));
+
+        for (tick, (average, black)) in average.iter().zip(&black).enumerate() {
+            assert!(average < black, "after {} ticks: {average} >= {black}", tick * 50);
+        }
+
+        // Even before any shapes, it's better than black after all of them
+        assert!(average[0] < black[black.len() - 1]);
+    }
+
+    /// Returns a gradient with stripes across it.
+    fn stripes() -> Image {
+        Image::from(RgbImage::from_fn(90, 60, |x, y| {
+            let stripe = if (x + y) / 15 % 2 == 0 { 60 } else { 0 };
+            [x as u8 * 2 + stripe, y as u8 * 3, 200 - stripe].into()
+        }))
+    }
+
+    /// Returns an approximator of [`stripes`], seeded with `seed`.
+    fn striped(seed: u64) -> Approximator<ChaCha12Rng> {
+        Approximator::new(stripes(), Config::default(), ChaCha12Rng::seed_from_u64(seed))
+    }
+
+    #[test]
+    fn parallel_runs_dont_depend_on_how_they_are_split() {
+        let mut whole = striped(7);
+        whole.run_parallel(3000, 4);
+
+        for split in [&[1, 2999][..], &[700, 2300], &[1024, 1976], &[64; 40]] {
+            let mut parts = striped(7);
+
+            for &ticks in split {
+                parts.run_parallel(ticks, 4);
+            }
+            parts.run_parallel(3000 - parts.ticks(), 4);
+
+            assert_eq!(parts.approx().rows(), whole.approx().rows(), "split {split:?}");
+            assert_eq!(parts.recording(), whole.recording(), "split {split:?}");
+        }
+    }
+
+    #[test]
+    fn resumed_runs_match_uninterrupted_ones() {
+        for threads in [1, 4] {
+            let mut whole = striped(8);
+            whole.run_parallel(2000, threads);
+
+            let mut before = striped(8);
+            before.run_parallel(900, threads);
+
+            let json = serde_json::to_string(&before.checkpoint()).unwrap();
+            let checkpoint: Checkpoint<ChaCha12Rng> = serde_json::from_str(&json).unwrap();
+            let target = before.target().clone();
+            let mut resumed = Approximator::from_checkpoint(target, Config::default(), checkpoint)
+                .unwrap();
+            resumed.run_parallel(1100, threads);
+
+            assert_eq!(resumed.approx().rows(), whole.approx().rows(), "{threads} threads");
+            assert_eq!(resumed.shapes().len(), whole.shapes().len(), "{threads} threads");
+        }
+    }
+
+    #[test]
+    fn checkpoints_missing_pixels_are_rejected() {
+        let approximator = striped(9);
+        let mut checkpoint = approximator.checkpoint();
+        checkpoint.pixels.pop();
+
+        let target = approximator.target().clone();
+        assert!(Approximator::from_checkpoint(target, Config::default(), checkpoint).is_err());
+    }
+
+    #[test]
+    fn recordings_render_like_the_approximation() {
+        let mask = GrayImage::from_fn(90, 60, |x, y| {
+            [if (x / 30 + y / 20) % 2 == 0 { 255 } else { 0 }].into()
+        });
+        let blended = Config {
+            opacity: 0.5,
+            antialias: true,
+            linear: true,
+            ..Config::default()
+        };
+
+        for masked in [false, true] {
+            for config in [Config::default(), blended.clone()] {
+                let mut target = stripes();
+                if masked {
+                    target.set_mask(&mask).unwrap();
+                }
+
+                let mut approximator = Approximator::new(target, config, StdRng::seed_from_u64(4));
+                approximator.run(1500);
+
+                // As `--shapes-json` writes it and `--replay` reads it back
+                let json = serde_json::to_string(&approximator.recording()).unwrap();
+                let recording: Recording = serde_json::from_str(&json).unwrap();
+
+                assert_eq!(recording.render().rows(), approximator.approx().rows());
+            }
+        }
+    }
+}
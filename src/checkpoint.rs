@@ -0,0 +1,65 @@
+use crate::approximator::Round;
+use crate::error_map::ErrorMap;
+use crate::spacing::RecentCenters;
+use crate::{Color, Image, RecordedShape};
+use serde::{Deserialize, Serialize};
+
+/// The state of an [`Approximator`](crate::Approximator) partway through a
+/// run, for picking it up again later with
+/// [`Approximator::from_checkpoint`](crate::Approximator::from_checkpoint).
+///
+/// It holds the canvas and its running loss, the shapes drawn so far, the
+/// random number generator `R` and everything else that carries over from
+/// tick to tick, but not the target or the [`Config`](crate::Config), which
+/// are handed over again when resuming. This is what `--checkpoint` writes.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint<R> {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) grayscale: bool,
+    /// The canvas, laid out like the pixels of an [`Image`].
+    pub(crate) pixels: Vec<u8>,
+    pub(crate) total_loss: f64,
+    pub(crate) background: Color,
+    pub(crate) rng: R,
+    pub(crate) temperature: f32,
+    pub(crate) shapes: Vec<RecordedShape>,
+    pub(crate) ticks: usize,
+    pub(crate) improved_at: usize,
+    pub(crate) error_map: Option<(ErrorMap, usize)>,
+    pub(crate) recent: RecentCenters,
+    pub(crate) pruned: usize,
+    /// The round of a parallel run the checkpoint was taken partway through.
+    #[serde(default)]
+    pub(crate) round: Option<Round>,
+}
+
+impl<R> Checkpoint<R> {
+    /// Returns how many ticks the run had gone when the checkpoint was taken.
+    pub fn ticks(&self) -> usize {
+        self.ticks
+    }
+
+    /// Returns the canvas as it was when the checkpoint was taken, tracking
+    /// the loss it had then.
+    ///
+    /// Fails if there aren't as many pixels as the size says.
+    pub(crate) fn approx(&self) -> Result<Image, String> {
+        let channels = if self.grayscale { 1 } else { 3 };
+        let len = self.width as usize * self.height as usize * channels;
+
+        if self.pixels.len() != len {
+            return Err(format!(
+                "the checkpoint is of a {}x{} image, but has {} bytes of pixels instead of {len}",
+                self.width,
+                self.height,
+                self.pixels.len()
+            ));
+        }
+
+        Ok(Image {
+            total_loss: self.total_loss,
+            ..Image::from_rows(self.width, self.height, channels, self.pixels.clone())
+        })
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{Color, Image};
+
+/// Maps every pixel of `image` to the closest of the `palette` colors (as
+/// `image` would store them) by squared distance, spreading each pixel's
+/// difference from its palette color onto the pixels right and below it
+/// with Floyd–Steinberg error diffusion.
+///
+/// Regions between palette colors come out as a mix of the nearest ones
+/// instead of a flat band of one of them, which is what limited palettes
+/// need once translucent or antialiased shapes have blended their colors.
+/// Pixels are visited row by row, left to right, so the result is always
+/// the same for the same image. Returns a copy of `image` for an empty
+/// palette.
+pub fn dither(image: &Image, palette: &[Color]) -> Image {
+    let mut dithered = image.clone();
+
+    if palette.is_empty() {
+        return dithered;
+    }
+
+    let palette: Vec<Color> = palette.iter().map(|&color| image.quantize(color)).collect();
+    let width = image.width as usize;
+
+    // The error carried into this row and the next, with a pixel of
+    // padding on either side so the edges need no special cases
+    let mut current = vec![[0f32; 3]; width + 2];
+    let mut next = vec![[0f32; 3]; width + 2];
+
+    for y in 0..image.height {
+        for x in 0..width {
+            let color = image.color_at([x as u32, y]);
+            let wanted: [f32; 3] = std::array::from_fn(|i| color[i] as f32 + current[x + 1][i]);
+
+            let closest = *palette
+                .iter()
+                .min_by(|&&a, &&b| distance(wanted, a).total_cmp(&distance(wanted, b)))
+                .unwrap();
+
+            dithered.recolor([x as u32, y], closest);
+
+            for i in 0..3 {
+                let error = wanted[i] - closest[i] as f32;
+
+                current[x + 2][i] += error * 7.0 / 16.0;
+                next[x][i] += error * 3.0 / 16.0;
+                next[x + 1][i] += error * 5.0 / 16.0;
+                next[x + 2][i] += error / 16.0;
+            }
+        }
+
+        std::mem::swap(&mut current, &mut next);
+        next.fill([0.0; 3]);
+    }
+
+    dithered
+}
+
+/// Returns the squared distance between `wanted`, which may lie outside of
+/// the RGB range after diffusion, and `color`.
+fn distance(wanted: [f32; 3], color: Color) -> f32 {
+    (0..3).map(|i| (wanted[i] - color[i] as f32).powi(2)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn gradients_keep_their_local_mean() {
+        let gradient = Image::from(RgbImage::from_fn(64, 32, |x, _| [x as u8 * 4; 3].into()));
+        let palette = [[0, 0, 0], [255, 255, 255]];
+        let dithered = dither(&gradient, &palette);
+
+        // Mean gray level of the 8x8 block at `[bx, by]`
+        let block_mean = |image: &Image, [bx, by]: [u32; 2]| {
+            let points = (0..8).flat_map(|y| (0..8).map(move |x| [bx * 8 + x, by * 8 + y]));
+            points.map(|point| image.color_at(point)[0] as f32).sum::<f32>() / 64.0
+        };
+
+        for y in 0..32 {
+            for x in 0..64 {
+                let color = dithered.color_at([x, y]);
+                assert!(palette.contains(&color), "{color:?} at {x},{y}");
+            }
+        }
+
+        for by in 0..4 {
+            for bx in 0..8 {
+                let (before, after) =
+                    (block_mean(&gradient, [bx, by]), block_mean(&dithered, [bx, by]));
+                assert!((before - after).abs() < 16.0, "block {bx},{by}: {before} -> {after}");
+            }
+        }
+    }
+}
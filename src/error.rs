@@ -0,0 +1,130 @@
+use image::ImageError;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Everything that can go wrong loading, approximating and saving images,
+/// with messages fit to show to users.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CirclezError {
+    /// An image couldn't be opened or decoded.
+    #[error("Failed to load {}: {source}", path.display())]
+    LoadImage { path: PathBuf, source: ImageError },
+
+    /// The image piped in on standard input couldn't be read or decoded.
+    #[error("Failed to load the target from standard input: {source}")]
+    LoadStdin { source: ImageError },
+
+    /// An image couldn't be encoded or written.
+    #[error("Failed to save {}: {source}", path.display())]
+    SaveImage { path: PathBuf, source: ImageError },
+
+    /// The target is an OpenEXR image, but circlez was built without the
+    /// `exr` feature; holds what was being loaded.
+    #[error("Failed to load {0}: OpenEXR images need circlez built with the `exr` feature")]
+    NoExr(String),
+
+    /// The result couldn't be encoded or written to standard output.
+    #[error("Failed to write the result to standard output: {source}")]
+    SaveStdout { source: ImageError },
+
+    /// A text file couldn't be read.
+    #[error("Failed to read {}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+
+    /// A text file couldn't be written.
+    #[error("Failed to save {}: {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+
+    /// A shape list isn't a valid [`Recording`](crate::Recording).
+    #[error("Failed to load shape list {}: {source}", path.display())]
+    ParseRecording {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    /// A `--config` file isn't valid TOML.
+    #[error("Failed to load settings {}: {source}", path.display())]
+    ParseConfig {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    /// A `--config` file has settings that don't exist or have the wrong
+    /// type.
+    #[error("Invalid settings {}: {reason}", path.display())]
+    InvalidConfig { path: PathBuf, reason: String },
+
+    /// A file isn't a valid [`Checkpoint`](crate::Checkpoint).
+    #[error("Failed to load checkpoint {}: {source}", path.display())]
+    ParseCheckpoint {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    /// A checkpoint doesn't fit the target it's resumed with, see
+    /// [`Approximator::from_checkpoint`](crate::Approximator::from_checkpoint).
+    #[error("Can't resume from {}: {reason}", path.display())]
+    InvalidCheckpoint { path: PathBuf, reason: String },
+
+    /// A palette file doesn't list valid colors.
+    #[error("Invalid palette {}: {reason}", path.display())]
+    InvalidPalette { path: PathBuf, reason: String },
+
+    /// The radius range doesn't work for the target, see
+    /// [`Config::validate`](crate::Config::validate).
+    #[error("Invalid radius: {0}")]
+    InvalidRadius(String),
+
+    /// `--symmetry` turns rectangles, which stay axis-aligned, by something
+    /// other than quarter turns.
+    #[error("Invalid symmetry: {0}")]
+    InvalidSymmetry(String),
+
+    /// The importance map doesn't fit the target, see
+    /// [`Image::set_importance_map`](crate::Image::set_importance_map).
+    #[error("Invalid importance map: {0}")]
+    InvalidImportanceMap(String),
+
+    /// The mask doesn't fit the target, see
+    /// [`Image::set_mask`](crate::Image::set_mask).
+    #[error("Invalid mask: {0}")]
+    InvalidMask(String),
+
+    /// `--transparent-bg` was given with an output format that has no
+    /// transparency.
+    #[error("Can't save {0} with a transparent background; save it as PNG or WebP")]
+    NoTransparency(String),
+
+    /// Several targets were given along with options that only make sense
+    /// for one, or none of them are images.
+    #[error("Can't approximate several images: {0}")]
+    Batch(String),
+
+    /// `--animate` was given something other than a single GIF, or options
+    /// that only make sense for still images.
+    #[error("Can't approximate an animation: {0}")]
+    Animation(String),
+
+    /// Some of several targets couldn't be approximated; each failure was
+    /// reported as it happened.
+    #[error("{failed} of {total} images failed")]
+    BatchFailed { failed: usize, total: usize },
+
+    /// The worker threads couldn't be started.
+    #[error("Failed to start {threads} threads: {source}")]
+    Threads {
+        threads: u64,
+        source: rayon::ThreadPoolBuildError,
+    },
+
+    /// The preview window couldn't be opened or drawn to.
+    #[cfg(feature = "gui")]
+    #[error("Failed to show the window: {0}")]
+    Window(#[from] minifb::Error),
+
+    /// A windowed run was asked of a build without the `gui` feature.
+    #[error("This build has no preview window; pass --headless")]
+    NoWindow,
+}
@@ -0,0 +1,71 @@
+use crate::{Image, Loss, Point};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Side length of the square cells the error map sums the loss over, in
+/// pixels.
+const CELL_SIZE: u32 = 8;
+
+/// A coarse map of where an approximation is furthest from its target, for
+/// sampling shape centers where they're needed most.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ErrorMap {
+    columns: u32,
+    width: u32,
+    height: u32,
+    /// Running totals of the loss of every cell, in row-major order.
+    cumulative: Vec<f64>,
+}
+
+impl ErrorMap {
+    /// Sums the loss of `approx` against `target` over every cell.
+    pub(crate) fn new(target: &Image, approx: &Image, loss: &dyn Loss) -> Self {
+        let columns = target.width.div_ceil(CELL_SIZE);
+        let rows = target.height.div_ceil(CELL_SIZE);
+        let mut cells = vec![0.0; (columns * rows) as usize];
+
+        for y in 0..target.height {
+            for x in 0..target.width {
+                let cell = (y / CELL_SIZE * columns + x / CELL_SIZE) as usize;
+                cells[cell] += target.loss(loss, [x, y], approx.color_at([x, y])) as f64;
+            }
+        }
+
+        let cumulative = cells
+            .into_iter()
+            .scan(0.0, |total, loss| {
+                *total += loss;
+                Some(*total)
+            })
+            .collect();
+
+        Self {
+            columns,
+            width: target.width,
+            height: target.height,
+            cumulative,
+        }
+    }
+
+    /// Picks a random pixel, with each cell chosen in proportion to its loss;
+    /// returns `None` if the approximation is already perfect.
+    pub(crate) fn sample(&self, rng: &mut impl Rng) -> Option<Point> {
+        let total = *self.cumulative.last()?;
+
+        if total <= 0.0 {
+            return None;
+        }
+
+        let goal = rng.random::<f64>() * total;
+        let cell = self.cumulative.partition_point(|&loss| loss <= goal);
+        let cell = cell.min(self.cumulative.len() - 1) as u32;
+
+        let x0 = cell % self.columns * CELL_SIZE;
+        let y0 = cell / self.columns * CELL_SIZE;
+
+        Some([
+            rng.random_range(x0..(x0 + CELL_SIZE).min(self.width)),
+            rng.random_range(y0..(y0 + CELL_SIZE).min(self.height)),
+        ])
+    }
+}
@@ -0,0 +1,1985 @@
+//! Approximates images using randomly placed circles and other shapes.
+//!
+//! The stable surface of the library is [`Image`] (construction from an
+//! [`RgbImage`], [`GrayImage`] or [`DynamicImage`], [`Image::blank`],
+//! [`Image::color_at`], [`Image::color_at_checked`], [`Image::contains`],
+//! [`Image::encode`], [`Image::is_grayscale`], [`Image::total_loss`] and the
+//! conversions back into an [`RgbImage`] or [`DynamicImage`]), [`Config`],
+//! the [`Loss`] trait and its implementations, [`LossKind`],
+//! [`RadiusSchedule`], [`Init`], [`ColorStrategy`], [`Symmetry`],
+//! [`Shape`], the [`Primitive`] trait and its implementations, [`Recording`], [`tick`],
+//! [`approximate`], [`mse`], [`psnr`], [`ssim`], [`heatmap`],
+//! [`side_by_side`], [`svg`], [`flatten`], [`tone_map`], [`kmeans_palette`],
+//! [`dither`], [`CirclezError`], [`generate_circle_points`],
+//! [`generate_filled_circle_points`], [`generate_triangle_points`],
+//! [`generate_line_points`], [`generate_ellipse_points`],
+//! [`generate_polygon_points`], and [`generate_ring_points`].
+//!
+//! [`Image::loss_delta`], [`Image::pixel_loss`], [`Image::apply`],
+//! [`Image::reset_loss`], [`Image::blend`], [`Image::set_linear`] and
+//! [`Primitive::coverage`] are public so callers can build their own loops,
+//! but their signatures may still change as the approximation machinery
+//! evolves.
+
+mod approximator;
+mod checkpoint;
+mod dither;
+mod error;
+mod error_map;
+mod loss;
+mod morton;
+mod palette;
+mod recording;
+mod shape;
+mod spacing;
+mod ssim;
+mod svg;
+mod symmetry;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use self::approximator::{Approximator, Steps};
+pub use self::checkpoint::Checkpoint;
+pub use self::dither::dither;
+pub use self::error::CirclezError;
+pub use self::loss::{
+    AbsoluteError, DeltaE, LinearAbsoluteError, LinearSquaredError, Loss, LossKind, SquaredError,
+};
+pub use self::palette::kmeans_palette;
+pub use self::recording::{RecordedShape, Recording};
+pub use self::shape::{
+    generate_circle_points, generate_ellipse_points, generate_filled_circle_points,
+    generate_line_points, generate_polygon_points, generate_ring_points,
+    generate_triangle_points, Blob, Circle, Dot, Ellipse, Line, Polygon, Primitive, Rect, Ring, Shape,
+    ShapeKind, Triangle,
+};
+pub use self::ssim::{ssim, Ssim};
+pub use self::svg::svg;
+pub use self::symmetry::Symmetry;
+
+use self::spacing::RecentCenters;
+use clap::ValueEnum;
+use image::{DynamicImage, GrayImage, Rgba32FImage, RgbImage, RgbaImage};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::thread::LocalKey;
+
+pub type Point = [u32; 2];
+
+/// An RGB color with 8 bits per channel, the precision images are
+/// approximated at; targets with deeper channels are reduced to it when
+/// they're converted into an [`Image`].
+pub type Color = [u8; 3];
+
+/// Knobs controlling how [`tick`] samples and draws shapes.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Which primitive to place.
+    pub shape: ShapeKind,
+
+    /// Primitives to mix instead of always placing [`Config::shape`], with
+    /// weights: each sampled shape is of a kind picked with probability
+    /// proportional to its weight. Ignored when empty or when every weight
+    /// is zero.
+    pub shape_mix: Vec<(ShapeKind, u32)>,
+
+    /// Draw solid disks instead of one pixel wide rings.
+    pub fill: bool,
+
+    /// Thickness of lines and rings, in pixels.
+    pub stroke_width: u32,
+
+    /// Radius of every dot, in pixels.
+    pub dot_size: u32,
+
+    /// How many corners polygons have; at least 3.
+    pub sides: u32,
+
+    /// How quickly blobs fade out, as the standard deviation of their
+    /// falloff relative to their radius.
+    pub sigma: f32,
+
+    /// Blend edge pixels according to how much of them the shape covers,
+    /// instead of overwriting them.
+    pub antialias: bool,
+
+    /// How opaque shapes are, from `0.0` (invisible) to `1.0` (overwrite
+    /// what's underneath).
+    pub opacity: f32,
+
+    /// Blend translucent and antialiased pixels, average the colors picked
+    /// for shapes and measure [`Config::loss`] in linear light instead of
+    /// on the sRGB values (see [`Config::metric`]), the way light actually
+    /// mixes; soft edges and gradients come out without the dark fringes
+    /// sRGB blending leaves. The canvas has to blend the same way, see
+    /// [`Image::set_linear`]; an [`Approximator`] sets its own up.
+    pub linear: bool,
+
+    /// Smallest radius to sample, in pixels.
+    pub min_radius: u32,
+
+    /// Largest radius to sample, in pixels; defaults to a quarter of the
+    /// smaller image dimension.
+    pub max_radius: Option<u32>,
+
+    /// How the largest radius shrinks as the run progresses; only runs
+    /// with a known length (see [`Approximator::set_budget`]) progress.
+    pub radius_schedule: RadiusSchedule,
+
+    /// Starting temperature for simulated annealing, in the same units as
+    /// the loss; `0.0` only ever accepts improvements.
+    pub temperature: f32,
+
+    /// Factor the temperature is multiplied by after every tick.
+    pub cooling: f32,
+
+    /// Spend half of the ticks (once something has been drawn) nudging an
+    /// already placed shape instead of sampling a new one; see
+    /// [`Approximator::tick`].
+    pub refine: bool,
+
+    /// Center half of the sampled shapes in regions where the approximation
+    /// is currently worst; see [`Approximator::tick`].
+    pub error_guided: bool,
+
+    /// How many ticks pass between refreshes of the error map that guided
+    /// sampling draws from.
+    pub error_refresh: usize,
+
+    /// Smallest distance, in pixels, between the center of a new shape and
+    /// the centers of the last few drawn by an [`Approximator`]; closer
+    /// shapes are turned away before their loss is worked out. `0.0` (the
+    /// default) allows any distance.
+    pub min_spacing: f32,
+
+    /// How many ticks pass between calls to [`Approximator::prune`], which
+    /// drops shapes that no longer lower the loss; `0` (the default) never
+    /// prunes.
+    pub prune_every: usize,
+
+    /// Symmetric copies drawn along with every shape; a shape is only kept
+    /// if it and its copies together lower the loss. Colors are picked from
+    /// the pixels under the sampled shape alone, except with
+    /// [`ColorStrategy::Optimal`], which accounts for the copies too.
+    pub symmetry: Symmetry,
+
+    /// How the difference between the approximation and the target is
+    /// measured.
+    pub loss: LossKind,
+
+    /// What the canvas is filled with before the first shape.
+    pub init: Init,
+
+    /// How the color of a sampled shape is picked from the target pixels it
+    /// covers.
+    pub color_strategy: ColorStrategy,
+
+    /// Colors shapes are restricted to: every picked color, and the
+    /// background, is snapped to the closest of them under
+    /// [`Config::loss`]. Empty (the default) allows any color.
+    pub palette: Vec<Color>,
+
+    /// A dark and a light color every picked color, and the background, is
+    /// mapped onto by its luminance, from the dark one for black to the
+    /// light one for white, for two-tone results; it takes the place of
+    /// [`Config::palette`]. `None` (the default) allows any color.
+    pub duotone: Option<[Color; 2]>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shape: ShapeKind::default(),
+            shape_mix: Vec::new(),
+            fill: false,
+            stroke_width: 1,
+            dot_size: 2,
+            sides: 5,
+            sigma: 0.4,
+            antialias: false,
+            opacity: 1.0,
+            linear: false,
+            min_radius: 1,
+            max_radius: None,
+            radius_schedule: RadiusSchedule::None,
+            temperature: 0.0,
+            cooling: 0.9999,
+            refine: false,
+            error_guided: false,
+            error_refresh: 1000,
+            min_spacing: 0.0,
+            prune_every: 0,
+            symmetry: Symmetry::None,
+            loss: LossKind::default(),
+            init: Init::default(),
+            color_strategy: ColorStrategy::default(),
+            palette: Vec::new(),
+            duotone: None,
+        }
+    }
+}
+
+impl Config {
+    /// Returns the range shape radii are sampled from on a `width`x`height`
+    /// canvas.
+    pub fn radius_range(&self, width: u32, height: u32) -> RangeInclusive<u32> {
+        let max_radius = self.max_radius.unwrap_or(width.min(height) / 4);
+        self.min_radius..=max_radius
+    }
+
+    /// Returns the radius range once `progress` (from `0.0` to `1.0`) of the
+    /// run is done, as narrowed by [`Config::radius_schedule`].
+    pub fn scheduled_radius_range(&self, width: u32, height: u32, progress: f32) -> RangeInclusive<u32> {
+        let radii = self.radius_range(width, height);
+        let (min_radius, max_radius) = (*radii.start(), *radii.end());
+
+        min_radius..=self
+            .radius_schedule
+            .max_radius(min_radius, max_radius, progress)
+    }
+
+    /// Returns the loss [`Config::loss`] names, between colors in linear
+    /// light with [`Config::linear`].
+    pub fn metric(&self) -> &'static dyn Loss {
+        if self.linear {
+            self.loss.linear_metric()
+        } else {
+            self.loss.metric()
+        }
+    }
+
+    /// Picks the kind of the next shape to sample, from
+    /// [`Config::shape_mix`] if it has any weight.
+    pub fn pick_shape(&self, rng: &mut impl Rng) -> ShapeKind {
+        let total: u64 = self.shape_mix.iter().map(|&(_, weight)| weight as u64).sum();
+
+        if total == 0 {
+            return self.shape;
+        }
+
+        let mut pick = rng.random_range(0..total);
+
+        for &(kind, weight) in &self.shape_mix {
+            if pick < weight as u64 {
+                return kind;
+            }
+            pick -= weight as u64;
+        }
+
+        unreachable!("the pick is less than the total weight")
+    }
+
+    /// Returns the [`Config::palette`] color closest to `color` under
+    /// [`Config::loss`], as `target` would store them, or `color` itself
+    /// without a palette. Ties go to whichever comes first in the palette.
+    ///
+    /// With a [`Config::duotone`], returns the color on its gradient
+    /// instead.
+    pub fn snap(&self, target: &Image, color: Color) -> Color {
+        if let Some(duotone) = self.duotone {
+            return target.quantize(gradient(duotone, luminance(color) as f32 / 255.0));
+        }
+
+        if self.palette.is_empty() {
+            return color;
+        }
+
+        let loss = self.metric();
+        let color = target.quantize(color);
+
+        self.palette
+            .iter()
+            .map(|&entry| target.quantize(entry))
+            .min_by(|&a, &b| loss.pixel(color, a).total_cmp(&loss.pixel(color, b)))
+            .unwrap_or(color)
+    }
+
+    /// Returns the color the canvas for `target` starts out as: the one
+    /// [`Config::init`] gives, averaged in linear light with
+    /// [`Config::linear`], snapped to the palette.
+    pub fn background(&self, target: &Image) -> Color {
+        self.snap(target, self.init.pick(target, self.linear))
+    }
+
+    /// Returns a copy for a canvas `factor` times narrower and shorter, with
+    /// the sizes in pixels (the radii, [`Config::stroke_width`],
+    /// [`Config::dot_size`] and [`Config::min_spacing`]) divided by `factor`,
+    /// but never below a pixel.
+    pub fn scaled_down(&self, factor: u32) -> Self {
+        let min_radius = (self.min_radius / factor).max(1);
+
+        Self {
+            stroke_width: (self.stroke_width / factor).max(1),
+            dot_size: (self.dot_size / factor).max(1),
+            min_radius,
+            max_radius: self.max_radius.map(|radius| (radius / factor).max(min_radius)),
+            min_spacing: self.min_spacing / factor as f32,
+            ..self.clone()
+        }
+    }
+
+    /// Checks that the radius range is non-empty and that its shapes fit on
+    /// a `width`x`height` canvas.
+    pub fn validate(&self, width: u32, height: u32) -> Result<(), String> {
+        let radii = self.radius_range(width, height);
+        let fits = width.min(height) / 2;
+
+        if *radii.start() == 0 {
+            return Err(String::from("minimum radius must be at least 1"));
+        }
+
+        if radii.start() > radii.end() {
+            return Err(format!(
+                "minimum radius ({}) is larger than the maximum radius ({})",
+                radii.start(),
+                radii.end()
+            ));
+        }
+
+        if *radii.end() > fits {
+            return Err(format!(
+                "maximum radius ({}) doesn't fit in a {}x{} image (at most {})",
+                radii.end(),
+                width,
+                height,
+                fits
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// A shape that was drawn onto an approximation, and the color it was drawn
+/// with.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlacedShape {
+    #[serde(flatten)]
+    pub shape: Shape,
+    pub color: Color,
+}
+
+impl PlacedShape {
+    /// Returns the in-bounds pixel changes that drawing the shape's `points`
+    /// (as returned by [`Shape::points`]) onto a `width`x`height` canvas
+    /// makes.
+    pub fn changes(
+        &self,
+        points: Vec<[isize; 2]>,
+        config: &Config,
+        width: u32,
+        height: u32,
+    ) -> Vec<(Point, Color, f32)> {
+        let mut changes = Vec::new();
+        self.changes_into(&points, config, width, height, &mut changes);
+        changes
+    }
+
+    /// Replaces the contents of `changes` with [`PlacedShape::changes`],
+    /// reusing its allocation.
+    pub fn changes_into(
+        &self,
+        points: &[[isize; 2]],
+        config: &Config,
+        width: u32,
+        height: u32,
+        changes: &mut Vec<(Point, Color, f32)>,
+    ) {
+        // Blobs have no hard edge, so they always fade out
+        let blend = config.antialias || matches!(self.shape, Shape::Blob(_));
+
+        changes.clear();
+        changes.extend(
+            points
+                .iter()
+                .filter(|&&[x, y]| {
+                    x >= 0 &&
+                        y >= 0 &&
+                        x < width as isize &&
+                        y < height as isize
+                })
+                .map(|&[x, y]| {
+                    let alpha = if blend {
+                        config.opacity * self.shape.coverage([x, y])
+                    } else {
+                        config.opacity
+                    };
+
+                    ([x as u32, y as u32], self.color, alpha)
+                }),
+        );
+    }
+
+    /// Returns a slightly changed copy: either the shape is nudged (see
+    /// [`Primitive::perturb`]) or one channel of its color shifts a little;
+    /// with a [`Config::palette`], it switches to another palette color
+    /// instead, and with a [`Config::duotone`] it moves a little along the
+    /// gradient.
+    pub fn perturb(&self, config: &Config, width: u32, height: u32, rng: &mut impl Rng) -> Self {
+        let mut perturbed = *self;
+
+        if rng.random_bool(0.5) {
+            perturbed.shape = self.shape.perturb(config, width, height, rng);
+        } else if let Some(duotone) = config.duotone {
+            let shift = rng.random_range(-16..=16) as f32 / 255.0;
+            perturbed.color = gradient(duotone, gradient_position(duotone, self.color) + shift);
+        } else if !config.palette.is_empty() {
+            perturbed.color = config.palette[rng.random_range(0..config.palette.len())];
+        } else {
+            let channel = rng.random_range(0..3);
+            let shift = rng.random_range(-16..=16);
+            perturbed.color[channel] = (self.color[channel] as i32 + shift).clamp(0, 255) as u8;
+        }
+
+        perturbed
+    }
+}
+
+fn calculate_weighted_color(target: &Image, center_x: isize, center_y: isize, radius: isize,
+                            max_radius: u32, circle_points: &[[isize; 2]], linear: bool) -> [u8; 3] {
+    // Get center color
+    let center_color = if center_x >= 0 && center_y >= 0
+        && center_x < target.width as isize && center_y < target.height as isize {
+        target.color_at([center_x as u32, center_y as u32])
+    } else {
+        [0, 0, 0]
+    };
+
+    if linear {
+        // Average the edge and blend the colors in linear light instead
+        let Some(edge_color) = average(covered_colors(target, circle_points), true) else {
+            return center_color;
+        };
+
+        let weight = (radius as f32 / max_radius as f32).min(1.0);
+        return Image::blend_linear(center_color, edge_color, weight);
+    }
+
+    // Calculate average edge color from valid points
+    let mut valid_points = 0;
+    let edge_color = circle_points.iter()
+        .filter(|[x, y]| {
+            x >= &0 && y >= &0 &&
+                x < &(target.width as isize) && y < &(target.height as isize)
+        })
+        .map(|[x, y]| {
+            valid_points += 1;
+            target.color_at([*x as u32, *y as u32])
+        })
+        .fold([0f32; 3], |acc, [r, g, b]| {
+            [acc[0] + r as f32, acc[1] + g as f32, acc[2] + b as f32]
+        });
+
+    if valid_points == 0 {
+        return center_color;
+    }
+
+    let edge_color = [
+        (edge_color[0] / valid_points as f32) as u8,
+        (edge_color[1] / valid_points as f32) as u8,
+        (edge_color[2] / valid_points as f32) as u8,
+    ];
+
+    // Calculate weight based on radius (larger radius = more weight to edge color)
+    let weight = (radius as f32 / max_radius as f32).min(1.0);
+
+    // Blend colors
+    [
+        ((1.0 - weight) * center_color[0] as f32 + weight * edge_color[0] as f32) as u8,
+        ((1.0 - weight) * center_color[1] as f32 + weight * edge_color[1] as f32) as u8,
+        ((1.0 - weight) * center_color[2] as f32 + weight * edge_color[2] as f32) as u8,
+    ]
+}
+
+/// Returns the mean color of the target pixels under `points`, averaged in
+/// linear light if `linear` is set, or black if none of them are on the
+/// target.
+fn mean_color(target: &Image, points: &[[isize; 2]], linear: bool) -> Color {
+    average(covered_colors(target, points), linear).unwrap_or([0, 0, 0])
+}
+
+/// Returns the mean of `colors`, averaged in linear light if `linear` is
+/// set, or `None` if there aren't any.
+fn average(colors: impl Iterator<Item = Color>, linear: bool) -> Option<Color> {
+    let mut sum = [0f64; 3];
+    let mut count = 0;
+
+    for color in colors {
+        for i in 0..3 {
+            sum[i] += if linear { loss::linear(color[i]) as f64 } else { color[i] as f64 };
+        }
+
+        count += 1;
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    Some(sum.map(|sum| {
+        let mean = sum / count as f64;
+
+        if linear {
+            loss::srgb(mean as f32)
+        } else {
+            mean.round() as u8
+        }
+    }))
+}
+
+/// Returns the median of each channel of the target pixels under `points`,
+/// or black if none of them are on the target.
+fn median_color(target: &Image, points: &[[isize; 2]]) -> Color {
+    let mut histograms = [[0u32; 256]; 3];
+    let mut count = 0;
+
+    for color in covered_colors(target, points) {
+        for i in 0..3 {
+            histograms[i][color[i] as usize] += 1;
+        }
+
+        count += 1;
+    }
+
+    if count == 0 {
+        return [0, 0, 0];
+    }
+
+    histograms.map(|histogram| {
+        let mut seen = 0;
+
+        histogram
+            .iter()
+            .position(|&n| {
+                seen += n;
+                seen * 2 >= count
+            })
+            .unwrap() as u8
+    })
+}
+
+/// Returns the colors of the target pixels under `points`, skipping points
+/// that aren't on it.
+fn covered_colors<'a>(target: &'a Image, points: &'a [[isize; 2]]) -> impl Iterator<Item = Color> + 'a {
+    points
+        .iter()
+        .filter(|&&[x, y]| x >= 0 && y >= 0 && x < target.width as isize && y < target.height as isize)
+        .map(|&[x, y]| target.color_at([x as u32, y as u32]))
+}
+
+/// How the color of a sampled shape is picked from the target.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorStrategy {
+    /// Blend the target color at the center with the average along the
+    /// outline, leaning towards the outline the larger the shape.
+    #[default]
+    Weighted,
+    /// Average every covered pixel.
+    Mean,
+    /// Take the median of every channel over the covered pixels, which
+    /// ignores a minority of differently colored pixels instead of mixing
+    /// them in, so shapes straddling an edge smear it less.
+    Median,
+    /// Solve for the color that brings the covered pixels closest to the
+    /// target, taking the current approximation, opacity and antialiasing
+    /// into account. With full opacity that's the mean of the covered
+    /// pixels; it's exact for [`SquaredError`] and a close stand-in for the
+    /// other losses.
+    Optimal,
+}
+
+/// Returns the color that, drawn with the alphas in `changes`, minimizes the
+/// squared error between `approx` and `target` over the changed pixels,
+/// weighted by the target's importance map; in linear light if `approx`
+/// blends that way.
+///
+/// Blending color `c` over `a` with alpha `α` gives `a + α(c - a)`, so the
+/// error is quadratic in `c` and each channel's minimum has a closed form.
+fn optimal_color(target: &Image, approx: &Image, changes: &[(Point, Color, f32)]) -> Color {
+    let value = |channel: u8| {
+        if approx.is_linear() {
+            loss::linear(channel) as f64 * 255.0
+        } else {
+            channel as f64
+        }
+    };
+
+    let mut numerator = [0.0f64; 3];
+    let mut denominator = 0.0f64;
+
+    for &(pos, _, alpha) in changes {
+        let weight = target.importance_at(pos).unwrap_or(1.0) as f64;
+        let alpha = alpha as f64;
+        let (wanted, under) = (target.pixel(pos), approx.pixel(pos));
+
+        for i in 0..3 {
+            numerator[i] += weight * alpha * (value(wanted[i]) - (1.0 - alpha) * value(under[i]));
+        }
+
+        denominator += weight * alpha * alpha;
+    }
+
+    if denominator <= 0.0 {
+        return [0, 0, 0];
+    }
+
+    numerator.map(|numerator| {
+        let channel = (numerator / denominator).clamp(0.0, 255.0);
+
+        if approx.is_linear() {
+            loss::srgb((channel / 255.0) as f32)
+        } else {
+            channel.round() as u8
+        }
+    })
+}
+
+/// How the largest sampled radius shrinks over a run, so that big shapes lay
+/// down the background early and small ones add detail later.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum RadiusSchedule {
+    /// Keep the full radius range for the whole run.
+    #[default]
+    None,
+    /// Shrink the largest radius linearly down to the smallest one.
+    Linear,
+    /// Shrink the largest radius geometrically, spending more of the run on
+    /// small shapes.
+    Exp,
+}
+
+impl RadiusSchedule {
+    /// Returns the largest radius to sample once `progress` (from `0.0` to
+    /// `1.0`) of the run is done.
+    pub fn max_radius(self, min_radius: u32, max_radius: u32, progress: f32) -> u32 {
+        let progress = progress.clamp(0.0, 1.0);
+        let (min, max) = (min_radius as f32, max_radius as f32);
+
+        let radius = match self {
+            RadiusSchedule::None => max,
+            RadiusSchedule::Linear => max - (max - min) * progress,
+            RadiusSchedule::Exp => max * (min / max).powf(progress),
+        };
+
+        (radius.round() as u32).clamp(min_radius, max_radius)
+    }
+}
+
+/// What an [`Approximator`] fills the canvas with before drawing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Init {
+    /// Start from black.
+    #[default]
+    Black,
+    /// Start from the average color of the target, so the first shapes add
+    /// detail instead of laying down a base tone.
+    Average,
+    /// Start from the given color.
+    Color(Color),
+}
+
+impl Init {
+    /// Returns the color the canvas for `target` starts out as.
+    pub fn color(self, target: &Image) -> Color {
+        self.pick(target, false)
+    }
+
+    /// Returns the color the canvas for `target` starts out as, averaging
+    /// in linear light if `linear` is set.
+    fn pick(self, target: &Image, linear: bool) -> Color {
+        let color = match self {
+            Init::Black => [0, 0, 0],
+            Init::Color(color) => color,
+            Init::Average => {
+                let colors = (0..target.height)
+                    .flat_map(|y| (0..target.width).map(move |x| target.color_at([x, y])));
+
+                average(colors, linear).unwrap_or([0, 0, 0])
+            }
+        };
+
+        target.quantize(color)
+    }
+}
+
+/// A randomly sampled shape, along with how drawing it would change the
+/// loss; the changes themselves are left in the [`Scratch`] it was sampled
+/// with.
+struct Candidate {
+    placed: PlacedShape,
+    loss_delta: f64,
+}
+
+/// Constraints on the shapes [`sample`] picks.
+#[derive(Clone, Copy, Debug, Default)]
+struct Placement<'a> {
+    /// How much of the run is done, for the radius schedule.
+    progress: f32,
+    /// Where to center the shape, instead of at random.
+    center: Option<Point>,
+    /// Whether shapes that stick out of the canvas are rejected.
+    contained: bool,
+    /// Centers that shapes closer than [`Config::min_spacing`] to are
+    /// rejected.
+    recent: Option<&'a RecentCenters>,
+}
+
+/// Buffers [`sample`] reuses from one tick to the next, so long runs don't
+/// allocate for every shape.
+#[derive(Debug, Default)]
+struct Scratch {
+    points: Vec<[isize; 2]>,
+    changes: Vec<(Point, Color, f32)>,
+    /// The changes of one symmetric copy at a time.
+    copy_changes: Vec<(Point, Color, f32)>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::default();
+    static RESOLVED: RefCell<Vec<(Point, Color)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` with this thread's buffer in `key`, or with a fresh one if it's
+/// already in use (by a [`Loss`] calling back into the library, say).
+fn with_scratch<T: Default + 'static, R>(
+    key: &'static LocalKey<RefCell<T>>,
+    f: impl FnOnce(&mut T) -> R,
+) -> R {
+    key.with(|cell| match cell.try_borrow_mut() {
+        Ok(mut scratch) => f(&mut scratch),
+        Err(_) => f(&mut T::default()),
+    })
+}
+
+/// Samples a random shape and works out how drawing it onto `approx` would
+/// change the loss against `target`, leaving the changes in
+/// `scratch.changes`; degenerate shapes, and shapes `placement` rules out,
+/// yield `None`.
+fn sample(
+    target: &Image,
+    approx: &Image,
+    config: &Config,
+    placement: Placement<'_>,
+    scratch: &mut Scratch,
+    rng: &mut impl Rng,
+) -> Option<Candidate> {
+    // Randomize position and size
+    let radii = config.scheduled_radius_range(target.width, target.height, placement.progress);
+    let mut shape = Shape::random(config, radii, target.width, target.height, rng);
+
+    if let Some([x, y]) = placement.center {
+        let [center_x, center_y] = shape.center();
+        shape = shape.translate([x as isize - center_x, y as isize - center_y]);
+    }
+    let [center_x, center_y] = shape.center();
+
+    if placement.contained {
+        let [x0, y0, x1, y1] = shape.bounding_box();
+
+        if x0 < 0 || y0 < 0 || x1 >= target.width as isize || y1 >= target.height as isize {
+            return None;
+        }
+    }
+
+    if let Some(recent) = placement.recent {
+        if config.min_spacing > 0.0 && recent.crowds([center_x, center_y], config.min_spacing) {
+            return None;
+        }
+    }
+
+    // Generate shape points first so we can use them for both color calculation and drawing
+    let Scratch { points, changes, copy_changes } = scratch;
+    shape.points_into((target.width, target.height), points);
+
+    // Degenerate shapes (e.g. collinear triangles) don't cover anything
+    if points.is_empty() {
+        return None;
+    }
+
+    let color = match config.color_strategy {
+        ColorStrategy::Weighted => {
+            let max_radius = *config.radius_range(target.width, target.height).end();
+            calculate_weighted_color(target, center_x, center_y, shape.radius(), max_radius,
+                                     points, config.linear)
+        }
+        ColorStrategy::Mean => mean_color(target, points, config.linear),
+        ColorStrategy::Median => median_color(target, points),
+        // Depends on the alpha of every change, so it's picked below
+        ColorStrategy::Optimal => [0, 0, 0],
+    };
+
+    let mut placed = PlacedShape {
+        shape,
+        color: config.snap(target, color),
+    };
+    placed.changes_into(points, config, target.width, target.height, changes);
+
+    let bounds = (target.width, target.height);
+
+    for shape in config.symmetry.copies(shape, bounds) {
+        shape.points_into(bounds, points);
+        let copy = PlacedShape { shape, ..placed };
+        copy.changes_into(points, config, target.width, target.height, copy_changes);
+        changes.append(copy_changes);
+    }
+
+    // Frozen pixels are left alone, and shapes covering nothing else with them
+    changes.retain(|&(pos, ..)| target.is_editable(pos));
+
+    if changes.is_empty() {
+        return None;
+    }
+
+    if config.color_strategy == ColorStrategy::Optimal {
+        placed.color = config.snap(target, optimal_color(target, approx, changes));
+
+        for change in changes.iter_mut() {
+            change.1 = placed.color;
+        }
+    }
+
+    if config.symmetry != Symmetry::None {
+        merge_overlaps(approx, changes);
+    }
+
+    // Check how drawing this shape would change the approximation
+    let loss_delta = Image::loss_delta(target, approx, changes, config.metric());
+
+    Some(Candidate { placed, loss_delta })
+}
+
+/// Folds changes to the same pixel, which overlapping symmetric copies make,
+/// into one that leaves the pixel as drawing them one after the other would.
+fn merge_overlaps(approx: &Image, changes: &mut Vec<(Point, Color, f32)>) {
+    // The sort is stable, so changes to a pixel stay in drawing order
+    changes.sort_by_key(|&([x, y], ..)| (y, x));
+    changes.dedup_by(|&mut (pos, color, alpha), earlier| {
+        if pos != earlier.0 {
+            return false;
+        }
+
+        let under = approx.composite(approx.pixel(pos), earlier.1, earlier.2);
+        *earlier = (pos, approx.composite(under, color, alpha), 1.0);
+        true
+    });
+}
+
+/// Samples a single random shape and draws it onto `approx` if doing so
+/// brings it closer to `target`.
+///
+/// Returns whether the shape was drawn. This is always greedy; use an
+/// [`Approximator`] for annealing. `approx` must be tracking
+/// [`Config::loss`], see [`Image::reset_loss`].
+pub fn tick(target: &Image, approx: &mut Image, config: &Config, rng: &mut impl Rng) -> bool {
+    with_scratch(&SCRATCH, |scratch| {
+        match sample(target, approx, config, Placement::default(), scratch, rng) {
+            // Apply the changes if the shape improves the approximation
+            Some(candidate) if candidate.loss_delta < 0.0 => {
+                approx.apply(target, &scratch.changes, config.metric());
+                true
+            }
+
+            _ => false,
+        }
+    })
+}
+
+/// Runs `iterations` ticks against `target`, starting from a black canvas,
+/// and returns the resulting approximation.
+///
+/// This doesn't need a display, so it's suitable for servers and CI. With a
+/// seeded `rng` the result is fully reproducible.
+pub fn approximate(
+    target: &Image,
+    config: &Config,
+    iterations: usize,
+    rng: &mut impl Rng,
+) -> Image {
+    let mut approximator = Approximator::new(target.clone(), config.clone(), rng);
+
+    approximator.run(iterations);
+    approximator.approx().debug_assert_loss(target, config.metric());
+
+    approximator.into_approx()
+}
+
+/// Returns the mean squared error between two images of the same size,
+/// averaged over every channel of every pixel; grayscale images only have
+/// one channel.
+///
+/// # Panics
+///
+/// Panics if the images aren't the same size.
+pub fn mse(a: &Image, b: &Image) -> f64 {
+    assert_eq!(
+        (a.width, a.height),
+        (b.width, b.height),
+        "images must be the same size"
+    );
+
+    let channels = if a.is_grayscale() && b.is_grayscale() { 1 } else { 3 };
+
+    let total: f64 = (0..a.height)
+        .flat_map(|y| (0..a.width).map(move |x| [x, y]))
+        .map(|pos| {
+            let (a, b) = (a.color_at(pos), b.color_at(pos));
+
+            (0..channels)
+                .map(|i| (a[i] as f64 - b[i] as f64).powi(2))
+                .sum::<f64>()
+        })
+        .sum();
+
+    total / (a.width as f64 * a.height as f64 * channels as f64)
+}
+
+/// Returns the peak signal-to-noise ratio between two images of the same
+/// size, in decibels; higher is better, and identical images give infinity.
+///
+/// # Panics
+///
+/// Panics if the images aren't the same size.
+pub fn psnr(a: &Image, b: &Image) -> f64 {
+    10.0 * (255.0f64.powi(2) / mse(a, b)).log10()
+}
+
+/// Renders where two images of the same size differ, as a heatmap of
+/// [`Image::pixel_loss`] running from blue where they match, through cyan,
+/// green and yellow, to red where they differ the most.
+///
+/// The colors are relative to the largest difference, so the heatmap shows
+/// detail however close the images are; identical images are all blue.
+///
+/// # Panics
+///
+/// Panics if the images aren't the same size.
+pub fn heatmap(a: &Image, b: &Image) -> RgbImage {
+    assert_eq!(
+        (a.width, a.height),
+        (b.width, b.height),
+        "images must be the same size"
+    );
+
+    let losses: Vec<f32> = (0..a.height)
+        .flat_map(|y| (0..a.width).map(move |x| [x, y]))
+        .map(|pos| Image::pixel_loss(a.color_at(pos), b.color_at(pos)))
+        .collect();
+    let max = losses.iter().copied().fold(0.0, f32::max);
+
+    RgbImage::from_fn(a.width, a.height, |x, y| {
+        let loss = losses[(y * a.width + x) as usize];
+        let t = if max > 0.0 { loss / max } else { 0.0 };
+
+        heat(t).into()
+    })
+}
+
+/// Width of the divider between the halves of [`side_by_side`], in pixels.
+const DIVIDER_WIDTH: u32 = 2;
+
+/// Places two images of the same size next to each other, `left` and then
+/// `right`, separated by a thin white divider, for comparing them at a
+/// glance.
+///
+/// # Panics
+///
+/// Panics if the images aren't the same size.
+pub fn side_by_side(left: &Image, right: &Image) -> RgbImage {
+    assert_eq!(
+        (left.width, left.height),
+        (right.width, right.height),
+        "images must be the same size"
+    );
+
+    let (width, height) = (left.width, left.height);
+    let mut image = RgbImage::from_pixel(2 * width + DIVIDER_WIDTH, height, [255; 3].into());
+
+    image::imageops::replace(&mut image, &RgbImage::from(left), 0, 0);
+    image::imageops::replace(&mut image, &RgbImage::from(right), (width + DIVIDER_WIDTH) as i64, 0);
+
+    image
+}
+
+/// Maps `t`, from `0.0` to `1.0`, onto the blue to red scale of [`heatmap`].
+fn heat(t: f32) -> Color {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 255.0],
+        [0.0, 255.0, 255.0],
+        [0.0, 255.0, 0.0],
+        [255.0, 255.0, 0.0],
+        [255.0, 0.0, 0.0],
+    ];
+
+    let scaled = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (scaled as usize).min(STOPS.len() - 2);
+    let f = scaled - i as f32;
+
+    std::array::from_fn(|c| (STOPS[i][c] + (STOPS[i + 1][c] - STOPS[i][c]) * f).round() as u8)
+}
+
+/// Returns the color `t` of the way from `dark` to `light`, where `t` is
+/// clamped to `0.0..=1.0`.
+fn gradient([dark, light]: [Color; 2], t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    std::array::from_fn(|c| (dark[c] as f32 + (light[c] as f32 - dark[c] as f32) * t).round() as u8)
+}
+
+/// Returns how far along the way from `dark` to `light` the point closest to
+/// `color` is, the opposite of [`gradient`].
+fn gradient_position([dark, light]: [Color; 2], color: Color) -> f32 {
+    let along = |c: usize| light[c] as f32 - dark[c] as f32;
+    let length: f32 = (0..3).map(|c| along(c).powi(2)).sum();
+
+    if length == 0.0 {
+        return 0.0;
+    }
+
+    (0..3).map(|c| (color[c] as f32 - dark[c] as f32) * along(c)).sum::<f32>() / length
+}
+
+/// Composites an image with transparency over a solid `background`, the way
+/// it would look displayed on top of it.
+pub fn flatten(image: &RgbaImage, background: Color) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        Image::blend(background, [r, g, b], a as f32 / 255.0).into()
+    })
+}
+
+/// Tone-maps a high dynamic range image, in linear light like OpenEXR and
+/// Radiance HDR files, to 8-bit sRGB, `exposure` stops brighter (or darker
+/// if negative).
+///
+/// Luminance is compressed with the extended Reinhard operator, with the
+/// brightest pixel as white, so highlights keep their detail instead of
+/// clipping and images that never go past `1.0` only get encoded. Colors
+/// keep their hue, clipping where they're too saturated to fit; negative
+/// and NaN channels count as black.
+pub fn tone_map(image: &Rgba32FImage, exposure: f32) -> RgbaImage {
+    let scale = exposure.exp2();
+    let luminance = |[r, g, b]: [f32; 3]| 0.2126 * r + 0.7152 * g + 0.0722 * b;
+
+    let rgb = |pixel: &image::Rgba<f32>| {
+        std::array::from_fn(|c| {
+            let value = pixel[c] * scale;
+
+            // Up to the largest half float, what OpenEXR usually stores, so
+            // infinities don't turn the whole image black
+            if value.is_nan() {
+                0.0
+            } else {
+                value.clamp(0.0, 65504.0)
+            }
+        })
+    };
+
+    let white = image.pixels().map(|pixel| luminance(rgb(pixel))).fold(1.0, f32::max);
+
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let pixel = image.get_pixel(x, y);
+        let color = rgb(pixel);
+        let before = luminance(color);
+        let after = before * (1.0 + before / (white * white)) / (1.0 + before);
+        let ratio = if before > 0.0 { after / before } else { 0.0 };
+
+        let [r, g, b] = color.map(|value| loss::srgb(value * ratio));
+        let alpha = (pixel[3].clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        [r, g, b, alpha].into()
+    })
+}
+
+/// An RGB or grayscale image stored as tightly packed bytes, row by row.
+///
+/// Grayscale images still hand out [`Color`]s, with the luminance repeated
+/// across all three channels, and store the luminance of colors written to
+/// them. Both kinds share every method, which only look at how many channels
+/// the image stores; build one from an [`RgbImage`], a [`GrayImage`] or,
+/// picking whichever fits, a [`DynamicImage`].
+#[derive(Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    channels: usize,
+    pixels: Vec<u8>,
+    /// How much each pixel counts towards the loss, row by row, see
+    /// [`Image::set_importance_map`].
+    importance: Option<Vec<f32>>,
+    /// Which pixels approximations may change, row by row, see
+    /// [`Image::set_mask`].
+    mask: Option<Vec<bool>>,
+    total_loss: f64,
+    /// Whether changes are blended in linear light, see
+    /// [`Image::set_linear`].
+    linear: bool,
+    /// Which pixels have been drawn onto, in the same layout as `pixels`;
+    /// see [`Image::is_touched`].
+    touched: Vec<bool>,
+    /// The columns of each row changed since the last call to
+    /// [`Image::encode_changes`], from `start` up to `end`; rows that didn't
+    /// change have `start >= end`.
+    changed: Vec<[u32; 2]>,
+}
+
+impl Image {
+    /// Returns a black canvas the size of `target`, ready to approximate it;
+    /// it's grayscale if `target` is.
+    ///
+    /// The canvas tracks the [`SquaredError`]; use [`Image::reset_loss`] to
+    /// track another [`Loss`].
+    pub fn blank(target: &Self) -> Self {
+        let mut approx = Self::filled(target.width, target.height, target.is_grayscale(), [0; 3]);
+        approx.reset_loss(target, &SquaredError);
+        approx
+    }
+
+    /// Returns a `width`x`height` image of a single color that doesn't track
+    /// a loss.
+    pub(crate) fn filled(width: u32, height: u32, grayscale: bool, color: Color) -> Self {
+        let pixel = if grayscale {
+            vec![luminance(color)]
+        } else {
+            color.to_vec()
+        };
+
+        Self {
+            width,
+            height,
+            channels: pixel.len(),
+            pixels: pixel.repeat(stored_len(width, height)),
+            importance: None,
+            mask: None,
+            total_loss: 0.0,
+            linear: false,
+            touched: vec![false; stored_len(width, height)],
+            changed: all_changed(width, height),
+        }
+    }
+
+    /// Returns a `width`x`height` image of `rows`, its pixels row by row
+    /// with `channels` bytes each, that doesn't track a loss.
+    pub(crate) fn from_rows(width: u32, height: u32, channels: usize, rows: Vec<u8>) -> Self {
+        let pixels = if cfg!(feature = "morton") {
+            morton::from_rows(width, height, channels, &rows)
+        } else {
+            rows
+        };
+
+        Self {
+            width,
+            height,
+            channels,
+            pixels,
+            importance: None,
+            mask: None,
+            total_loss: 0.0,
+            linear: false,
+            touched: vec![false; stored_len(width, height)],
+            changed: all_changed(width, height),
+        }
+    }
+
+    /// Returns the pixels row by row, whichever layout they're stored in.
+    pub(crate) fn rows(&self) -> Vec<u8> {
+        if cfg!(feature = "morton") {
+            morton::to_rows(self.width, self.height, self.channels, &self.pixels)
+        } else {
+            self.pixels.clone()
+        }
+    }
+
+    /// Weighs how much each pixel of this image counts towards the loss of
+    /// approximations of it by the brightness of the matching pixel in
+    /// `map`: white pixels count fully, black ones not at all.
+    ///
+    /// Fails if `map` isn't the same size as the image.
+    pub fn set_importance_map(&mut self, map: &GrayImage) -> Result<(), String> {
+        if map.dimensions() != (self.width, self.height) {
+            return Err(format!(
+                "importance map is {}x{}, but the image is {}x{}",
+                map.width(),
+                map.height(),
+                self.width,
+                self.height
+            ));
+        }
+
+        self.importance = Some(map.pixels().map(|pixel| pixel.0[0] as f32 / 255.0).collect());
+        Ok(())
+    }
+
+    /// Freezes the pixels of this image that are dark in `mask`, so that
+    /// approximations of it leave them as they are in the image: shapes are
+    /// only drawn onto the pixels that are light in `mask`, and an
+    /// [`Approximator`] copies the frozen ones onto its canvas.
+    ///
+    /// Fails if `mask` isn't the same size as the image.
+    pub fn set_mask(&mut self, mask: &GrayImage) -> Result<(), String> {
+        if mask.dimensions() != (self.width, self.height) {
+            return Err(format!(
+                "mask is {}x{}, but the image is {}x{}",
+                mask.width(),
+                mask.height(),
+                self.width,
+                self.height
+            ));
+        }
+
+        self.mask = Some(mask.pixels().map(|pixel| pixel.0[0] >= 128).collect());
+        Ok(())
+    }
+
+    /// Returns whether the image only stores luminance.
+    pub fn is_grayscale(&self) -> bool {
+        self.channels == 1
+    }
+
+    /// Makes [`Image::apply`] and [`Image::loss_delta`] blend changes over
+    /// the image in linear light, for canvases approximating with
+    /// [`Config::linear`].
+    pub fn set_linear(&mut self, linear: bool) {
+        self.linear = linear;
+    }
+
+    /// Returns whether changes are blended in linear light, see
+    /// [`Image::set_linear`].
+    pub fn is_linear(&self) -> bool {
+        self.linear
+    }
+
+    /// Returns the loss against the image this one approximates, as kept up
+    /// to date by [`Image::apply`].
+    ///
+    /// This is only meaningful after [`Image::reset_loss`] (or
+    /// [`Image::blank`]) has established the starting point.
+    pub fn total_loss(&self) -> f64 {
+        self.total_loss
+    }
+
+    /// Recomputes the loss against `target` from scratch and starts tracking
+    /// it from there; later [`Image::apply`] calls must use the same `loss`.
+    pub fn reset_loss(&mut self, target: &Self, loss: &dyn Loss) {
+        self.total_loss = self.compute_loss(target, loss);
+    }
+
+    /// Returns the loss against `target`, summed over every pixel.
+    pub fn compute_loss(&self, target: &Self, loss: &dyn Loss) -> f64 {
+        loss.total(target, self)
+    }
+
+    /// Checks, in debug builds, that the incrementally tracked loss hasn't
+    /// drifted from a full recompute.
+    pub fn debug_assert_loss(&self, target: &Self, loss: &dyn Loss) {
+        if cfg!(debug_assertions) {
+            let loss = self.compute_loss(target, loss);
+
+            debug_assert!(
+                (self.total_loss - loss).abs() <= 1e-6 * loss.max(1.0),
+                "tracked loss {} drifted from the actual loss {}",
+                self.total_loss,
+                loss
+            );
+        }
+    }
+
+    /// Returns how much the loss against `target` would change if `changes`
+    /// were applied to `approx`; negative values mean an improvement.
+    ///
+    /// Each change is blended over the current color with its alpha, exactly
+    /// like [`Image::apply`] does. The difference is worked out and summed
+    /// in `f64`, so even for huge shapes whose gains and losses nearly cancel
+    /// out its sign can be trusted.
+    pub fn loss_delta(
+        target: &Self,
+        approx: &Self,
+        changes: &[(Point, Color, f32)],
+        loss: &dyn Loss,
+    ) -> f64 {
+        with_scratch(&RESOLVED, |resolved| {
+            approx.resolve(changes, resolved);
+            loss.delta(target, approx, resolved)
+        })
+    }
+
+    pub fn pixel_loss(a: Color, b: Color) -> f32 {
+        a.into_iter()
+            .zip(b)
+            .map(|(a, b)| (a as f32 - b as f32).powi(2))
+            .sum()
+    }
+
+    /// Returns the loss of `color` against this image's color at `pos`,
+    /// weighted by its importance; grayscale images only compare a single
+    /// channel.
+    pub(crate) fn loss<L: Loss + ?Sized>(&self, loss: &L, pos: Point, color: Color) -> f32 {
+        let own = self.pixel(pos);
+
+        let pixel_loss = if self.is_grayscale() {
+            loss.gray(own[0], color[0])
+        } else {
+            loss.pixel(own, color)
+        };
+
+        match self.importance_at(pos) {
+            Some(importance) => pixel_loss * importance,
+            None => pixel_loss,
+        }
+    }
+
+    /// Returns whether approximations of this image may change the pixel at
+    /// `pos`, see [`Image::set_mask`].
+    pub(crate) fn is_editable(&self, [x, y]: Point) -> bool {
+        self.mask
+            .as_ref()
+            .is_none_or(|mask| mask[y as usize * self.width as usize + x as usize])
+    }
+
+    /// Copies the pixels of `target` that its mask freezes onto the image,
+    /// without tracking the loss.
+    pub(crate) fn copy_frozen(&mut self, target: &Self) {
+        for (pos, color) in target.frozen() {
+            self.set_color(pos, self.quantize(color));
+        }
+    }
+
+    /// Returns the pixels the image's mask freezes, along with their colors.
+    pub(crate) fn frozen(&self) -> Vec<(Point, Color)> {
+        if self.mask.is_none() {
+            return Vec::new();
+        }
+
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| [x, y]))
+            .filter(|&pos| !self.is_editable(pos))
+            .map(|pos| (pos, self.color_at(pos)))
+            .collect()
+    }
+
+    /// Returns how much the pixel at `pos` counts towards the loss, if the
+    /// image has an importance map.
+    pub(crate) fn importance_at(&self, [x, y]: Point) -> Option<f32> {
+        self.importance
+            .as_ref()
+            .map(|importance| importance[y as usize * self.width as usize + x as usize])
+    }
+
+    /// Paints `changes` onto the image, blending each color over the current
+    /// one with its alpha (`1.0` overwrites the pixel), and updates the
+    /// tracked loss against `target`.
+    pub fn apply(&mut self, target: &Self, changes: &[(Point, Color, f32)], loss: &dyn Loss) {
+        with_scratch(&RESOLVED, |resolved| {
+            self.resolve(changes, resolved);
+            self.total_loss += loss.delta(target, self, resolved);
+
+            for &(pos, color) in resolved.iter() {
+                self.set_color(pos, color);
+            }
+        })
+    }
+
+    /// Replaces the contents of `resolved` with the colors `changes` would
+    /// leave behind, blended over this image's current ones and
+    /// [quantized](Image::quantize).
+    fn resolve(&self, changes: &[(Point, Color, f32)], resolved: &mut Vec<(Point, Color)>) {
+        resolved.clear();
+        resolved.extend(changes.iter().map(|&(pos, color, alpha)| {
+            (pos, self.composite(self.pixel(pos), color, alpha))
+        }));
+    }
+
+    /// Paints `changes` onto the image like [`Image::apply`], without
+    /// tracking the loss.
+    pub(crate) fn paint(&mut self, changes: impl IntoIterator<Item = (Point, Color, f32)>) {
+        for (pos, col, alpha) in changes {
+            let new_col = self.composite(self.pixel(pos), col, alpha);
+            self.set_color(pos, new_col);
+        }
+    }
+
+    /// Returns the `width`x`height` part of the image with its top left
+    /// corner at `[x, y]`, along with that part of the importance map and
+    /// mask; it doesn't track a loss.
+    pub(crate) fn crop(&self, [x, y]: Point, width: u32, height: u32) -> Self {
+        let mut rows = Vec::with_capacity(width as usize * height as usize * self.channels);
+
+        for row in y..y + height {
+            if cfg!(feature = "morton") {
+                for col in x..x + width {
+                    let start = self.offset([col, row]);
+                    rows.extend_from_slice(&self.pixels[start..start + self.channels]);
+                }
+            } else {
+                let start = self.offset([x, row]);
+                rows.extend_from_slice(&self.pixels[start..start + width as usize * self.channels]);
+            }
+        }
+
+        let importance = self.importance.as_ref().map(|importance| {
+            (y..y + height)
+                .flat_map(|row| {
+                    let start = (row * self.width + x) as usize;
+                    importance[start..start + width as usize].iter().copied()
+                })
+                .collect()
+        });
+
+        let mask = self.mask.as_ref().map(|mask| {
+            (y..y + height)
+                .flat_map(|row| {
+                    let start = (row * self.width + x) as usize;
+                    mask[start..start + width as usize].iter().copied()
+                })
+                .collect()
+        });
+
+        let mut part = Self {
+            importance,
+            mask,
+            linear: self.linear,
+            ..Self::from_rows(width, height, self.channels, rows)
+        };
+
+        for row in 0..height {
+            for col in 0..width {
+                let index = part.index([col, row]) / self.channels;
+                part.touched[index] = self.is_touched([x + col, y + row]);
+            }
+        }
+
+        part
+    }
+
+    /// Copies the pixels of `part` into the image with its top left corner
+    /// at `[x, y]`, without tracking the loss.
+    pub(crate) fn paste(&mut self, part: &Self, [x, y]: Point) {
+        let row_len = part.width as usize * self.channels;
+
+        for row in 0..part.height {
+            if cfg!(feature = "morton") {
+                for col in 0..part.width {
+                    let start = self.offset([x + col, y + row]);
+                    let part_start = part.offset([col, row]);
+
+                    self.pixels[start..start + self.channels]
+                        .copy_from_slice(&part.pixels[part_start..part_start + self.channels]);
+                }
+            } else {
+                let start = self.offset([x, y + row]);
+                let part_start = part.offset([0, row]);
+
+                self.pixels[start..start + row_len]
+                    .copy_from_slice(&part.pixels[part_start..part_start + row_len]);
+            }
+
+            for col in 0..part.width {
+                let index = self.index([x + col, y + row]) / self.channels;
+                self.touched[index] = part.is_touched([col, row]);
+            }
+
+            self.mark_changed([x, y + row], part.width);
+        }
+    }
+
+    /// Blends `over` on top of `under`, where `alpha` is the opacity of
+    /// `over`.
+    pub fn blend(under: Color, over: Color, alpha: f32) -> Color {
+        [0, 1, 2].map(|i| {
+            (under[i] as f32 + (over[i] as f32 - under[i] as f32) * alpha).round() as u8
+        })
+    }
+
+    /// Blends `over` on top of `under` like [`Image::blend`], in linear light.
+    fn blend_linear(under: Color, over: Color, alpha: f32) -> Color {
+        [0, 1, 2].map(|i| {
+            let (under, over) = (loss::linear(under[i]), loss::linear(over[i]));
+            loss::srgb(under + (over - under) * alpha)
+        })
+    }
+
+    /// Returns the color blending `over` on top of `under` leaves behind on
+    /// this image: blended in linear light if it's [set up](Image::set_linear)
+    /// that way, and [quantized](Image::quantize).
+    pub(crate) fn composite(&self, under: Color, over: Color, alpha: f32) -> Color {
+        self.quantize(if self.linear {
+            Self::blend_linear(under, over, alpha)
+        } else {
+            Self::blend(under, over, alpha)
+        })
+    }
+
+    /// Writes the image into `buf` as `0RGB` pixels, the layout `minifb`
+    /// expects.
+    pub fn encode(&self, buf: &mut [u32]) {
+        let mut buf = buf.iter_mut();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let [r, g, b] = self.color_at([x, y]);
+                *buf.next().unwrap() = u32::from_be_bytes([0, r, g, b]);
+            }
+        }
+    }
+
+    /// Writes the pixels that changed since the last call into `buf` like
+    /// [`Image::encode`], leaving the rest of `buf` as it was; the first call
+    /// writes every pixel.
+    ///
+    /// Keeping a window up to date this way only costs as much as the shapes
+    /// drawn in between, instead of a pass over the whole image.
+    pub fn encode_changes(&mut self, buf: &mut [u32]) {
+        for y in 0..self.height {
+            let [start, end] = std::mem::replace(&mut self.changed[y as usize], [self.width, 0]);
+            let row = (y * self.width) as usize;
+
+            for x in start..end {
+                let offset = self.index([x, y]);
+                let pixel = &self.pixels[offset..offset + self.channels];
+
+                buf[row + x as usize] = match *pixel {
+                    [luma] => u32::from_be_bytes([0, luma, luma, luma]),
+                    [r, g, b] => u32::from_be_bytes([0, r, g, b]),
+                    _ => unreachable!("images have one or three channels"),
+                };
+            }
+        }
+    }
+
+    /// Returns the color at `point`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `point` lies outside of the image; use
+    /// [`Image::color_at_checked`] for points that might.
+    pub fn color_at(&self, point: Point) -> Color {
+        self.color_at_offset(self.offset(point))
+    }
+
+    /// Returns the color at `point` like [`Image::color_at`], without
+    /// checking that it lies inside the image, for the hot paths whose
+    /// points were already clipped to it.
+    pub(crate) fn pixel(&self, point: Point) -> Color {
+        self.color_at_offset(self.index(point))
+    }
+
+    /// Returns the color of the pixel that starts at `offset` in `pixels`.
+    fn color_at_offset(&self, offset: usize) -> Color {
+        if self.is_grayscale() {
+            [self.pixels[offset]; 3]
+        } else {
+            self.pixels[offset..][..3].try_into().unwrap()
+        }
+    }
+
+    /// Returns the color at `point`, or `None` if it lies outside of the
+    /// image.
+    pub fn color_at_checked(&self, point: Point) -> Option<Color> {
+        self.contains(point).then(|| self.color_at(point))
+    }
+
+    /// Returns whether `point` lies within the image.
+    pub fn contains(&self, [x, y]: Point) -> bool {
+        x < self.width && y < self.height
+    }
+
+    /// Returns `color` as this image would store it.
+    pub(crate) fn quantize(&self, color: Color) -> Color {
+        if self.is_grayscale() {
+            [luminance(color); 3]
+        } else {
+            color
+        }
+    }
+
+    /// Stores `color`, which must already be [quantized](Image::quantize).
+    fn set_color(&mut self, point: Point, color: Color) {
+        self.recolor(point, color);
+
+        let index = self.index(point) / self.channels;
+        self.touched[index] = true;
+    }
+
+    /// Stores `color` like [`Image::set_color`], without counting the pixel
+    /// as drawn onto, for passes over a finished image like [`dither`].
+    pub(crate) fn recolor(&mut self, point: Point, color: Color) {
+        let offset = self.index(point);
+
+        if self.is_grayscale() {
+            self.pixels[offset] = color[0];
+        } else {
+            self.pixels[offset..][..3].copy_from_slice(&color);
+        }
+
+        self.mark_changed(point, 1);
+    }
+
+    /// Returns whether anything has been drawn onto the pixel at `point`
+    /// since the image was made: a shape, through [`Image::apply`] or
+    /// otherwise, or a frozen pixel copied from a [masked](Image::set_mask)
+    /// target. Pixels shapes were drawn onto stay touched even if the
+    /// shapes are removed again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `point` lies outside of the image.
+    pub fn is_touched(&self, point: Point) -> bool {
+        self.touched[self.offset(point) / self.channels]
+    }
+
+    /// Marks the pixels at `points` as drawn onto, without changing them.
+    pub(crate) fn touch(&mut self, points: impl IntoIterator<Item = Point>) {
+        for point in points {
+            let index = self.index(point) / self.channels;
+            self.touched[index] = true;
+        }
+    }
+
+    /// Returns the image with an alpha channel, where the pixels nothing was
+    /// drawn onto (see [`Image::is_touched`]) are fully transparent and the
+    /// rest fully opaque.
+    pub fn transparent_background(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let [r, g, b] = self.color_at([x, y]);
+            let alpha = if self.is_touched([x, y]) { 255 } else { 0 };
+
+            [r, g, b, alpha].into()
+        })
+    }
+
+    /// Records that `width` pixels starting at `point` changed, for
+    /// [`Image::encode_changes`].
+    fn mark_changed(&mut self, [x, y]: Point, width: u32) {
+        let changed = &mut self.changed[y as usize];
+        changed[0] = changed[0].min(x);
+        changed[1] = changed[1].max(x + width);
+    }
+
+    fn offset(&self, point: Point) -> usize {
+        // Without this, an out-of-range x silently wraps onto the next row
+        assert!(
+            self.contains(point),
+            "point {:?} is outside of the {}x{} image",
+            point,
+            self.width,
+            self.height
+        );
+
+        self.index(point)
+    }
+
+    /// Returns where the pixel at `point`, which must lie inside the image,
+    /// starts in `pixels`.
+    fn index(&self, [x, y]: Point) -> usize {
+        let index = if cfg!(feature = "morton") {
+            morton::index(self.width, self.height, [x, y])
+        } else {
+            y as usize * self.width as usize + x as usize
+        };
+
+        index * self.channels
+    }
+}
+
+/// Returns how many pixels a `width`x`height` image stores, which is more
+/// than it has in the Z-order layout of the `morton` feature.
+fn stored_len(width: u32, height: u32) -> usize {
+    if cfg!(feature = "morton") {
+        morton::len(width, height)
+    } else {
+        width as usize * height as usize
+    }
+}
+
+/// Returns the change spans of a `width`x`height` image whose pixels all
+/// still have to be encoded.
+fn all_changed(width: u32, height: u32) -> Vec<[u32; 2]> {
+    vec![[0, width]; height as usize]
+}
+
+/// Returns the luminance of `color`, with the same weights `image` uses when
+/// converting to grayscale.
+fn luminance([r, g, b]: Color) -> u8 {
+    (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32).round() as u8
+}
+
+impl From<RgbImage> for Image {
+    fn from(img: RgbImage) -> Self {
+        let (width, height) = img.dimensions();
+        Self::from_rows(width, height, 3, img.into_raw())
+    }
+}
+
+impl From<GrayImage> for Image {
+    fn from(img: GrayImage) -> Self {
+        let (width, height) = img.dimensions();
+        Self::from_rows(width, height, 1, img.into_raw())
+    }
+}
+
+impl From<DynamicImage> for Image {
+    /// Keeps grayscale images grayscale and turns everything else into RGB,
+    /// with 8 bits per channel; transparency is dropped, so [`flatten`]
+    /// images with it first.
+    fn from(img: DynamicImage) -> Self {
+        if img.color().has_color() {
+            Image::from(img.into_rgb8())
+        } else {
+            Image::from(img.into_luma8())
+        }
+    }
+}
+
+impl From<&Image> for RgbImage {
+    fn from(img: &Image) -> Self {
+        let pixels = if img.is_grayscale() {
+            img.rows().into_iter().flat_map(|luma| [luma; 3]).collect()
+        } else {
+            img.rows()
+        };
+
+        RgbImage::from_raw(img.width, img.height, pixels)
+            .expect("pixel buffer matches the image dimensions")
+    }
+}
+
+impl From<&Image> for DynamicImage {
+    /// Keeps grayscale images grayscale, unlike converting to an [`RgbImage`].
+    fn from(img: &Image) -> Self {
+        if img.is_grayscale() {
+            let pixels = GrayImage::from_raw(img.width, img.height, img.rows())
+                .expect("pixel buffer matches the image dimensions");
+
+            DynamicImage::ImageLuma8(pixels)
+        } else {
+            DynamicImage::ImageRgb8(img.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use rand_chacha::ChaCha12Rng;
+
+    /// Returns a `width`x`height` image whose pixels all differ.
+    fn pattern(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| [x as u8, y as u8, (x * 7 + y * 3) as u8].into())
+    }
+
+    /// Returns the summed loss of every pixel of `approx` against `target`.
+    fn summed_loss(target: &Image, approx: &Image) -> f64 {
+        (0..target.height)
+            .flat_map(|y| (0..target.width).map(move |x| [x, y]))
+            .map(|point| Image::pixel_loss(target.color_at(point), approx.color_at(point)) as f64)
+            .sum()
+    }
+
+    #[test]
+    fn disks_converge_faster_than_outlines() {
+        let target = Image::from(RgbImage::from_fn(60, 40, |x, y| {
+            [x as u8 * 4, y as u8 * 6, 128].into()
+        }));
+        let loss_after = |fill| {
+            let config = Config {
+                fill,
+                ..Config::default()
+            };
+            let mut rng = StdRng::seed_from_u64(1);
+            summed_loss(&target, &approximate(&target, &config, 500, &mut rng))
+        };
+
+        assert!(loss_after(true) < loss_after(false));
+    }
+
+    #[test]
+    fn off_canvas_points_have_no_color() {
+        let image = Image::from(pattern(37, 23));
+
+        assert_eq!(image.color_at_checked([36, 22]), Some(image.color_at([36, 22])));
+
+        for point in [[37, 0], [0, 23], [37, 23], [u32::MAX, u32::MAX]] {
+            assert!(!image.contains(point));
+            assert_eq!(image.color_at_checked(point), None, "at {point:?}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of the 37x23 image")]
+    fn off_canvas_color_panics() {
+        // Would wrap onto the start of the next row
+        Image::from(pattern(37, 23)).color_at([37, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of the 37x23 image")]
+    fn off_canvas_touch_checks_panic() {
+        Image::from(pattern(37, 23)).is_touched([37, 0]);
+    }
+
+    #[test]
+    fn transparent_pixels_are_composited_over_the_background() {
+        let alphas = [0, 64, 128, 255];
+        let red = RgbaImage::from_fn(4, 1, |x, _| [255, 0, 0, alphas[x as usize]].into());
+        let over = |background| -> Vec<Color> {
+            flatten(&red, background).pixels().map(|pixel| pixel.0).collect()
+        };
+
+        let (over_black, over_white) = (over([0, 0, 0]), over([255, 255, 255]));
+        assert_eq!(over_black, [[0, 0, 0], [64, 0, 0], [128, 0, 0], [255, 0, 0]]);
+        assert_eq!(over_white, [[255, 255, 255], [255, 191, 191], [255, 127, 127], [255, 0, 0]]);
+    }
+
+    #[test]
+    fn only_the_median_keeps_the_majority_color() {
+        let (dark, light): (Color, Color) = ([20, 40, 120], [255; 3]);
+        let target = Image::from(RgbImage::from_fn(60, 40, |x, _| {
+            if x < 20 { dark } else { light }.into()
+        }));
+
+        // Mostly on the dark side of the edge
+        let disk = generate_filled_circle_points(16, 20, 16);
+        let dark_pixels = disk.iter().filter(|&&[x, _]| x < 20).count();
+        let light_pixels = disk.len() - dark_pixels;
+        assert!(dark_pixels > light_pixels);
+
+        let expected_mean = [0, 1, 2].map(|i| {
+            let sum = dark_pixels * dark[i] as usize + light_pixels * light[i] as usize;
+            (sum as f64 / disk.len() as f64).round() as u8
+        });
+        assert_eq!(mean_color(&target, &disk, false), expected_mean);
+        assert_eq!(median_color(&target, &disk), dark);
+
+        // Blends the center with the outline, so it mixes in both too
+        let weighted = calculate_weighted_color(&target, 16, 20, 16, 16, &disk, false);
+        for i in 0..3 {
+            assert!(dark[i] < weighted[i] && weighted[i] < light[i], "{weighted:?}");
+        }
+    }
+
+    #[test]
+    fn optimal_colors_are_never_worse_than_weighted_ones() {
+        let target = Image::from(RgbImage::from_fn(48, 32, |x, y| {
+            [(x * 5) as u8, (y * 8) as u8, (x * y) as u8].into()
+        }));
+        let config = |color_strategy| Config {
+            fill: true,
+            color_strategy,
+            ..Config::default()
+        };
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let approx = approximate(&target, &config(ColorStrategy::Weighted), 200, &mut rng);
+
+        for seed in 0..200 {
+            // The same seed places the same shape, only the color differs
+            let sample_with = |color_strategy| {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let (placement, scratch) = (Placement::default(), &mut Scratch::default());
+                sample(&target, &approx, &config(color_strategy), placement, scratch, &mut rng)
+            };
+
+            let weighted = sample_with(ColorStrategy::Weighted).unwrap();
+            let optimal = sample_with(ColorStrategy::Optimal).unwrap();
+
+            assert!(
+                optimal.loss_delta <= weighted.loss_delta,
+                "seed {seed}: {} > {}",
+                optimal.loss_delta,
+                weighted.loss_delta
+            );
+        }
+    }
+
+    #[test]
+    fn saved_images_only_use_the_palette() {
+        let palette = vec![[0, 0, 0], [255, 255, 255], [200, 30, 40], [20, 90, 200]];
+        let target = Image::from(RgbImage::from_fn(48, 32, |x, y| {
+            [x as u8 * 5, y as u8 * 8, 100].into()
+        }));
+        // The average background gets snapped too
+        let config = Config {
+            fill: true,
+            init: Init::Average,
+            palette: palette.clone(),
+            ..Config::default()
+        };
+
+        let approx = approximate(&target, &config, 500, &mut StdRng::seed_from_u64(1));
+        let path = std::env::temp_dir().join(format!("circlez-palette-{}.png", std::process::id()));
+        RgbImage::from(&approx).save(&path).unwrap();
+        let saved = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_file(path).unwrap();
+
+        for (x, y, pixel) in saved.enumerate_pixels() {
+            assert!(palette.contains(&pixel.0), "{:?} at {x},{y}", pixel.0);
+        }
+    }
+
+    #[test]
+    fn pixels_read_back_row_by_row() {
+        for (width, height) in [(1, 1), (37, 23), (23, 37), (64, 5), (3, 129)] {
+            let rows = pattern(width, height);
+            let image = Image::from(rows.clone());
+
+            for (x, y, pixel) in rows.enumerate_pixels() {
+                assert_eq!(image.color_at([x, y]), pixel.0, "at {x},{y} of {width}x{height}");
+            }
+
+            assert_eq!(image.rows(), rows.into_raw());
+        }
+    }
+
+    #[test]
+    fn importance_map_is_row_by_row() {
+        let (width, height) = (37, 23);
+        let mut target = Image::from(pattern(width, height));
+        let map = GrayImage::from_fn(width, height, |x, y| [(x * 5 + y * 11) as u8].into());
+        target.set_importance_map(&map).unwrap();
+
+        let expected: f64 = pattern(width, height)
+            .enumerate_pixels()
+            .map(|(x, y, pixel)| {
+                let weight = map.get_pixel(x, y).0[0] as f32 / 255.0;
+                (Image::pixel_loss(pixel.0, [0; 3]) * weight) as f64
+            })
+            .sum();
+
+        let approx = Image::blank(&target);
+        assert!((approx.total_loss() - expected).abs() <= 1e-6 * expected);
+
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        let result = approximate(&target, &Config::default(), 200, &mut rng);
+        assert!(result.total_loss() < expected);
+    }
+
+    #[test]
+    fn duotone_colors_lie_on_the_gradient() {
+        let duotone = [[20, 30, 80], [250, 240, 200]];
+        let target = Image::from(RgbImage::from_fn(48, 32, |x, y| {
+            [x as u8 * 5, y as u8 * 8, 100].into()
+        }));
+        let config = Config {
+            fill: true,
+            init: Init::Average,
+            duotone: Some(duotone),
+            ..Config::default()
+        };
+
+        let approx = approximate(&target, &config, 500, &mut StdRng::seed_from_u64(1));
+        let [dark, light] = duotone;
+
+        for (x, y, pixel) in RgbImage::from(&approx).enumerate_pixels() {
+            // The closest point on the gradient, only off by rounding
+            let t = gradient_position(duotone, pixel.0).clamp(0.0, 1.0);
+
+            for c in 0..3 {
+                let on_gradient = dark[c] as f32 + (light[c] as f32 - dark[c] as f32) * t;
+                let off = (pixel.0[c] as f32 - on_gradient).abs();
+                assert!(off <= 0.6, "{:?} at {x},{y} is {off} off", pixel.0);
+            }
+        }
+    }
+}
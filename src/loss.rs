@@ -0,0 +1,433 @@
+use crate::{Color, Image, Point, Ssim};
+use clap::ValueEnum;
+use rayon::prelude::*;
+use std::sync::OnceLock;
+use wide::f32x8;
+
+/// How many pixels the default [`Loss::total`] and [`Loss::delta`] handle
+/// per parallel job; fewer changes than this are summed on the calling
+/// thread.
+const PARALLEL_PIXELS: usize = 4096;
+
+/// A measure of how different two colors are, which the approximation tries
+/// to minimize.
+///
+/// Implementations must be non-negative and return `0.0` for equal colors.
+///
+/// By default the loss of an image is the sum of the losses of its pixels,
+/// weighted by the target's importance map; losses that look at whole
+/// neighbourhoods instead override [`Loss::total`] and [`Loss::delta`].
+///
+/// Large sums are split into fixed chunks that are evaluated in parallel
+/// and added up in order, so they come out the same however the work is
+/// scheduled.
+pub trait Loss: Sync {
+    /// Returns the loss between two colors.
+    fn pixel(&self, a: Color, b: Color) -> f32;
+
+    /// Returns the loss between two gray levels, as used for grayscale
+    /// images; override it when there's something cheaper than comparing
+    /// them as colors.
+    fn gray(&self, a: u8, b: u8) -> f32 {
+        self.pixel([a; 3], [b; 3])
+    }
+
+    /// Returns the loss of `approx` against `target`.
+    fn total(&self, target: &Image, approx: &Image) -> f64 {
+        let rows = (PARALLEL_PIXELS / approx.width.max(1) as usize).max(1);
+
+        let chunks: Vec<f64> = (0..approx.height)
+            .collect::<Vec<_>>()
+            .par_chunks(rows)
+            .map(|rows| {
+                rows.iter()
+                    .flat_map(|&y| (0..approx.width).map(move |x| [x, y]))
+                    .map(|pos| target.loss(self, pos, approx.pixel(pos)) as f64)
+                    .sum()
+            })
+            .collect();
+
+        chunks.into_iter().sum()
+    }
+
+    /// Returns how much the loss of `approx` against `target` would change
+    /// if the pixels in `changes` were set to the given colors; each pixel
+    /// appears at most once.
+    fn delta(&self, target: &Image, approx: &Image, changes: &[(Point, Color)]) -> f64 {
+        sum_chunks(changes, |changes| {
+            changes
+                .iter()
+                .map(|&(pos, color)| pixel_delta(self, target, approx, pos, color))
+                .sum()
+        })
+    }
+}
+
+/// Returns how much the loss at `pos` changes when `approx` gets `color`
+/// there.
+fn pixel_delta<L: Loss + ?Sized>(
+    loss: &L,
+    target: &Image,
+    approx: &Image,
+    pos: Point,
+    color: Color,
+) -> f64 {
+    target.loss(loss, pos, color) as f64 - target.loss(loss, pos, approx.pixel(pos)) as f64
+}
+
+/// Adds up `delta` over `changes`, in parallel chunks if there are enough of
+/// them.
+fn sum_chunks(
+    changes: &[(Point, Color)],
+    delta: impl Fn(&[(Point, Color)]) -> f64 + Send + Sync,
+) -> f64 {
+    if changes.len() < PARALLEL_PIXELS {
+        return delta(changes);
+    }
+
+    let chunks: Vec<f64> = changes.par_chunks(PARALLEL_PIXELS).map(delta).collect();
+    chunks.into_iter().sum()
+}
+
+/// The squared distance between the RGB channels; see
+/// [`Image::pixel_loss`].
+///
+/// [`Loss::delta`] handles eight pixels at a time with SIMD instructions,
+/// where the target has them, and gives exactly the same result as one
+/// pixel at a time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SquaredError;
+
+impl Loss for SquaredError {
+    fn pixel(&self, a: Color, b: Color) -> f32 {
+        Image::pixel_loss(a, b)
+    }
+
+    fn gray(&self, a: u8, b: u8) -> f32 {
+        (a as f32 - b as f32).powi(2)
+    }
+
+    fn delta(&self, target: &Image, approx: &Image, changes: &[(Point, Color)]) -> f64 {
+        sum_chunks(changes, |changes| squared_error_delta(target, approx, changes))
+    }
+}
+
+/// Sums how much the squared error changes over `changes`, eight pixels at a
+/// time.
+///
+/// Every lane does the same operations in the same order as
+/// [`SquaredError::pixel`] followed by the importance weighting, and lanes
+/// are subtracted and added up in order in `f64`, so the result is bit for
+/// bit the scalar one.
+fn squared_error_delta(target: &Image, approx: &Image, changes: &[(Point, Color)]) -> f64 {
+    let channels = if target.is_grayscale() { 1 } else { 3 };
+
+    let mut total = 0.0;
+    let mut chunks = changes.chunks_exact(8);
+
+    for chunk in &mut chunks {
+        let mut wanted = [[0.0; 8]; 3];
+        let mut old = [[0.0; 8]; 3];
+        let mut new = [[0.0; 8]; 3];
+        let mut weights = [1.0; 8];
+
+        for (lane, &(pos, color)) in chunk.iter().enumerate() {
+            let (target_color, approx_color) = (target.pixel(pos), approx.pixel(pos));
+
+            for channel in 0..channels {
+                wanted[channel][lane] = target_color[channel] as f32;
+                old[channel][lane] = approx_color[channel] as f32;
+                new[channel][lane] = color[channel] as f32;
+            }
+
+            if let Some(importance) = target.importance_at(pos) {
+                weights[lane] = importance;
+            }
+        }
+
+        let (mut old_loss, mut new_loss) = (f32x8::ZERO, f32x8::ZERO);
+
+        for channel in 0..channels {
+            let wanted = f32x8::from(wanted[channel]);
+            let old = wanted - f32x8::from(old[channel]);
+            let new = wanted - f32x8::from(new[channel]);
+
+            old_loss += old * old;
+            new_loss += new * new;
+        }
+
+        let weights = f32x8::from(weights);
+        let (old_loss, new_loss) = ((old_loss * weights).to_array(), (new_loss * weights).to_array());
+
+        for lane in 0..8 {
+            total += new_loss[lane] as f64 - old_loss[lane] as f64;
+        }
+    }
+
+    for &(pos, color) in chunks.remainder() {
+        total += pixel_delta(&SquaredError, target, approx, pos, color);
+    }
+
+    total
+}
+
+/// The sum of the absolute differences between the RGB channels, which
+/// penalizes outliers less than [`SquaredError`] and so keeps high-contrast
+/// regions crisper.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AbsoluteError;
+
+impl Loss for AbsoluteError {
+    fn pixel(&self, a: Color, b: Color) -> f32 {
+        a.into_iter()
+            .zip(b)
+            .map(|(a, b)| (a as f32 - b as f32).abs())
+            .sum()
+    }
+
+    fn gray(&self, a: u8, b: u8) -> f32 {
+        (a as f32 - b as f32).abs()
+    }
+}
+
+/// The CIE76 color difference, the distance between the colors in CIELAB
+/// space, which tracks perceived differences more closely.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeltaE;
+
+impl Loss for DeltaE {
+    fn pixel(&self, a: Color, b: Color) -> f32 {
+        let [a, b] = [lab(a), lab(b)];
+        (0..3).map(|i| (a[i] - b[i]).powi(2)).sum::<f32>().sqrt()
+    }
+
+    fn gray(&self, a: u8, b: u8) -> f32 {
+        (lightness(linear(a)) - lightness(linear(b))).abs()
+    }
+}
+
+/// [`SquaredError`] between the colors in linear light, scaled back up to
+/// `0..=255` so it's in the same units; see
+/// [`Config::linear`](crate::Config::linear).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LinearSquaredError;
+
+impl Loss for LinearSquaredError {
+    fn pixel(&self, a: Color, b: Color) -> f32 {
+        (0..3).map(|i| self.gray(a[i], b[i])).sum()
+    }
+
+    fn gray(&self, a: u8, b: u8) -> f32 {
+        ((linear(a) - linear(b)) * 255.0).powi(2)
+    }
+}
+
+/// [`AbsoluteError`] between the colors in linear light, like
+/// [`LinearSquaredError`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LinearAbsoluteError;
+
+impl Loss for LinearAbsoluteError {
+    fn pixel(&self, a: Color, b: Color) -> f32 {
+        (0..3).map(|i| self.gray(a[i], b[i])).sum()
+    }
+
+    fn gray(&self, a: u8, b: u8) -> f32 {
+        ((linear(a) - linear(b)) * 255.0).abs()
+    }
+}
+
+/// The built-in [`Loss`]es, for picking one from a [`Config`](crate::Config)
+/// or the command line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LossKind {
+    /// [`SquaredError`].
+    #[default]
+    #[value(name = "l2")]
+    SquaredError,
+    /// [`AbsoluteError`].
+    #[value(name = "l1")]
+    AbsoluteError,
+    /// [`DeltaE`].
+    DeltaE,
+    /// [`Ssim`].
+    Ssim,
+}
+
+impl LossKind {
+    /// Returns the loss of this kind.
+    pub fn metric(self) -> &'static dyn Loss {
+        match self {
+            LossKind::SquaredError => &SquaredError,
+            LossKind::AbsoluteError => &AbsoluteError,
+            LossKind::DeltaE => &DeltaE,
+            LossKind::Ssim => &Ssim,
+        }
+    }
+
+    /// Returns the loss of this kind between colors in linear light:
+    /// [`LinearSquaredError`] and [`LinearAbsoluteError`] for the
+    /// differences of channels. [`DeltaE`] already goes through linear light
+    /// on its way to CIELAB, and [`Ssim`] compares the structure people see,
+    /// so both stay as they are.
+    pub fn linear_metric(self) -> &'static dyn Loss {
+        match self {
+            LossKind::SquaredError => &LinearSquaredError,
+            LossKind::AbsoluteError => &LinearAbsoluteError,
+            LossKind::DeltaE | LossKind::Ssim => self.metric(),
+        }
+    }
+}
+
+/// Converts an sRGB channel to linear light, from `0.0` to `1.0`.
+pub(crate) fn linear(channel: u8) -> f32 {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+
+    let table = TABLE.get_or_init(|| {
+        std::array::from_fn(|i| {
+            let c = i as f32 / 255.0;
+
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    });
+
+    table[channel as usize]
+}
+
+/// Converts linear light, from `0.0` to `1.0`, back to an sRGB channel;
+/// every channel survives the round trip through [`linear`].
+pub(crate) fn srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+
+    let c = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+
+    (c * 255.0).round() as u8
+}
+
+/// The CIELAB companding function, relative to the D65 white point.
+fn lab_f(t: f32) -> f32 {
+    if t > (6.0f32 / 29.0).powi(3) {
+        t.cbrt()
+    } else {
+        t / (3.0 * (6.0f32 / 29.0).powi(2)) + 4.0 / 29.0
+    }
+}
+
+/// Returns the CIELAB lightness of a relative luminance `y`.
+fn lightness(y: f32) -> f32 {
+    116.0 * lab_f(y) - 16.0
+}
+
+/// Converts an sRGB color to CIELAB under the D65 white point.
+fn lab([r, g, b]: Color) -> [f32; 3] {
+    let [r, g, b] = [linear(r), linear(g), linear(b)];
+
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.950_47;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.088_83;
+
+    let [fx, fy, fz] = [lab_f(x), lab_f(y), lab_f(z)];
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn squared_error_known_values() {
+        assert_eq!(SquaredError.pixel([10, 20, 30], [10, 20, 30]), 0.0);
+        assert_eq!(SquaredError.pixel([0, 0, 0], [3, 4, 0]), 25.0);
+        assert_eq!(SquaredError.pixel([255, 0, 255], [0, 255, 0]), 3.0 * 255.0 * 255.0);
+        assert_eq!(SquaredError.gray(10, 13), 9.0);
+    }
+
+    #[test]
+    fn delta_e_known_values() {
+        let close = |a: f32, b: f32| (a - b).abs() < 0.05;
+
+        assert_eq!(DeltaE.pixel([10, 20, 30], [10, 20, 30]), 0.0);
+        // Black and white are L* 0 and 100, without any a* or b*
+        assert!(close(DeltaE.pixel([0, 0, 0], [255, 255, 255]), 100.0));
+        assert!(close(DeltaE.gray(0, 255), 100.0));
+        // sRGB red is (53.24, 80.09, 67.20) in CIELAB
+        assert!(close(DeltaE.pixel([0, 0, 0], [255, 0, 0]), 117.32));
+    }
+
+    #[test]
+    fn absolute_error_known_values() {
+        assert_eq!(AbsoluteError.pixel([10, 20, 30], [10, 20, 30]), 0.0);
+        assert_eq!(AbsoluteError.pixel([10, 20, 30], [13, 16, 30]), 7.0);
+        assert_eq!(AbsoluteError.pixel([255, 0, 255], [0, 255, 0]), 3.0 * 255.0);
+        assert_eq!(AbsoluteError.gray(13, 10), 3.0);
+    }
+
+    #[test]
+    fn l1_and_l2_prefer_different_shapes() {
+        // A white stripe on black, under a white canvas
+        let target = Image::from(RgbImage::from_fn(9, 9, |x, _| {
+            if (3..6).contains(&x) { [255; 3] } else { [0; 3] }.into()
+        }));
+        let approx = Image::from(RgbImage::from_pixel(9, 9, [255; 3].into()));
+
+        let disk: Vec<Point> = crate::generate_filled_circle_points(4, 4, 4)
+            .into_iter()
+            .map(|[x, y]| [x as u32, y as u32])
+            .collect();
+        let mean = disk.iter().map(|&pos| target.color_at(pos)[0] as u32).sum::<u32>()
+            / disk.len() as u32;
+
+        // The mean of the pixels under the disk, and their median
+        let gray: Vec<_> = disk.iter().map(|&pos| (pos, [mean as u8; 3], 1.0)).collect();
+        let black: Vec<_> = disk.iter().map(|&pos| (pos, [0; 3], 1.0)).collect();
+
+        assert!(
+            Image::loss_delta(&target, &approx, &gray, &SquaredError)
+                < Image::loss_delta(&target, &approx, &black, &SquaredError)
+        );
+
+        assert!(
+            Image::loss_delta(&target, &approx, &black, &AbsoluteError)
+                < Image::loss_delta(&target, &approx, &gray, &AbsoluteError)
+        );
+    }
+
+    #[test]
+    fn large_shapes_are_summed_exactly() {
+        // A 1400 pixel shape that makes 200 pixels as bad as can be, fixes
+        // 200 others and moves the rest a step away: worse, by 1000
+        let (width, height) = (50, 28);
+        let kind = |[x, y]: Point| match y * width + x {
+            ..200 => ([0; 3], [255; 3]),
+            200..1200 => ([0; 3], [1, 0, 0]),
+            _ => ([255; 3], [255; 3]),
+        };
+
+        let target = Image::from(RgbImage::from_fn(width, height, |x, y| kind([x, y]).0.into()));
+        let approx = Image::blank(&target);
+        let changes: Vec<_> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| [x, y]))
+            .map(|pos| (pos, kind(pos).1, 1.0))
+            .collect();
+
+        // Summed in f32, the small steps vanish next to the large terms and
+        // the shape looks like a slight improvement
+        let rounded = changes.iter().fold(0.0f32, |total, &(pos, color, _)| {
+            let wanted = target.color_at(pos);
+            total + SquaredError.pixel(wanted, color) - SquaredError.pixel(wanted, [0; 3])
+        });
+        assert!(rounded < 0.0, "{rounded}");
+
+        assert_eq!(Image::loss_delta(&target, &approx, &changes, &SquaredError), 1000.0);
+    }
+}
@@ -1,271 +1,2811 @@
-use clap::Parser;
-use image::{ImageReader, RgbImage};
-use minifb::{Key, Window, WindowOptions};
-use rand::{random_range};
+use circlez::{CirclezError, Checkpoint, Color, ColorStrategy, Config, Image, Init, LossKind, RadiusSchedule, Recording, ShapeKind, Symmetry};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::imageops::{self, FilterType};
+use image::{AnimationDecoder, Delay, DynamicImage, Frame, ImageError, ImageFormat, ImageReader, ImageResult, RgbaImage};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info};
+#[cfg(feature = "gui")]
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use std::ffi::OsString;
+use std::fs::File;
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, BufWriter, Cursor, Read, Seek, Write};
 use std::path::{PathBuf, Path};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 
-#[derive(Parser)]
+/// The approximator runs use: `StdRng` is ChaCha12 as well, so seeds give
+/// the same results as with the library's default, but only this one can
+/// save its state into checkpoints.
+type Approximator = circlez::Approximator<ChaCha12Rng>;
+
+#[derive(Clone, Parser)]
+#[clap(group(
+    clap::ArgGroup::new("palettes")
+        .multiple(true)
+        .args(["palette", "palette_file", "auto_palette", "duotone"])
+))]
 struct Args {
-    target: PathBuf,
+    /// The image to approximate, or `-` to read it from standard input;
+    /// several images, a directory or a glob like `photos/*.jpg` are all
+    /// approximated headless, a few at once (see `--jobs`)
+    #[clap(required_unless_present = "replay")]
+    target: Vec<PathBuf>,
+
+    /// Redraw the shapes saved with `--shapes-json` instead of approximating
+    /// a target
+    #[clap(long, conflicts_with = "target")]
+    replay: Option<PathBuf>,
+
+    /// Approximate every frame of an animated GIF target with `--iterations`
+    /// ticks, headless, and save the frames as an animated GIF with the same
+    /// timing
+    #[clap(long)]
+    animate: bool,
+
+    /// With `--animate`, start each frame from the approximation of the one
+    /// before instead of from scratch, so that only what changed gets
+    /// redrawn; frames are then approximated one at a time
+    #[clap(long, requires = "animate", conflicts_with = "shapes")]
+    warm_start: bool,
+
+    /// Read settings from a TOML file, like `iterations = 100000` or
+    /// `shape = "rect"`, for any option given on the command line
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Save every setting of the run to a TOML file `--config` can read, for
+    /// repeating it later
+    #[clap(long)]
+    dump_config: Option<PathBuf>,
 
     #[clap(short, long, default_value = "4096")]
     iterations: usize,
+
+    /// Keep going until this many shapes have been drawn, instead of for
+    /// `--iterations` ticks, giving up after 1000 ticks per shape
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    shapes: Option<u64>,
+
+    /// Stop early once the total loss drops below this, as shown by
+    /// `--progress`
+    #[clap(long)]
+    target_loss: Option<f64>,
+
+    /// Stop early after this many ticks in a row without lowering the loss
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    stall: Option<u64>,
+
+    /// Stop early after running for this many seconds, saving the result so
+    /// far; whichever of the stopping conditions comes first ends the run
+    #[clap(long, value_parser = parse_seconds)]
+    max_time: Option<Duration>,
+
+    /// Downscale the target, preserving its aspect ratio, so neither side
+    /// exceeds this many pixels
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    max_dimension: Option<u32>,
+
+    /// Brighten high dynamic range targets (OpenEXR or Radiance HDR) by
+    /// this many stops, or darken them if negative, before tone-mapping
+    /// them to 8 bits
+    #[clap(long, default_value = "0", allow_negative_numbers = true, value_parser = parse_exposure)]
+    exposure: f32,
+
+    /// Lay down the structure of the image on smaller copies of the target
+    /// first: halve its size this many times minus one and spend an equal
+    /// share of `--iterations` on each size, from the smallest up to the
+    /// full one
+    #[clap(
+        long,
+        conflicts_with_all = ["shapes", "resume"],
+        value_parser = clap::value_parser!(u32).range(1..=8)
+    )]
+    pyramid: Option<u32>,
+
+    /// Run `iterations` ticks without opening a window, then save and exit
+    #[clap(long)]
+    headless: bool,
+
+    /// Redraw the window every this many ticks rather than after every
+    /// `--iterations` ticks, though at most about 60 times a second
+    #[clap(long, conflicts_with = "headless", value_parser = clap::value_parser!(u64).range(1..))]
+    redraw_every: Option<u64>,
+
+    /// Show the image this many times its size in the window, like 0.5 for
+    /// half; the approximation keeps its full resolution [default: as large
+    /// as fits in 1600x900, but at most 1]
+    #[clap(long, conflicts_with = "headless", value_parser = parse_window_scale)]
+    window_scale: Option<f32>,
+
+    /// Split the canvas into tiles and approximate them on this many threads
+    /// at once, or `auto` (or 0) for one per CPU; results depend on the
+    /// count, so pass a number to reproduce runs on other machines
+    #[clap(long, default_value = "1", value_parser = parse_threads)]
+    threads: u64,
+
+    /// How many images (or frames, with `--animate`) to approximate at once
+    /// when given several targets, each on `--threads` threads of its own
+    /// [default: as many as fit on the CPUs]
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
+
+    /// Seed for the random number generator, for reproducible runs
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Where to save the result, or `-` to write it to standard output; the
+    /// format is picked from the extension, or is PNG for `-`; with several
+    /// targets, the directory to save `<target>_circlez.jpg` files to
+    /// [default: generated_images/<target>_circlez.jpg]
+    #[clap(short, long)]
+    output: Option<PathBuf>,
+
+    /// Save the result this many times larger, by drawing the shapes again
+    /// at that size rather than scaling up the pixels, for crisp prints;
+    /// rings and lines get thicker to match
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..=64))]
+    upscale: u32,
+
+    /// Also save the placed shapes as an SVG, for printing at any size
+    #[clap(long)]
+    svg: Option<PathBuf>,
+
+    /// Also save a heatmap of where the result differs from the target, from
+    /// blue (matching) to red (the largest difference)
+    #[clap(long)]
+    diff: Option<PathBuf>,
+
+    /// Also save the target and the result side by side, for sharing
+    #[clap(long)]
+    compare: Option<PathBuf>,
+
+    /// Also save the placed shapes, with the tick each was accepted in, as
+    /// JSON
+    #[clap(long)]
+    shapes_json: Option<PathBuf>,
+
+    /// Also record how the image forms as an animated GIF
+    #[clap(long)]
+    gif: Option<PathBuf>,
+
+    /// Ticks between GIF frames; doubled whenever `--gif-frames` is reached,
+    /// so long runs are still sampled evenly
+    #[clap(long, default_value = "256", value_parser = clap::value_parser!(u64).range(1..))]
+    gif_every: u64,
+
+    /// How long each GIF frame is shown, in milliseconds
+    #[clap(long, default_value = "50")]
+    gif_delay: u32,
+
+    /// Most frames to keep in memory for the GIF
+    #[clap(long, default_value = "100", value_parser = clap::value_parser!(u64).range(2..))]
+    gif_frames: u64,
+
+    /// Save a numbered PNG of the approximation every this many redraws
+    /// (ticks with `--headless`) into `--frames-dir`
+    #[clap(long, requires = "frames_dir", value_parser = clap::value_parser!(u64).range(1..))]
+    save_every: Option<u64>,
+
+    /// Directory `--save-every` writes `frame_00001.png` and so on to
+    #[clap(long, requires = "save_every")]
+    frames_dir: Option<PathBuf>,
+
+    /// Save the state of the run to this file every `--checkpoint-every`
+    /// ticks and when it ends, so `--resume` can continue it
+    #[clap(long, requires = "headless")]
+    checkpoint: Option<PathBuf>,
+
+    /// Ticks between saves of `--checkpoint`
+    #[clap(long, default_value = "100000", value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoint_every: u64,
+
+    /// Continue the run saved with `--checkpoint` to this file, until
+    /// `--iterations` ticks in total; pass the same target and options
+    #[clap(long)]
+    resume: Option<PathBuf>,
+
+    /// Show a progress bar with the current loss; headless runs also get a
+    /// percentage and an ETA
+    #[clap(long, conflicts_with = "quiet")]
+    progress: bool,
+
+    /// Print nothing but errors
+    #[clap(short, long, overrides_with = "verbose")]
+    quiet: bool,
+
+    /// Also log how the run is going every `--stats-every` ticks, unless
+    /// `RUST_LOG` asks for something else; the later of `--quiet` and
+    /// `--verbose` wins
+    #[clap(short, long, overrides_with = "quiet")]
+    verbose: bool,
+
+    /// Ticks between the acceptance statistics logged with `--verbose` or
+    /// `RUST_LOG=debug`
+    #[clap(long, default_value = "10000", value_parser = clap::value_parser!(u64).range(1..))]
+    stats_every: u64,
+
+    /// Output format, overriding the one inferred from the output extension
+    #[clap(long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// Color to start the canvas from, and to composite targets with
+    /// transparency over, as `rrggbb` hex; overrides `--init` [default: 000000]
+    #[clap(long, conflicts_with = "init", value_parser = parse_color)]
+    background: Option<Color>,
+
+    /// Leave the pixels no shape was drawn onto transparent in the result,
+    /// which must then be a PNG or WebP file
+    #[clap(long)]
+    transparent_bg: bool,
+
+    /// Grayscale image the size of the target weighing how much each pixel
+    /// matters; brighter regions are approximated more closely
+    #[clap(long)]
+    importance_map: Option<PathBuf>,
+
+    /// Black and white image the size of the target marking what to
+    /// approximate: shapes only go on the white parts, and the black parts
+    /// keep the target's own pixels
+    #[clap(long, conflicts_with = "upscale")]
+    mask: Option<PathBuf>,
+
+    /// Approximate the luminance of the target only, and save a grayscale
+    /// image
+    #[clap(long)]
+    grayscale: bool,
+
+    /// Which primitive to approximate the image with
+    #[clap(long, value_enum, default_value = "circle")]
+    shape: ShapeKind,
+
+    /// Mix several primitives, each picked with probability proportional to
+    /// its weight, like `circle:3,rect:1,triangle:1`; a kind without a
+    /// weight counts once
+    #[clap(long, conflicts_with = "shape", value_parser = parse_shape_mix)]
+    shape_mix: Option<ShapeMix>,
+
+    /// Draw solid disks instead of circle outlines
+    #[clap(long)]
+    fill: bool,
+
+    /// Thickness of lines and rings drawn with `--shape line` or `--shape
+    /// ring`, in pixels
+    #[clap(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    stroke_width: u32,
+
+    /// Radius of every dot drawn with `--shape dot`, in pixels
+    #[clap(long, default_value = "2", value_parser = clap::value_parser!(u32).range(1..))]
+    dot_size: u32,
+
+    /// How many corners shapes drawn with `--shape polygon` have
+    #[clap(long, default_value = "5", value_parser = clap::value_parser!(u32).range(3..))]
+    sides: u32,
+
+    /// How soft shapes drawn with `--shape blob` are: the standard deviation
+    /// of their falloff, as a fraction of their radius
+    #[clap(long, default_value = "0.4", value_parser = parse_sigma)]
+    sigma: f32,
+
+    /// Smooth shape edges by blending partially covered pixels
+    #[clap(long)]
+    antialias: bool,
+
+    /// Opacity of each shape, between 0 and 1; translucent shapes blend with
+    /// the ones underneath
+    #[clap(long, default_value = "1.0", value_parser = parse_opacity)]
+    opacity: f32,
+
+    /// Blend, average colors and measure the loss in linear light instead of
+    /// on sRGB values, so soft edges and gradients don't come out too dark
+    #[clap(long)]
+    linear: bool,
+
+    /// Smallest shape radius, in pixels
+    #[clap(long, default_value = "1")]
+    min_radius: u32,
+
+    /// Largest shape radius, in pixels; smaller values capture finer detail
+    /// but converge slower [default: a quarter of the smaller image dimension]
+    #[clap(long)]
+    max_radius: Option<u32>,
+
+    /// Shrink the largest radius over the run, from `--max-radius` down to
+    /// `--min-radius`; only applies to headless runs, which have a fixed length
+    #[clap(long, value_enum, default_value = "none")]
+    radius_schedule: RadiusSchedule,
+
+    /// Starting temperature for simulated annealing, which occasionally
+    /// accepts worse shapes to escape local minima; 0 disables it
+    #[clap(long, default_value = "0", value_parser = parse_temperature)]
+    temperature: f32,
+
+    /// Factor the annealing temperature is multiplied by after every tick,
+    /// more than 0 and at most 1
+    #[clap(long, default_value = "0.9999", value_parser = parse_cooling)]
+    cooling: f32,
+
+    /// How differences from the target are measured: `l2` sums squared RGB
+    /// differences, `l1` absolute ones, and `delta-e` measures perceived
+    /// color differences
+    #[clap(long, value_enum, default_value = "l2")]
+    loss: LossKind,
+
+    /// How shapes pick their color from the target pixels they cover:
+    /// `weighted` blends the center with the outline, `mean` averages them
+    /// all, `median` keeps edges sharper, and `optimal` picks the color that
+    /// lowers the loss the most
+    #[clap(long, value_enum, default_value = "weighted")]
+    color_strategy: ColorStrategy,
+
+    /// Restrict shapes (and the background) to these comma-separated hex
+    /// colors, like `1d2b53,7e2553,ff004d,fff1e8`; each color is snapped to
+    /// the closest one under `--loss`
+    #[clap(long, value_delimiter = ',', value_parser = parse_color, conflicts_with = "palette_file")]
+    palette: Vec<Color>,
+
+    /// Like `--palette`, with the hex colors read from a file, separated by
+    /// whitespace or commas
+    #[clap(long, conflicts_with = "auto_palette")]
+    palette_file: Option<PathBuf>,
+
+    /// Like `--palette`, with this many colors picked from the target by
+    /// k-means clustering; they're printed before the run starts
+    #[clap(long, conflicts_with = "palette", value_parser = clap::value_parser!(u32).range(1..=256))]
+    auto_palette: Option<u32>,
+
+    /// Map every shape color (and the background) onto the gradient between
+    /// a dark and a light hex color by its luminance, like
+    /// `--duotone 1b2a49 f4ecd6` for navy to cream
+    #[clap(
+        long,
+        num_args = 2,
+        value_names = ["DARK", "LIGHT"],
+        value_parser = parse_color,
+        conflicts_with_all = ["palette", "palette_file", "auto_palette", "grayscale"]
+    )]
+    duotone: Vec<Color>,
+
+    /// Map every pixel of the result to the palette, or the two `--duotone`
+    /// colors, with Floyd–Steinberg dithering, so blends between them come
+    /// out as a mix instead of bands
+    #[clap(long, requires = "palettes", conflicts_with = "replay")]
+    dither: bool,
+
+    /// What to fill the canvas with before the first shape: black, or the
+    /// average color of the target; see `--background` for any other color
+    #[clap(long, value_enum, default_value = "black")]
+    init: InitKind,
+
+    /// Center half of the new shapes in the regions where the approximation
+    /// is currently worst
+    #[clap(long)]
+    error_guided: bool,
+
+    /// Ticks between refreshes of the error map `--error-guided` samples
+    /// from; lower values track the error more closely but cost more
+    #[clap(long, default_value = "1000", value_parser = clap::value_parser!(u64).range(1..))]
+    error_refresh: u64,
+
+    /// Spend half of the ticks nudging already placed shapes, keeping the
+    /// changes that improve the result
+    #[clap(long)]
+    refine: bool,
+
+    /// Turn away new shapes centered closer than this many pixels to one of
+    /// the last 64 drawn, to keep them from clumping; 0 allows any distance
+    #[clap(long, default_value = "0", value_parser = parse_spacing)]
+    min_spacing: f32,
+
+    /// Draw every shape along with symmetric copies about the center of the
+    /// image: `mirror` reflects it left to right, and `radial:N` repeats it
+    /// N times around the center; `--refine` and `--threads` have no effect
+    #[clap(long, value_parser = parse_symmetry)]
+    symmetry: Option<Symmetry>,
+
+    /// Every this many ticks, remove the shapes whose removal doesn't make
+    /// the result worse, like ones later shapes have covered up, to shrink
+    /// `--shapes-json` and `--svg` output
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    prune_every: Option<u64>,
+}
+
+impl Args {
+    /// Prints a status update, unless `--quiet` was given; it goes to
+    /// standard error if the image is written to standard output.
+    fn status(&self, message: impl Display) {
+        if self.quiet {
+            return;
+        }
+
+        if self.output.as_deref() == Some(Path::new(STDIO)) {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
 }
 
-fn main() {
-    let args = Args::parse();
+fn parse_threads(s: &str) -> Result<u64, String> {
+    if s == "auto" {
+        return Ok(cpu_count());
+    }
 
-    let target = ImageReader::open(&args.target)
-        .expect("couldn't load given image")
-        .decode()
-        .expect("couldn't decode given image")
-        .into_rgb8();
+    match s.parse().map_err(|_| String::from("must be a thread count or auto"))? {
+        0 => Ok(cpu_count()),
+        threads => Ok(threads),
+    }
+}
 
-    let target = Image::from(target);
-    let width = target.width;
-    let height = target.height;
+/// Returns how many threads can run at once, or 1 if that's unknown.
+fn cpu_count() -> u64 {
+    std::thread::available_parallelism().map_or(1, |threads| threads.get() as u64)
+}
 
-    let mut approx = Image::from(RgbImage::new(width, height));
+fn parse_opacity(s: &str) -> Result<f32, String> {
+    let opacity: f32 = s.parse().map_err(|err| format!("{}", err))?;
 
-    let mut canvas = vec![0; (width * height) as usize];
+    if (0.0..=1.0).contains(&opacity) {
+        Ok(opacity)
+    } else {
+        Err(String::from("must be between 0 and 1"))
+    }
+}
+
+fn parse_window_scale(s: &str) -> Result<f32, String> {
+    let scale: f32 = s.parse().map_err(|err| format!("{}", err))?;
+
+    if scale > 0.0 && scale <= MAX_ZOOM {
+        Ok(scale)
+    } else {
+        Err(format!("must be more than 0 and at most {MAX_ZOOM}"))
+    }
+}
+
+/// The weighted primitives given to `--shape-mix`, which clap can't take as a
+/// plain `Vec` since that would mean the flag can repeat.
+#[derive(Clone, Debug)]
+struct ShapeMix(Vec<(ShapeKind, u32)>);
+
+fn parse_shape_mix(s: &str) -> Result<ShapeMix, String> {
+    let mix = s
+        .split(',')
+        .map(|entry| {
+            let (name, weight) = match entry.split_once(':') {
+                Some((name, weight)) => {
+                    let weight = weight
+                        .trim()
+                        .parse()
+                        .map_err(|err| format!("invalid weight for {}: {}", name.trim(), err))?;
+
+                    (name, weight)
+                }
+                None => (entry, 1),
+            };
+
+            let kind = ShapeKind::from_str(name.trim(), true)
+                .map_err(|_| format!("unknown shape {:?}", name.trim()))?;
+
+            Ok((kind, weight))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if mix.iter().all(|&(_, weight)| weight == 0) {
+        return Err(String::from("the weights must not all be zero"));
+    }
+
+    Ok(ShapeMix(mix))
+}
+
+fn parse_sigma(s: &str) -> Result<f32, String> {
+    let sigma: f32 = s.parse().map_err(|err| format!("{}", err))?;
+
+    if sigma > 0.0 && sigma.is_finite() {
+        Ok(sigma)
+    } else {
+        Err(String::from("must be a positive number"))
+    }
+}
+
+fn parse_temperature(s: &str) -> Result<f32, String> {
+    let temperature: f32 = s.parse().map_err(|err| format!("{}", err))?;
+
+    if temperature >= 0.0 && temperature.is_finite() {
+        Ok(temperature)
+    } else {
+        Err(String::from("must be a non-negative number"))
+    }
+}
+
+fn parse_cooling(s: &str) -> Result<f32, String> {
+    let cooling: f32 = s.parse().map_err(|err| format!("{}", err))?;
+
+    if cooling > 0.0 && cooling <= 1.0 {
+        Ok(cooling)
+    } else {
+        Err(String::from("must be more than 0 and at most 1"))
+    }
+}
+
+fn parse_exposure(s: &str) -> Result<f32, String> {
+    let exposure: f32 = s.parse().map_err(|err| format!("{}", err))?;
+
+    if exposure.is_finite() {
+        Ok(exposure)
+    } else {
+        Err(String::from("must be a number"))
+    }
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s.parse().map_err(|err| format!("{}", err))?;
+
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| String::from("must be a positive number of seconds"))
+}
+
+fn parse_spacing(s: &str) -> Result<f32, String> {
+    let spacing: f32 = s.parse().map_err(|err| format!("{}", err))?;
+
+    if spacing >= 0.0 && spacing.is_finite() {
+        Ok(spacing)
+    } else {
+        Err(String::from("must be a non-negative number"))
+    }
+}
+
+fn parse_symmetry(s: &str) -> Result<Symmetry, String> {
+    if s == "mirror" {
+        return Ok(Symmetry::Mirror);
+    }
+
+    let copies = s
+        .strip_prefix("radial:")
+        .ok_or_else(|| String::from("expected `mirror` or `radial:N`"))?;
+
+    match copies.parse() {
+        Ok(copies) if copies >= 2 => Ok(Symmetry::Radial(copies)),
+        _ => Err(format!("expected at least 2 copies, got `{}`", copies)),
+    }
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(String::from("must be six hex digits, like ff8800"));
+    }
 
-    let mut window = Window::new(
-        "circlez",
-        width as usize,
-        height as usize,
-        WindowOptions::default(),
-    )
-        .unwrap();
+    Ok([0, 2, 4].map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()))
+}
+
+/// Formats a color the way [`parse_color`] reads it.
+fn format_color([r, g, b]: Color) -> String {
+    format!("{:02x}{:02x}{:02x}", r, g, b)
+}
 
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        let mut got_improvement = false;
+#[derive(Clone, Copy, ValueEnum)]
+enum InitKind {
+    Avg,
+    Black,
+}
 
-        for _ in 0..args.iterations {
-            got_improvement |= tick(&target, &mut approx);
+impl From<InitKind> for Init {
+    fn from(init: InitKind) -> Self {
+        match init {
+            InitKind::Avg => Init::Average,
+            InitKind::Black => Init::Black,
         }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Png,
+    Jpeg,
+    Webp,
+    Bmp,
+}
 
-        if got_improvement {
-            approx.encode(&mut canvas);
+impl OutputFormat {
+    /// The usual file extension for the format.
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Bmp => "bmp",
         }
+    }
+}
 
-        window
-            .update_with_buffer(&canvas, width as usize, height as usize)
-            .unwrap();
+impl From<OutputFormat> for ImageFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Png => ImageFormat::Png,
+            OutputFormat::Jpeg => ImageFormat::Jpeg,
+            OutputFormat::Webp => ImageFormat::WebP,
+            OutputFormat::Bmp => ImageFormat::Bmp,
+        }
     }
+}
 
-    // Save the final image when window closes
-    if !window.is_open() || window.is_key_down(Key::Escape) {
-        // Create the output filename
-        let input_path = Path::new(&args.target);
-        let input_stem = input_path.file_stem().unwrap().to_str().unwrap();
-        let output_filename = format!("generated_images/{}_circlez.jpg", input_stem);
+fn main() -> ExitCode {
+    let result = parse_args().and_then(|(args, matches)| {
+        init_logging(&args);
 
-        // Convert the current state to an image
-        let mut output_image = RgbImage::new(width, height);
-        for y in 0..height {
-            for x in 0..width {
-                let [r, g, b] = approx.color_at([x, y]);
-                output_image.put_pixel(x, y, image::Rgb([r, g, b]));
-            }
+        if let Some(path) = &args.dump_config {
+            save_text(path, &dump_config(&Args::command(), &matches))?;
+            args.status(format_args!("Saved settings to: {}", path.display()));
         }
 
-        // Ensure the directory exists
-        std::fs::create_dir_all("generated_images").expect("Failed to create output directory");
+        run(&args)
+    });
 
-        // Save the image
-        output_image.save(&output_filename).expect("Failed to save output image");
-        println!("Saved final image to: {}", output_filename);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
     }
 }
 
-fn calculate_weighted_color(target: &Image, center_x: isize, center_y: isize, radius: isize,
-                            circle_points: &[[isize; 2]]) -> [u8; 3] {
-    // Get center color
-    let center_color = if center_x >= 0 && center_y >= 0
-        && center_x < target.width as isize && center_y < target.height as isize {
-        target.color_at([center_x as u32, center_y as u32])
+/// Sends logs to standard error, as set by `RUST_LOG` and only errors by
+/// default; `--verbose` makes the default debug messages from circlez, and
+/// `--quiet` leaves nothing but errors whatever `RUST_LOG` says.
+fn init_logging(args: &Args) {
+    let mut builder = if args.quiet {
+        let mut builder = env_logger::Builder::new();
+        builder.filter_level(log::LevelFilter::Error);
+        builder
     } else {
-        [0, 0, 0]
+        let default = if args.verbose { "error,circlez=debug" } else { "error" };
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default))
     };
 
-    // Calculate average edge color from valid points
-    let mut valid_points = 0;
-    let edge_color = circle_points.iter()
-        .filter(|[x, y]| {
-            x >= &0 && y >= &0 &&
-                x < &(target.width as isize) && y < &(target.height as isize)
-        })
-        .map(|[x, y]| {
-            valid_points += 1;
-            target.color_at([*x as u32, *y as u32])
-        })
-        .fold([0f32; 3], |acc, [r, g, b]| {
-            [acc[0] + r as f32, acc[1] + g as f32, acc[2] + b as f32]
-        });
+    builder.init();
+}
 
-    if valid_points == 0 {
-        return center_color;
-    }
+/// Parses the command line, filling in whatever it leaves out from the
+/// `--config` file if there is one; returns the matches as well, for
+/// `--dump-config`.
+///
+/// Settings from the file are turned into the options they stand for, so
+/// clap checks them like any other.
+fn parse_args() -> Result<(Args, ArgMatches), CirclezError> {
+    let command = Args::command();
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
 
-    let edge_color = [
-        (edge_color[0] / valid_points as f32) as u8,
-        (edge_color[1] / valid_points as f32) as u8,
-        (edge_color[2] / valid_points as f32) as u8,
-    ];
+    // Only to find the config file; the real parse below reports any errors
+    let given = command.clone().ignore_errors(true).get_matches_from(&argv);
+
+    if let Some(path) = given.get_one::<PathBuf>("config") {
+        let text = std::fs::read_to_string(path).map_err(|source| CirclezError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        let settings: toml::Table =
+            toml::from_str(&text).map_err(|source| CirclezError::ParseConfig {
+                path: path.to_owned(),
+                source,
+            })?;
+
+        let extra = config_args(&command, &given, &settings).map_err(|reason| {
+            CirclezError::InvalidConfig {
+                path: path.to_owned(),
+                reason,
+            }
+        })?;
+        argv.extend(extra.into_iter().map(OsString::from));
+    }
 
-    // Calculate weight based on radius (larger radius = more weight to edge color)
-    let max_radius = (target.width.min(target.height) / 4) as f32;
-    let weight = (radius as f32 / max_radius).min(1.0);
+    let matches = command.get_matches_from(argv);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
 
-    // Blend colors
-    [
-        ((1.0 - weight) * center_color[0] as f32 + weight * edge_color[0] as f32) as u8,
-        ((1.0 - weight) * center_color[1] as f32 + weight * edge_color[1] as f32) as u8,
-        ((1.0 - weight) * center_color[2] as f32 + weight * edge_color[2] as f32) as u8,
-    ]
+    Ok((args, matches))
 }
 
-fn tick(target: &Image, approx: &mut Image) -> bool {
-    // Randomize center point
-    let center_x = random_range(0..target.width) as isize;
-    let center_y = random_range(0..target.height) as isize;
+/// Settings that only make sense on the command line.
+const COMMAND_LINE_ONLY: [&str; 4] = ["config", "dump_config", "help", "version"];
 
-    // Randomize radius (limit to reasonable size based on image dimensions)
-    let max_radius = (target.width.min(target.height) / 4) as isize;
-    let radius = random_range(1..=max_radius as usize);
+/// Settings that override each other, the later one winning, instead of
+/// conflicting; clap doesn't say which ones those are.
+const OVERRIDING: [[&str; 2]; 1] = [["quiet", "verbose"]];
 
-    // Generate circle points first so we can use them for both color calculation and drawing
-    let circle_points = generate_circle_points(center_x, center_y, radius as isize);
+/// Turns `settings` into command line arguments, leaving out the ones that
+/// were `given` on the command line already or that conflict with one that
+/// was, so the command line always wins.
+fn config_args(
+    command: &Command,
+    given: &ArgMatches,
+    settings: &toml::Table,
+) -> Result<Vec<String>, String> {
+    let on_command_line = |arg: &Arg| {
+        given.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+    };
+    let conflict = |a: &Arg, b: &Arg| {
+        let ids = [a.get_id().as_str(), b.get_id().as_str()];
 
-    // Calculate weighted average color
-    let color = calculate_weighted_color(target, center_x, center_y, radius as isize, &circle_points);
+        command.get_arg_conflicts_with(a).contains(&b)
+            || command.get_arg_conflicts_with(b).contains(&a)
+            || OVERRIDING.iter().any(|pair| pair.contains(&ids[0]) && pair.contains(&ids[1]))
+    };
 
-    // Generate all points that would be affected by the circle
-    let changes = circle_points
-        .into_iter()
-        .filter(|&[x, y]| {
-            x >= 0 &&
-                y >= 0 &&
-                x < target.width as isize &&
-                y < target.height as isize
-        })
-        .map(|[x, y]| ([x as u32, y as u32], color));
+    let mut args = Vec::new();
+
+    for (key, value) in settings {
+        let id = key.replace('-', "_");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() && !COMMAND_LINE_ONLY.contains(&id.as_str()))
+            .ok_or_else(|| format!("unknown setting {:?}", key))?;
+
+        let overridden = command
+            .get_arguments()
+            .filter(|&other| on_command_line(other))
+            .any(|other| other == arg || conflict(arg, other));
+
+        if overridden {
+            continue;
+        }
 
-    // Check if drawing this circle would improve the approximation
-    let loss_delta = Image::loss_delta(target, approx, changes.clone());
+        let values = match value {
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        let values = values
+            .into_iter()
+            .map(|value| match value {
+                toml::Value::String(value) => Ok(value.clone()),
+                toml::Value::Integer(value) => Ok(value.to_string()),
+                toml::Value::Float(value) => Ok(value.to_string()),
+                toml::Value::Boolean(value) => Ok(value.to_string()),
+                _ => Err(format!("{} must be a string, number or boolean", key)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
-    if loss_delta >= 0.0 {
-        return false;
+        if arg.is_positional() {
+            args.extend(values);
+        } else if arg.get_num_args().is_some_and(|count| count.min_values() > 1) {
+            // Like `--duotone a b`, which needs all of its values at once
+            args.push(format!("--{}", key.replace('_', "-")));
+            args.extend(values);
+        } else if matches!(arg.get_action(), ArgAction::SetTrue) {
+            match value {
+                toml::Value::Boolean(true) => args.push(format!("--{}", key.replace('_', "-"))),
+                toml::Value::Boolean(false) => {}
+                _ => return Err(format!("{} must be true or false", key)),
+            }
+        } else {
+            args.extend(values.into_iter().map(|value| format!("--{}={}", key.replace('_', "-"), value)));
+        }
     }
 
-    // Apply the changes if the circle improves the approximation
-    approx.apply(changes);
-    true
+    Ok(args)
 }
 
-fn generate_circle_points(xc: isize, yc: isize, r: isize) -> Vec<[isize; 2]> {
-    let mut points = Vec::new();
-    let mut x = 0;
-    let mut y = r;
-    let mut d = 3 - 2 * r;
+/// Writes out every setting in `matches`, defaults included, as TOML that
+/// [`config_args`] reads back into the same settings.
+fn dump_config(command: &Command, matches: &ArgMatches) -> String {
+    let mut text = String::from("# circlez settings, for use with --config\n");
+
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+
+        if COMMAND_LINE_ONLY.contains(&id) {
+            continue;
+        }
+
+        let key = id.replace('_', "-");
+
+        if matches!(arg.get_action(), ArgAction::SetTrue) {
+            text.push_str(&format!("{} = {}\n", key, matches.get_flag(id)));
+            continue;
+        }
+
+        let Some(raw) = matches.get_raw(id) else {
+            continue;
+        };
 
-    while x <= y {
-        // Add points in all octants
-        let octant_points = [
-            [xc + x, yc + y], [xc - x, yc + y],
-            [xc + x, yc - y], [xc - x, yc - y],
-            [xc + y, yc + x], [xc - y, yc + x],
-            [xc + y, yc - x], [xc - y, yc - x],
-        ];
-        points.extend_from_slice(&octant_points);
+        let values: Vec<String> = raw.map(|value| toml_value(&value.to_string_lossy())).collect();
 
-        if d < 0 {
-            d = d + 4 * x + 6;
+        if matches!(arg.get_action(), ArgAction::Append) {
+            text.push_str(&format!("{} = [{}]\n", key, values.join(", ")));
         } else {
-            d = d + 4 * (x - y) + 10;
-            y -= 1;
+            text.push_str(&format!("{} = {}\n", key, values.join(", ")));
         }
-        x += 1;
     }
-    points
+
+    text
 }
 
-type Point = [u32; 2];
-type Color = [u8; 3];
+/// Formats an option's value for TOML: as a number if it reads back as the
+/// same text, and as a string otherwise.
+fn toml_value(value: &str) -> String {
+    let number = value.parse().map(toml::Value::Integer).ok().or_else(|| {
+        let float: f64 = value.parse().ok()?;
+        float.is_finite().then_some(toml::Value::Float(float))
+    });
 
-struct Image {
-    width: u32,
-    height: u32,
-    pixels: Vec<u8>,
+    match number {
+        Some(number) if number.to_string() == value => number.to_string(),
+        _ => toml::Value::String(value.to_owned()).to_string(),
+    }
 }
 
-impl Image {
-    fn loss_delta(
-        target: &Self,
-        approx: &Self,
-        changes: impl IntoIterator<Item = (Point, Color)>,
-    ) -> f32 {
-        changes
-            .into_iter()
-            .map(|(pos, new_col)| {
-                let target_color = target.color_at(pos);
-                let approx_color = approx.color_at(pos);
+/// Does everything `args` asks for.
+fn run(args: &Args) -> Result<(), CirclezError> {
+    // Both the tiles and large loss evaluations share rayon's pool
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads as usize)
+        .build_global()
+        .map_err(|source| CirclezError::Threads {
+            threads: args.threads,
+            source,
+        })?;
 
-                let loss_without_changes = Self::pixel_loss(target_color, approx_color);
-                let loss_with_changes = Self::pixel_loss(target_color, new_col);
+    if let Some(path) = &args.replay {
+        return replay(args, path);
+    }
 
-                loss_with_changes - loss_without_changes
-            })
-            .sum()
+    if args.animate {
+        return run_animation(args);
     }
 
-    fn pixel_loss(a: Color, b: Color) -> f32 {
-        a.into_iter()
-            .zip(b)
-            .map(|(a, b)| (a as f32 - b as f32).powi(2))
-            .sum()
+    if is_batch(&args.target) {
+        return run_batch(args);
     }
 
-    fn apply(&mut self, changes: impl IntoIterator<Item = (Point, Color)>) {
-        for (pos, col) in changes {
-            *self.color_at_mut(pos) = col;
+    let target_path = args.target.first().expect("clap requires a target without --replay");
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| default_output(target_path));
+
+    approximate(args, target_path, &output).map(|_| ())
+}
+
+/// Sets up an approximator for `target` as `args` asks, resuming from
+/// `--resume` if given.
+fn prepare(args: &Args, target: DynamicImage) -> Result<Approximator, CirclezError> {
+    let original_size = (target.width(), target.height());
+    let mut target = convert_target(args, target);
+
+    if let Some(path) = &args.importance_map {
+        let mut map = load_image(path)?.into_luma8();
+
+        // A map made for the full-size target is scaled along with it
+        if map.dimensions() == original_size {
+            map = imageops::resize(&map, target.width, target.height, FilterType::Lanczos3);
+        }
+
+        target
+            .set_importance_map(&map)
+            .map_err(CirclezError::InvalidImportanceMap)?;
+    }
+
+    if let Some(path) = &args.mask {
+        let mut mask = load_image(path)?.into_luma8();
+
+        if mask.dimensions() == original_size {
+            mask = imageops::resize(&mask, target.width, target.height, FilterType::Nearest);
         }
+
+        target.set_mask(&mask).map_err(CirclezError::InvalidMask)?;
     }
 
-    fn encode(&self, buf: &mut [u32]) {
-        let mut buf = buf.iter_mut();
+    let config = Config {
+        shape: args.shape,
+        shape_mix: args.shape_mix.clone().map(|mix| mix.0).unwrap_or_default(),
+        fill: args.fill,
+        stroke_width: args.stroke_width,
+        dot_size: args.dot_size,
+        sides: args.sides,
+        sigma: args.sigma,
+        antialias: args.antialias,
+        opacity: args.opacity,
+        linear: args.linear,
+        min_radius: args.min_radius,
+        max_radius: args.max_radius,
+        radius_schedule: args.radius_schedule,
+        temperature: args.temperature,
+        cooling: args.cooling,
+        refine: args.refine,
+        loss: args.loss,
+        error_guided: args.error_guided,
+        error_refresh: args.error_refresh as usize,
+        min_spacing: args.min_spacing,
+        symmetry: args.symmetry.unwrap_or_default(),
+        prune_every: args.prune_every.unwrap_or(0) as usize,
+        init: args.background.map_or(args.init.into(), Init::Color),
+        color_strategy: args.color_strategy,
+        palette: match (&args.palette_file, args.auto_palette) {
+            (Some(path), _) => load_palette(path)?,
+            (None, Some(colors)) => {
+                let palette = circlez::kmeans_palette(&target, colors as usize);
+                let hex: Vec<_> = palette.iter().map(|&color| format_color(color)).collect();
 
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let [r, g, b] = self.color_at([x, y]);
-                *buf.next().unwrap() = u32::from_be_bytes([0, r, g, b]);
+                args.status(format_args!("Palette: {}", hex.join(",")));
+                palette
             }
+            (None, None) => args.palette.clone(),
+        },
+        duotone: match args.duotone[..] {
+            [dark, light] => Some([dark, light]),
+            _ => None,
+        },
+    };
+
+    config
+        .validate(target.width, target.height)
+        .map_err(CirclezError::InvalidRadius)?;
+
+    if let Symmetry::Radial(copies) = config.symmetry {
+        let rects = match &args.shape_mix {
+            Some(mix) => mix.0.iter().any(|&(kind, weight)| kind == ShapeKind::Rect && weight > 0),
+            None => args.shape == ShapeKind::Rect,
+        };
+
+        if rects && 4 % copies != 0 {
+            return Err(CirclezError::InvalidSymmetry(format!(
+                "rectangles can only be turned by quarter turns, not 1/{} turns",
+                copies
+            )));
         }
     }
 
-    fn color_at(&self, point: Point) -> Color {
-        let offset = (point[1] * self.width + point[0]) as usize * 3;
-        let color = &self.pixels[offset..][..3];
-        color.try_into().unwrap()
+    if (!args.headless || args.shapes.is_some())
+        && args.radius_schedule != RadiusSchedule::None
+        && !args.quiet
+    {
+        eprintln!(
+            "Note: windowed runs and --shapes runs have no fixed length, so --radius-schedule has no effect"
+        );
     }
 
-    fn color_at_mut(&mut self, [x, y]: [u32; 2]) -> &mut Color {
-        let offset = (y * self.width + x) as usize * 3;
-        let color = &mut self.pixels[offset..][..3];
-        color.try_into().unwrap()
+    match &args.resume {
+        Some(path) => {
+            let checkpoint = load_checkpoint(path)?;
+
+            args.status(format_args!("Resuming from tick {}", checkpoint.ticks()));
+            Approximator::from_checkpoint(target, config, checkpoint).map_err(|reason| {
+                CirclezError::InvalidCheckpoint {
+                    path: path.to_owned(),
+                    reason,
+                }
+            })
+        }
+        None => {
+            let rng = match args.seed {
+                Some(seed) => ChaCha12Rng::seed_from_u64(seed),
+                None => ChaCha12Rng::from_os_rng(),
+            };
+
+            match args.pyramid {
+                Some(levels) if levels > 1 => coarse_to_fine(args, target, config, rng, levels),
+                _ => Ok(Approximator::new(target, config, rng)),
+            }
+        }
     }
 }
 
-impl From<RgbImage> for Image {
-    fn from(img: RgbImage) -> Self {
-        let width = img.width();
-        let height = img.height();
-        let pixels = img.pixels().flat_map(|pixel| pixel.0).collect();
+/// Approximates `target` on a copy `2^(levels - 1)` times smaller first,
+/// then on copies twice as large in turn, scaling the shapes up each time
+/// (see [`circlez::Approximator::upscale`]), and returns the approximator
+/// once it's full size, for the last share of `--iterations`.
+///
+/// Every size gets an equal share of the ticks. The smaller copies are
+/// cheaper to tick, and the shapes drawn on them lay down the structure of
+/// the image, leaving the full size to fill in the details.
+fn coarse_to_fine(
+    args: &Args,
+    target: Image,
+    config: Config,
+    rng: ChaCha12Rng,
+    levels: u32,
+) -> Result<Approximator, CirclezError> {
+    let level = |level: u32| -> Result<(Image, Config), CirclezError> {
+        if level == 0 {
+            return Ok((target.clone(), config.clone()));
+        }
 
-        Self {
-            width,
-            height,
-            pixels,
+        let factor = 1 << level;
+        let (width, height) = (target.width.div_ceil(factor), target.height.div_ceil(factor));
+        let smaller = DynamicImage::from(&target).resize_exact(width, height, FilterType::Triangle);
+        let config = config.scaled_down(factor);
+
+        config
+            .validate(width, height)
+            .map_err(|reason| CirclezError::InvalidRadius(format!("with --pyramid, {reason}")))?;
+
+        Ok((Image::from(smaller), config))
+    };
+
+    let share = args.iterations / levels as usize;
+    let (smallest, smallest_config) = level(levels - 1)?;
+    let mut approximator = Approximator::new(smallest, smallest_config, rng);
+    approximator.set_budget(args.iterations);
+
+    for next in (0..levels - 1).rev() {
+        let size = (approximator.target().width, approximator.target().height);
+        approximator.run_parallel(share, args.threads as usize);
+
+        args.status(format_args!(
+            "Drew {} shapes at {}x{}",
+            approximator.shapes().len(),
+            size.0,
+            size.1
+        ));
+
+        let (target, config) = level(next)?;
+        approximator
+            .upscale(target, config, 2)
+            .expect("every size is twice the one before, rounded up");
+    }
+
+    Ok(approximator)
+}
+
+/// Turns `target` into an image to approximate: tone-mapped at `--exposure`
+/// if it's in floating point, flattened onto `--background` if it's
+/// transparent, then shrunk to `--max-dimension` and made grayscale as
+/// asked.
+fn convert_target(args: &Args, mut target: DynamicImage) -> Image {
+    // Colors are 8 bits per channel all the way through, see `circlez::Color`
+    let color = target.color();
+    if matches!(target, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)) {
+        target = DynamicImage::ImageRgba8(circlez::tone_map(&target.to_rgba32f(), args.exposure));
+    } else if color.bytes_per_pixel() > color.channel_count() && !args.quiet {
+        eprintln!("Note: the target has more than 8 bits per channel, but is approximated and saved with 8");
+    }
+
+    if target.color().has_alpha() {
+        let background = args.background.unwrap_or([0, 0, 0]);
+        target = DynamicImage::ImageRgb8(circlez::flatten(&target.to_rgba8(), background));
+    }
+
+    if let Some(max_dimension) = args.max_dimension {
+        if target.width().max(target.height()) > max_dimension {
+            let (width, height) = (target.width(), target.height());
+            target = target.resize(max_dimension, max_dimension, FilterType::Lanczos3);
+
+            args.status(format_args!(
+                "Resized target from {}x{} to {}x{}",
+                width,
+                height,
+                target.width(),
+                target.height()
+            ));
         }
     }
-}
\ No newline at end of file
+
+    if args.grayscale {
+        Image::from(target.into_luma8())
+    } else {
+        Image::from(target.into_rgb8())
+    }
+}
+
+/// Approximates the target at `target_path` as `args` asks, saving the
+/// result to `output` along with everything else requested.
+fn approximate(args: &Args, target_path: &Path, output: &Path) -> Result<Approximator, CirclezError> {
+    check_transparency(args, output)?;
+
+    let mut approximator = prepare(args, load_target(target_path)?)?;
+    let started = Instant::now();
+
+    info!(
+        "approximating {} at {}x{} from tick {}",
+        target_path.display(),
+        approximator.target().width,
+        approximator.target().height,
+        approximator.ticks()
+    );
+
+    let mut observers = Observers {
+        gif: args.gif.as_ref().map(|_| {
+            GifRecorder::new(args.gif_every as usize, args.gif_frames as usize, args.gif_delay)
+        }),
+        frames: args
+            .frames_dir
+            .clone()
+            .zip(args.save_every)
+            .map(|(dir, every)| FrameWriter::new(dir, every as usize)),
+        progress: args.progress.then(|| {
+            progress_bar((args.headless && args.shapes.is_none()).then_some(args.iterations))
+        }),
+        checkpoints: args.checkpoint.clone().map(|path| {
+            CheckpointWriter::new(path, args.checkpoint_every as usize, approximator.ticks())
+        }),
+        stats: log::log_enabled!(log::Level::Debug)
+            .then(|| Stats::new(&approximator, args.stats_every as usize)),
+    };
+
+    let threads = args.threads as usize;
+    let early_stop = EarlyStop::new(args);
+
+    let result = if args.headless {
+        let iterations = early_stop.headless_iterations(args, &mut approximator);
+        run_headless(&mut approximator, iterations, threads, &early_stop, &mut observers)
+    } else {
+        // Snapshots can't go to standard output along with the result
+        let snapshot_base = match &args.output {
+            Some(path) if path != Path::new(STDIO) => path.clone(),
+            _ => default_output(target_path),
+        };
+        let config = approximator.config().clone();
+        let save_snapshot = |approx: &Image| {
+            let path = snapshot_path(&snapshot_base);
+
+            save(&path, args.format, &result_image(args, &config, approx))?;
+            args.status(format_args!("Saved snapshot to: {}", path.display()));
+            Ok(())
+        };
+
+        run_windowed(
+            &mut approximator,
+            args,
+            threads,
+            &early_stop,
+            &mut observers,
+            &save_snapshot,
+        )
+    };
+
+    if let Some(progress) = &observers.progress {
+        progress.finish_and_clear();
+    }
+
+    result?;
+
+    info!(
+        "stopped after {} ticks and {:.2?} with {} shapes, loss {:.0}",
+        approximator.ticks(),
+        started.elapsed(),
+        approximator.shapes().len(),
+        approximator.approx().total_loss()
+    );
+
+    if let Some(checkpoints) = &observers.checkpoints {
+        checkpoints.save(&approximator)?;
+        args.status(format_args!("Saved checkpoint to: {}", checkpoints.path.display()));
+    }
+
+    match (early_stop.reason(&approximator), early_stop.shapes) {
+        (Some(reason), _) => {
+            args.status(format_args!("Stopped after {} ticks: {}", approximator.ticks(), reason))
+        }
+        (None, Some(shapes)) if args.headless => args.status(format_args!(
+            "Gave up after {} ticks with {} of {} shapes drawn",
+            approximator.ticks(),
+            approximator.shapes().len(),
+            shapes
+        )),
+        _ => {}
+    }
+
+    if args.prune_every.is_some() {
+        let pruned = approximator.pruned();
+
+        args.status(format_args!(
+            "Pruned {} shape{}, leaving {}",
+            pruned,
+            if pruned == 1 { "" } else { "s" },
+            approximator.shapes().len()
+        ));
+    }
+
+    approximator
+        .approx()
+        .debug_assert_loss(approximator.target(), approximator.config().metric());
+
+    if args.upscale > 1 {
+        let upscaled = approximator.recording().render_scaled(args.upscale);
+
+        save(output, args.format, &result_image(args, approximator.config(), &upscaled))?;
+        args.status(format_args!(
+            "Saved final image to: {} ({}x{})",
+            describe(output),
+            upscaled.width,
+            upscaled.height
+        ));
+    } else {
+        let result = result_image(args, approximator.config(), approximator.approx());
+
+        save(output, args.format, &result)?;
+        args.status(format_args!("Saved final image to: {}", describe(output)));
+    }
+
+    args.status(format_args!(
+        "MSE: {:.2}, PSNR: {:.2} dB, SSIM: {:.4}",
+        circlez::mse(approximator.target(), approximator.approx()),
+        circlez::psnr(approximator.target(), approximator.approx()),
+        circlez::ssim(approximator.target(), approximator.approx())
+    ));
+
+    if let Some(path) = &args.diff {
+        let heatmap = circlez::heatmap(approximator.target(), approximator.approx());
+
+        save(path, None, &DynamicImage::from(heatmap))?;
+        args.status(format_args!("Saved difference heatmap to: {}", describe(path)));
+    }
+
+    if let Some(path) = &args.compare {
+        let comparison = circlez::side_by_side(approximator.target(), approximator.approx());
+
+        save(path, None, &DynamicImage::from(comparison))?;
+        args.status(format_args!("Saved comparison to: {}", describe(path)));
+    }
+
+    if let Some(path) = &args.svg {
+        let svg = circlez::svg(approximator.target(), approximator.shapes(), approximator.config());
+
+        save_text(path, &svg)?;
+        args.status(format_args!("Saved shapes to: {}", path.display()));
+    }
+
+    if let (Some(path), Some(gif)) = (&args.gif, observers.gif) {
+        gif.save(path, approximator.approx())?;
+        args.status(format_args!("Saved animation to: {}", path.display()));
+    }
+
+    if let Some(path) = &args.shapes_json {
+        let json = serde_json::to_string_pretty(&approximator.recording())
+            .expect("recordings only contain serializable values");
+
+        save_text(path, &json)?;
+        args.status(format_args!("Saved shape list to: {}", path.display()));
+    }
+
+    Ok(approximator)
+}
+
+/// Returns whether `targets` name several images rather than one.
+fn is_batch(targets: &[PathBuf]) -> bool {
+    match targets {
+        [target] => target.is_dir() || (is_glob(target) && !target.exists()),
+        _ => true,
+    }
+}
+
+/// Approximates every image among the targets with the same settings,
+/// `--jobs` at a time, saving each into the output directory under its own
+/// name.
+fn run_batch(args: &Args) -> Result<(), CirclezError> {
+    if let Some(flag) = single_image_flag(args) {
+        return Err(CirclezError::Batch(format!("{} only works with a single target", flag)));
+    }
+
+    if args.output.as_deref() == Some(Path::new(STDIO)) {
+        return Err(CirclezError::Batch(String::from(
+            "several results can't be written to standard output",
+        )));
+    }
+
+    let (paths, skipped) = batch_targets(&args.target)?;
+
+    if paths.is_empty() {
+        return Err(CirclezError::Batch(String::from("none of the targets are images")));
+    }
+
+    let dir = args.output.clone().unwrap_or_else(|| PathBuf::from(OUTPUT_DIR));
+    let extension = args.format.map_or("jpg", OutputFormat::extension);
+
+    // Each image gets a run of its own, which only reports failures
+    let job = Args {
+        headless: true,
+        progress: false,
+        quiet: true,
+        ..args.clone()
+    };
+
+    let progress = args.progress.then(|| {
+        let progress = ProgressBar::new(paths.len() as u64);
+        progress.set_style(
+            ProgressStyle::with_template("{wide_bar} {pos}/{len} images, ETA {eta}")
+                .expect("progress template is valid"),
+        );
+        progress
+    });
+
+    // Messages are printed above the progress bar rather than through it
+    let print = |message: &dyn Fn()| match &progress {
+        Some(progress) => progress.suspend(message),
+        None => message(),
+    };
+
+    let started = Instant::now();
+
+    let failed = run_jobs(args, &paths, |path| {
+        let output = dir.join(output_name(path, extension));
+        let started = Instant::now();
+        let result = approximate(&job, path, &output);
+
+        match &result {
+            Ok(approximator) => print(&|| {
+                args.status(format_args!(
+                    "Saved {} to: {} in {:.2?} (PSNR: {:.2} dB)",
+                    path.display(),
+                    output.display(),
+                    started.elapsed(),
+                    circlez::psnr(approximator.target(), approximator.approx())
+                ))
+            }),
+            Err(err) => print(&|| eprintln!("{}", err)),
+        }
+
+        if let Some(progress) = &progress {
+            progress.inc(1);
+        }
+
+        result.is_err()
+    })?
+    .into_iter()
+    .filter(|&failed| failed)
+    .count();
+
+    if let Some(progress) = &progress {
+        progress.finish_and_clear();
+    }
+
+    let approximated = paths.len() - failed;
+    args.status(format_args!(
+        "Approximated {} image{} in {:.2?}",
+        approximated,
+        if approximated == 1 { "" } else { "s" },
+        started.elapsed()
+    ));
+
+    if skipped > 0 {
+        args.status(format_args!(
+            "Skipped {} non-image file{}",
+            skipped,
+            if skipped == 1 { "" } else { "s" }
+        ));
+    }
+
+    if failed > 0 {
+        return Err(CirclezError::BatchFailed {
+            failed,
+            total: paths.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Runs `work` on every one of `items`, `--jobs` at a time, and returns
+/// what it returned for each, in order.
+///
+/// Every job gets a thread pool of `--threads` threads of its own for its
+/// tiles and loss evaluations, so running jobs don't compete for each
+/// other's threads; without `--jobs`, as many run at once as fit on the
+/// CPUs that way.
+fn run_jobs<T: Sync, R: Send>(
+    args: &Args,
+    items: &[T],
+    work: impl Fn(&T) -> R + Sync,
+) -> Result<Vec<R>, CirclezError> {
+    let cpus = cpu_count();
+    let jobs = args.jobs.unwrap_or((cpus / args.threads).max(1));
+
+    if args.jobs.is_some() && jobs * args.threads > cpus && !args.quiet {
+        eprintln!(
+            "Note: {} jobs of {} threads each run {} threads at once on {} CPUs, which slows them all down",
+            jobs,
+            args.threads,
+            jobs * args.threads,
+            cpus
+        );
+    }
+
+    let pools = (0..jobs.min(items.len() as u64))
+        .map(|_| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(args.threads as usize)
+                .build()
+                .map_err(|source| CirclezError::Threads {
+                    threads: args.threads,
+                    source,
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Each job takes the next item as soon as it's done with the last one
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(items.len()));
+
+    std::thread::scope(|scope| {
+        for pool in &pools {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+
+                let result = pool.install(|| work(item));
+                results.lock().expect("no job panicked").push((index, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().expect("no job panicked");
+    results.sort_unstable_by_key(|&(index, _)| index);
+
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// Returns the first of the options given in `args` that only make sense
+/// when approximating a single image.
+fn single_image_flag(args: &Args) -> Option<&'static str> {
+    let flags = [
+        ("--svg", args.svg.is_some()),
+        ("--diff", args.diff.is_some()),
+        ("--compare", args.compare.is_some()),
+        ("--shapes-json", args.shapes_json.is_some()),
+        ("--gif", args.gif.is_some()),
+        ("--save-every", args.save_every.is_some()),
+        ("--importance-map", args.importance_map.is_some()),
+        ("--mask", args.mask.is_some()),
+        ("--checkpoint", args.checkpoint.is_some()),
+        ("--resume", args.resume.is_some()),
+    ];
+
+    flags.into_iter().find(|(_, given)| *given).map(|(flag, _)| flag)
+}
+
+/// Approximates every frame of the animated GIF target on its own,
+/// `--jobs` at a time, and saves the results as an animated GIF with the
+/// frames' original timing.
+///
+/// Every frame starts from the same seed, so shapes land in similar places
+/// in similar frames, but nothing ties consecutive frames together, so the
+/// result may flicker. With `--warm-start`, frames instead go one at a time,
+/// each carrying on from the approximation of the one before.
+fn run_animation(args: &Args) -> Result<(), CirclezError> {
+    let target_path = match &args.target[..] {
+        [path] if !is_batch(&args.target) && path != Path::new(STDIO) => path,
+        _ => {
+            let reason = String::from("the target must be a single GIF file");
+            return Err(CirclezError::Animation(reason));
+        }
+    };
+
+    let still_only = (args.format.map(|_| "--format"))
+        .or(args.transparent_bg.then_some("--transparent-bg"));
+
+    if let Some(flag) = single_image_flag(args).or(still_only) {
+        return Err(CirclezError::Animation(format!("{} only works with still images", flag)));
+    }
+
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| Path::new(OUTPUT_DIR).join(output_name(target_path, "gif")));
+
+    if ImageFormat::from_path(&output).ok() != Some(ImageFormat::Gif) {
+        return Err(CirclezError::Animation(format!(
+            "{} must be a .gif file, the only animated format supported",
+            describe(&output)
+        )));
+    }
+
+    let frames = load_frames(target_path)?;
+    args.status(format_args!("Approximating {} frames", frames.len()));
+
+    // Frames that start from the same seed draw similar shapes where they're alike
+    let seed = args.seed.unwrap_or_else(|| ChaCha12Rng::from_os_rng().random());
+    let job = Args {
+        headless: true,
+        progress: false,
+        quiet: true,
+        seed: Some(seed),
+        ..args.clone()
+    };
+
+    let progress = args.progress.then(|| {
+        let progress = ProgressBar::new(frames.len() as u64);
+        progress.set_style(
+            ProgressStyle::with_template("{wide_bar} {pos}/{len} frames, ETA {eta}")
+                .expect("progress template is valid"),
+        );
+        progress
+    });
+
+    let started = Instant::now();
+
+    let run = |approximator: &mut Approximator| -> Result<RgbaImage, CirclezError> {
+        let early_stop = EarlyStop::new(&job);
+        let iterations = early_stop.headless_iterations(&job, approximator);
+
+        run_headless(
+            approximator,
+            iterations,
+            job.threads as usize,
+            &early_stop,
+            &mut Observers::default(),
+        )?;
+
+        if let Some(progress) = &progress {
+            progress.inc(1);
+        }
+
+        Ok(DynamicImage::from(approximator.approx()).into_rgba8())
+    };
+
+    let approximated = if args.warm_start {
+        // Each frame carries on from the last one, so they go one at a time
+        let mut last: Option<Approximator> = None;
+
+        frames
+            .into_iter()
+            .map(|frame| {
+                let delay = frame.delay();
+                let target = DynamicImage::ImageRgba8(frame.into_buffer());
+                let mut approximator = match last.take() {
+                    Some(mut approximator) => {
+                        approximator
+                            .retarget(convert_target(&job, target))
+                            .map_err(CirclezError::Animation)?;
+                        approximator
+                    }
+                    None => prepare(&job, target)?,
+                };
+
+                let image = run(&mut approximator)?;
+                last = Some(approximator);
+
+                Ok(Frame::from_parts(image, 0, 0, delay))
+            })
+            .collect::<Result<Vec<_>, CirclezError>>()
+    } else {
+        run_jobs(args, &frames, |frame| {
+            let delay = frame.delay();
+            let target = DynamicImage::ImageRgba8(frame.buffer().clone());
+            let mut approximator = prepare(&job, target)?;
+
+            Ok(Frame::from_parts(run(&mut approximator)?, 0, 0, delay))
+        })?
+        .into_iter()
+        .collect::<Result<Vec<_>, CirclezError>>()
+    };
+
+    if let Some(progress) = &progress {
+        progress.finish_and_clear();
+    }
+
+    save_frames(&output, approximated?)?;
+    args.status(format_args!(
+        "Saved animation to: {} in {:.2?}",
+        output.display(),
+        started.elapsed()
+    ));
+
+    Ok(())
+}
+
+/// Decodes every frame of the animated GIF at `path`, each composited onto
+/// the ones before it as it's shown.
+fn load_frames(path: &Path) -> Result<Vec<Frame>, CirclezError> {
+    File::open(path)
+        .map_err(ImageError::IoError)
+        .and_then(|file| GifDecoder::new(BufReader::new(file)))
+        .and_then(|decoder| decoder.into_frames().collect_frames())
+        .map_err(|source| CirclezError::LoadImage {
+            path: path.to_owned(),
+            source,
+        })
+}
+
+/// Encodes `frames` into a looping GIF at `path`.
+fn save_frames(path: &Path, frames: Vec<Frame>) -> Result<(), CirclezError> {
+    let encode = || -> ImageResult<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)
+    };
+
+    encode().map_err(|source| CirclezError::SaveImage {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Lists the image files among `targets`, going through directories and
+/// matching globs, along with how many other files were skipped.
+///
+/// Files count as images by their extension; ones that turn out not to
+/// decode fail when they're loaded instead.
+fn batch_targets(targets: &[PathBuf]) -> Result<(Vec<PathBuf>, usize), CirclezError> {
+    let mut files = Vec::new();
+
+    for target in targets {
+        if target.is_dir() {
+            files.extend(list_files(target, |_| true)?);
+        } else if is_glob(target) && !target.exists() {
+            let name = target.file_name().unwrap_or_default().to_string_lossy();
+            let pattern: Vec<char> = name.chars().collect();
+            let dir = match target.parent() {
+                Some(parent) if parent != Path::new("") => parent,
+                _ => Path::new("."),
+            };
+
+            files.extend(list_files(dir, |name| {
+                glob_matches(&pattern, &name.chars().collect::<Vec<_>>())
+            })?);
+        } else {
+            files.push(target.clone());
+        }
+    }
+
+    let listed = files.len();
+    files.retain(|path| ImageFormat::from_path(path).is_ok());
+
+    let skipped = listed - files.len();
+    Ok((files, skipped))
+}
+
+/// Returns the files directly inside `dir` whose names pass `filter`, sorted
+/// by path.
+fn list_files(dir: &Path, filter: impl Fn(&str) -> bool) -> Result<Vec<PathBuf>, CirclezError> {
+    let read_error = |source| CirclezError::Read {
+        path: dir.to_owned(),
+        source,
+    };
+
+    let mut files = Vec::new();
+
+    for entry in std::fs::read_dir(dir).map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+
+        if path.is_file() && filter(&path.file_name().unwrap_or_default().to_string_lossy()) {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Returns whether the file name of `path` has wildcards in it.
+fn is_glob(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().contains(['*', '?']))
+}
+
+/// Returns whether `name` matches `pattern`, where `*` stands for any run
+/// of characters and `?` for any single one.
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some(('*', rest)), _) => {
+            glob_matches(rest, name) || (!name.is_empty() && glob_matches(pattern, &name[1..]))
+        }
+        (Some(_), None) => false,
+        (Some((&wanted, rest)), Some((&c, name_rest))) => {
+            (wanted == '?' || wanted == c) && glob_matches(rest, name_rest)
+        }
+    }
+}
+
+/// Redraws the shapes recorded in the JSON file at `path` and saves them like
+/// a regular run would.
+fn replay(args: &Args, path: &Path) -> Result<(), CirclezError> {
+    let json = std::fs::read_to_string(path).map_err(|source| CirclezError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    let recording: Recording =
+        serde_json::from_str(&json).map_err(|source| CirclezError::ParseRecording {
+            path: path.to_owned(),
+            source,
+        })?;
+
+    let image = recording.render();
+    let output = args.output.clone().unwrap_or_else(|| default_output(path));
+    check_transparency(args, &output)?;
+
+    let config = recording.config();
+
+    if args.upscale > 1 {
+        let upscaled = recording.render_scaled(args.upscale);
+        save(&output, args.format, &result_image(args, &config, &upscaled))?;
+    } else {
+        save(&output, args.format, &result_image(args, &config, &image))?;
+    }
+    args.status(format_args!("Saved replayed image to: {}", describe(&output)));
+
+    if let Some(svg_path) = &args.svg {
+        let placed: Vec<_> = recording.shapes.iter().map(|recorded| recorded.placed).collect();
+        let svg = circlez::svg(&image, &placed, &recording.config());
+
+        save_text(svg_path, &svg)?;
+        args.status(format_args!("Saved shapes to: {}", svg_path.display()));
+    }
+
+    Ok(())
+}
+
+/// How many ticks a `--shapes` run may spend per shape before giving up.
+const MAX_TICKS_PER_SHAPE: usize = 1000;
+
+/// How many ticks per thread parallel runs go between checks of
+/// `--target-loss`, since every check means merging the tiles.
+const PARALLEL_CHECK_TICKS: usize = 256;
+
+/// How many ticks per thread runs go between looks at the clock for
+/// `--max-time`, which they overshoot by however long that many ticks take:
+/// a few milliseconds usually, a tenth of a second with very large shapes.
+const CLOCK_CHECK_TICKS: usize = 256;
+
+/// Conditions that end a run before its last tick.
+struct EarlyStop {
+    /// How often `target_loss` needs checking, in ticks.
+    check_every: usize,
+    shapes: Option<usize>,
+    target_loss: Option<f64>,
+    stall: Option<usize>,
+    /// When `--max-time` runs out.
+    deadline: Option<Instant>,
+    /// How often `deadline` needs checking, in ticks.
+    clock_every: usize,
+}
+
+impl EarlyStop {
+    /// Returns the limits `args` set, with `--max-time` counting from now.
+    fn new(args: &Args) -> Self {
+        let threads = args.threads as usize;
+
+        Self {
+            check_every: if threads > 1 { threads * PARALLEL_CHECK_TICKS } else { 1 },
+            shapes: args.shapes.map(|shapes| shapes as usize),
+            target_loss: args.target_loss,
+            stall: args.stall.map(|stall| stall as usize),
+            deadline: args.max_time.map(|max_time| Instant::now() + max_time),
+            clock_every: threads * CLOCK_CHECK_TICKS,
+        }
+    }
+
+    /// Returns how many ticks a headless run goes for at most, telling
+    /// `approximator` the budget if it's a fixed number of them.
+    fn headless_iterations(&self, args: &Args, approximator: &mut Approximator) -> usize {
+        match self.shapes {
+            Some(shapes) => shapes.saturating_mul(MAX_TICKS_PER_SHAPE),
+            None => {
+                approximator.set_budget(args.iterations);
+                args.iterations
+            }
+        }
+    }
+
+    /// Returns why the run should stop now, if it should.
+    fn reason(&self, approximator: &Approximator) -> Option<&'static str> {
+        if self.shapes.is_some_and(|shapes| approximator.shapes().len() >= shapes) {
+            Some("drew --shapes shapes")
+        } else if self.target_loss.is_some_and(|goal| approximator.approx().total_loss() < goal) {
+            Some("the loss dropped below --target-loss")
+        } else if self.stall.is_some_and(|stall| approximator.ticks_since_improvement() >= stall) {
+            Some("the loss stalled for --stall ticks")
+        } else if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Some("ran for --max-time")
+        } else {
+            None
+        }
+    }
+
+    /// Returns how many ticks can run before a condition could trigger.
+    fn until_check(&self, approximator: &Approximator) -> Option<usize> {
+        if self.target_loss.is_some() {
+            return Some(self.check_every);
+        }
+
+        let shapes = self
+            .shapes
+            .map(|shapes| shapes.saturating_sub(approximator.shapes().len()));
+        let stall = self
+            .stall
+            .map(|stall| stall.saturating_sub(approximator.ticks_since_improvement()));
+
+        let clock = self.deadline.map(|_| self.clock_every);
+
+        [shapes, stall, clock].into_iter().flatten().min().map(|ticks| ticks.max(1))
+    }
+}
+
+/// Everything that watches a run as it goes.
+#[derive(Default)]
+struct Observers {
+    gif: Option<GifRecorder>,
+    frames: Option<FrameWriter>,
+    progress: Option<ProgressBar>,
+    checkpoints: Option<CheckpointWriter>,
+    stats: Option<Stats>,
+}
+
+impl Observers {
+    /// Moves the progress bar, if any, up to `approximator`'s state.
+    fn update_progress(&self, approximator: &Approximator) {
+        if let Some(progress) = &self.progress {
+            progress.set_position(approximator.ticks() as u64);
+            progress.set_message(format!("{:.0}", approximator.approx().total_loss()));
+        }
+    }
+}
+
+/// Logs, at the debug level, how many ticks got a shape accepted and how
+/// much those shapes lowered the loss on average, every `--stats-every`
+/// ticks; a falling rate suggests shrinking the radii or stopping.
+struct Stats {
+    every: usize,
+    /// The ticks, accepted ticks and their total loss delta, shapes drawn
+    /// (including pruned ones) and loss as of the last report.
+    last: (usize, (usize, f64), usize, f64),
+}
+
+impl Stats {
+    fn new(approximator: &Approximator, every: usize) -> Self {
+        Self {
+            every,
+            last: Self::current(approximator),
+        }
+    }
+
+    fn current(approximator: &Approximator) -> (usize, (usize, f64), usize, f64) {
+        (
+            approximator.ticks(),
+            approximator.accepted(),
+            approximator.shapes().len() + approximator.pruned(),
+            approximator.approx().total_loss(),
+        )
+    }
+
+    /// Returns how many ticks can run before the next report is due.
+    fn until_report(&self, ticks: usize) -> usize {
+        (self.last.0 + self.every).saturating_sub(ticks).max(1)
+    }
+
+    /// Logs a report if one is due.
+    fn observe(&mut self, approximator: &Approximator) {
+        let current = Self::current(approximator);
+        let (ticks, (accepted, accepted_delta), drawn, loss) = current;
+        let (last_ticks, (last_accepted, last_accepted_delta), last_drawn, last_loss) = self.last;
+
+        if ticks < last_ticks + self.every {
+            return;
+        }
+
+        let (ran, accepted) = (ticks - last_ticks, accepted - last_accepted);
+        let mean_delta = match accepted {
+            0 => 0.0,
+            accepted => (accepted_delta - last_accepted_delta) / accepted as f64,
+        };
+
+        debug!(
+            "ticks {}-{}: accepted {} ({:.1}%), {:+.0} loss each on average; \
+             drew {} shapes, loss {:.0} ({:+.0}), temperature {:.3}, \
+             {} ticks since the loss went down",
+            last_ticks + 1,
+            ticks,
+            accepted,
+            100.0 * accepted as f64 / ran as f64,
+            mean_delta,
+            drawn.saturating_sub(last_drawn),
+            loss,
+            loss - last_loss,
+            approximator.temperature(),
+            approximator.ticks_since_improvement()
+        );
+
+        self.last = current;
+    }
+}
+
+/// How many ticks (per thread) headless runs go between progress bar updates.
+const PROGRESS_INTERVAL: usize = 64;
+
+/// Returns a progress bar for a run of `iterations` ticks, or an open-ended
+/// counter if the length isn't known.
+fn progress_bar(iterations: Option<usize>) -> ProgressBar {
+    let (progress, template) = match iterations {
+        Some(iterations) => (
+            ProgressBar::new(iterations as u64),
+            "{wide_bar} {pos}/{len} ({percent}%), loss {msg}, ETA {eta}",
+        ),
+        None => (ProgressBar::new_spinner(), "{spinner} {pos} ticks, loss {msg}"),
+    };
+
+    progress.set_style(ProgressStyle::with_template(template).expect("progress template is valid"));
+    progress
+}
+
+/// Runs `iterations` ticks, or fewer if `early_stop` says so, stopping along
+/// the way whenever one of the `observers` wants a look.
+fn run_headless(
+    approximator: &mut Approximator,
+    iterations: usize,
+    threads: usize,
+    early_stop: &EarlyStop,
+    observers: &mut Observers,
+) -> Result<(), CirclezError> {
+    while approximator.ticks() < iterations && early_stop.reason(approximator).is_none() {
+        let ticks = approximator.ticks();
+        let remaining = iterations - ticks;
+
+        let chunk = [
+            early_stop.until_check(approximator),
+            observers.gif.as_ref().map(|gif| gif.until_frame(ticks)),
+            observers.frames.as_ref().map(|frames| frames.until_frame(ticks)),
+            observers.checkpoints.as_ref().map(|checkpoints| checkpoints.until_save(ticks)),
+            observers.progress.as_ref().map(|_| PROGRESS_INTERVAL * threads),
+            observers.stats.as_ref().map(|stats| stats.until_report(ticks)),
+        ]
+        .into_iter()
+        .flatten()
+        .fold(remaining, usize::min);
+
+        approximator.run_parallel(chunk, threads);
+        observers.update_progress(approximator);
+
+        if let Some(gif) = &mut observers.gif {
+            gif.observe(approximator);
+        }
+
+        if let Some(frames) = &mut observers.frames {
+            frames.observe(approximator.ticks(), approximator.approx())?;
+        }
+
+        if let Some(checkpoints) = &mut observers.checkpoints {
+            checkpoints.observe(approximator)?;
+        }
+
+        if let Some(stats) = &mut observers.stats {
+            stats.observe(approximator);
+        }
+    }
+
+    Ok(())
+}
+
+/// Shortest time between two redraws of the window; redraws that come
+/// sooner are skipped, so fast runs don't spend their time drawing frames
+/// nobody gets to see.
+#[cfg(feature = "gui")]
+const MIN_REDRAW_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Shows the approximation live until the window is closed, Escape is
+/// pressed or `early_stop` says so, running up to `--iterations` ticks
+/// between checks for input and redrawing every `--redraw-every` ticks.
+///
+/// The window shows the image `--window-scale` times its size, or as large
+/// as fits in [`MAX_WINDOW`], up to its own size, without one.
+///
+/// Pressing S hands the current approximation to `save_snapshot`, while the
+/// run carries on, and Space pauses or resumes ticking; paused windows still
+/// redraw and take input, but sleep in between. The mouse wheel and drags
+/// zoom and pan the view (see [`Viewport`]), and R resets it.
+#[cfg(feature = "gui")]
+fn run_windowed(
+    approximator: &mut Approximator,
+    args: &Args,
+    threads: usize,
+    early_stop: &EarlyStop,
+    observers: &mut Observers,
+    save_snapshot: &dyn Fn(&Image) -> Result<(), CirclezError>,
+) -> Result<(), CirclezError> {
+    let width = approximator.target().width;
+    let height = approximator.target().height;
+    let iterations = args.iterations;
+    let redraw_every = args.redraw_every.map_or(iterations, |ticks| ticks as usize);
+
+    // Windows larger than the screen fail to open or can't be used, and
+    // minifb can't say how large the screen is
+    let scale = args.window_scale.unwrap_or_else(|| {
+        let (max_width, max_height) = MAX_WINDOW;
+        (max_width as f32 / width as f32).min(max_height as f32 / height as f32).min(1.0)
+    });
+
+    let mut canvas = vec![0; (width * height) as usize];
+    let mut viewport = Viewport::new(width, height, scale);
+    let (window_width, window_height) = viewport.window_size();
+    let mut view = vec![0; window_width * window_height];
+    let mut redraws = 0;
+
+    let mut window = Window::new("circlez", window_width, window_height, WindowOptions::default())?;
+
+    // Redraws are throttled below instead, without sleeping in between
+    window.set_target_fps(0);
+
+    let mut changed = true;
+    let mut redrawn_at = 0;
+    let mut last_redraw: Option<Instant> = None;
+    let mut paused = false;
+
+    while window.is_open()
+        && !window.is_key_down(Key::Escape)
+        && early_stop.reason(approximator).is_none()
+    {
+        if window.is_key_pressed(Key::S, KeyRepeat::No) {
+            save_snapshot(approximator.approx())?;
+        }
+
+        if window.is_key_pressed(Key::Space, KeyRepeat::No) {
+            paused = !paused;
+            window.set_title(if paused { "circlez (paused)" } else { "circlez" });
+        }
+
+        // Show the new view right away, rather than at the next redraw
+        if viewport.handle_input(&window) {
+            viewport.show(&canvas, &mut view, &mut window)?;
+        }
+
+        if paused {
+            if changed {
+                encode_canvas(approximator, &mut canvas);
+                changed = false;
+            }
+
+            viewport.show(&canvas, &mut view, &mut window)?;
+            std::thread::sleep(MIN_REDRAW_INTERVAL);
+            continue;
+        }
+
+        let chunk = match (redrawn_at + redraw_every).checked_sub(approximator.ticks()) {
+            Some(until_redraw) if until_redraw > 0 => iterations.min(until_redraw),
+            // Overdue, but the last redraw was too recent
+            _ => iterations,
+        };
+
+        changed |= approximator.run_parallel(chunk, threads);
+        observers.update_progress(approximator);
+
+        if let Some(gif) = &mut observers.gif {
+            gif.observe(approximator);
+        }
+
+        if let Some(stats) = &mut observers.stats {
+            stats.observe(approximator);
+        }
+
+        let due = approximator.ticks() >= redrawn_at + redraw_every
+            && last_redraw.is_none_or(|last| last.elapsed() >= MIN_REDRAW_INTERVAL);
+
+        if !due {
+            window.update();
+            continue;
+        }
+
+        if changed {
+            encode_canvas(approximator, &mut canvas);
+            changed = false;
+        }
+
+        viewport.show(&canvas, &mut view, &mut window)?;
+
+        redrawn_at = approximator.ticks();
+        last_redraw = Some(Instant::now());
+        redraws += 1;
+
+        if let Some(frames) = &mut observers.frames {
+            frames.observe(redraws, approximator.approx())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Builds without the `gui` feature have no window to run in.
+#[cfg(not(feature = "gui"))]
+fn run_windowed(
+    _: &mut Approximator,
+    _: &Args,
+    _: usize,
+    _: &EarlyStop,
+    _: &mut Observers,
+    _: &dyn Fn(&Image) -> Result<(), CirclezError>,
+) -> Result<(), CirclezError> {
+    Err(CirclezError::NoWindow)
+}
+
+/// Brings `canvas`, the window's copy of the approximation, up to date by
+/// encoding only the pixels that changed since the last time; debug builds
+/// check that against encoding every pixel.
+#[cfg(feature = "gui")]
+fn encode_canvas(approximator: &mut Approximator, canvas: &mut [u32]) {
+    approximator.encode_changes(canvas);
+
+    if cfg!(debug_assertions) {
+        let mut full = vec![0; canvas.len()];
+        approximator.approx().encode(&mut full);
+        assert!(full == canvas, "the canvas missed some changed pixels");
+    }
+}
+
+/// How much each step of the mouse wheel zooms in or out.
+#[cfg(feature = "gui")]
+const ZOOM_STEP: f32 = 1.25;
+
+/// How far the view zooms in at most, in window pixels per image pixel.
+const MAX_ZOOM: f32 = 32.0;
+
+/// The largest window opened without `--window-scale`, which most screens
+/// have room for along with the title bar and a taskbar.
+#[cfg(feature = "gui")]
+const MAX_WINDOW: (u32, u32) = (1600, 900);
+
+/// The part of the approximation the window shows, which can be zoomed in to
+/// look at details; the image itself keeps its resolution.
+#[cfg(feature = "gui")]
+struct Viewport {
+    width: u32,
+    height: u32,
+    /// Window pixels per image pixel when the whole image is in view, as
+    /// `--window-scale` says.
+    scale: f32,
+    /// Window pixels per image pixel, at least `scale`.
+    zoom: f32,
+    /// The point of the image shown in the top left corner of the window.
+    origin: [f32; 2],
+    /// Where the mouse was the last time it was checked, while dragging.
+    drag: Option<(f32, f32)>,
+}
+
+#[cfg(feature = "gui")]
+impl Viewport {
+    /// Returns a view of the whole `width`x`height` image, `scale` times its
+    /// size.
+    fn new(width: u32, height: u32, scale: f32) -> Self {
+        Self {
+            width,
+            height,
+            scale,
+            zoom: scale,
+            origin: [0.0, 0.0],
+            drag: None,
+        }
+    }
+
+    /// Returns the size of the window, in pixels.
+    fn window_size(&self) -> (usize, usize) {
+        let size = |size: u32| ((size as f32 * self.scale).round() as usize).max(1);
+        (size(self.width), size(self.height))
+    }
+
+    /// Zooms in or out around the mouse as the wheel turns, pans while the
+    /// left button is dragged and resets when R is pressed; returns whether
+    /// any of that changed the view.
+    fn handle_input(&mut self, window: &Window) -> bool {
+        let before = (self.zoom, self.origin);
+        let mouse = window.get_mouse_pos(MouseMode::Clamp);
+
+        if window.is_key_pressed(Key::R, KeyRepeat::No) {
+            self.zoom = self.scale;
+        }
+
+        if let (Some((_, scroll)), Some((x, y))) = (window.get_scroll_wheel(), mouse) {
+            if scroll != 0.0 {
+                let zoom = (self.zoom * ZOOM_STEP.powf(scroll.signum()))
+                    .clamp(self.scale, MAX_ZOOM.max(self.scale));
+
+                // Keep the point under the mouse where it is
+                self.origin[0] += x / self.zoom - x / zoom;
+                self.origin[1] += y / self.zoom - y / zoom;
+                self.zoom = zoom;
+            }
+        }
+
+        self.drag = match (window.get_mouse_down(MouseButton::Left), mouse) {
+            (true, Some((x, y))) => {
+                if let Some((from_x, from_y)) = self.drag {
+                    self.origin[0] -= (x - from_x) / self.zoom;
+                    self.origin[1] -= (y - from_y) / self.zoom;
+                }
+
+                Some((x, y))
+            }
+            _ => None,
+        };
+
+        // Never show anything past the edges of the image
+        let (window_width, window_height) = self.window_size();
+        let sizes = [(self.width, window_width), (self.height, window_height)];
+
+        for (origin, (size, shown)) in self.origin.iter_mut().zip(sizes) {
+            *origin = origin.clamp(0.0, (size as f32 - shown as f32 / self.zoom).max(0.0));
+        }
+
+        (self.zoom, self.origin) != before
+    }
+
+    /// Draws the visible part of `canvas`, the encoded image, to `window`,
+    /// scaling it into `view` first unless it's shown at its own size:
+    /// zoomed in, every image pixel becomes a block of window pixels, and
+    /// zoomed out, window pixels interpolate linearly between the image
+    /// pixels around them.
+    fn show(&self, canvas: &[u32], view: &mut [u32], window: &mut Window) -> Result<(), minifb::Error> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (window_width, window_height) = self.window_size();
+
+        if self.zoom == 1.0 && (window_width, window_height) == (width, height) {
+            return window.update_with_buffer(canvas, width, height);
+        }
+
+        // Where the center of window pixel `i` falls in the image
+        let source = |i: usize, origin: f32| origin + (i as f32 + 0.5) / self.zoom;
+
+        for (y, row) in view.chunks_exact_mut(window_width).enumerate() {
+            let source_y = source(y, self.origin[1]);
+
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let source_x = source(x, self.origin[0]);
+
+                *pixel = if self.zoom >= 1.0 {
+                    let x = (source_x as usize).min(width - 1);
+                    let y = (source_y as usize).min(height - 1);
+                    canvas[y * width + x]
+                } else {
+                    bilinear(canvas, width, height, source_x - 0.5, source_y - 0.5)
+                };
+            }
+        }
+
+        window.update_with_buffer(view, window_width, window_height)
+    }
+}
+
+/// Returns the `0RGB` pixel at `[x, y]` of the `width`x`height` `canvas`,
+/// interpolated linearly between the four pixels around it; points past the
+/// edges take the pixels on them.
+#[cfg(feature = "gui")]
+fn bilinear(canvas: &[u32], width: usize, height: usize, x: f32, y: f32) -> u32 {
+    let (x, y) = (x.clamp(0.0, (width - 1) as f32), y.clamp(0.0, (height - 1) as f32));
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+
+    let corners = [
+        (canvas[y0 * width + x0], (1.0 - fx) * (1.0 - fy)),
+        (canvas[y0 * width + x1], fx * (1.0 - fy)),
+        (canvas[y1 * width + x0], (1.0 - fx) * fy),
+        (canvas[y1 * width + x1], fx * fy),
+    ];
+
+    let [_, r, g, b] = [0, 1, 2, 3].map(|channel| {
+        let value: f32 = corners
+            .iter()
+            .map(|&(pixel, weight)| pixel.to_be_bytes()[channel] as f32 * weight)
+            .sum();
+
+        value.round() as u8
+    });
+
+    u32::from_be_bytes([0, r, g, b])
+}
+
+/// Saves numbered frames of a run into a directory, every `every` ticks or
+/// redraws.
+struct FrameWriter {
+    dir: PathBuf,
+    every: usize,
+    /// The tick or redraw the last frame was saved at.
+    last_frame: usize,
+    saved: usize,
+}
+
+impl FrameWriter {
+    fn new(dir: PathBuf, every: usize) -> Self {
+        Self {
+            dir,
+            every,
+            last_frame: 0,
+            saved: 0,
+        }
+    }
+
+    /// Returns how many ticks or redraws after `progress` the next frame is
+    /// due.
+    fn until_frame(&self, progress: usize) -> usize {
+        (self.last_frame + self.every).saturating_sub(progress).max(1)
+    }
+
+    /// Saves `approx` as the next frame if one is due at `progress`.
+    fn observe(&mut self, progress: usize, approx: &Image) -> Result<(), CirclezError> {
+        if progress < self.last_frame + self.every {
+            return Ok(());
+        }
+
+        self.last_frame = progress;
+        self.saved += 1;
+
+        let path = self.dir.join(format!("frame_{:05}.png", self.saved));
+        save(&path, Some(OutputFormat::Png), &DynamicImage::from(approx))
+    }
+}
+
+/// Saves the state of a run every `every` ticks, replacing the last save.
+struct CheckpointWriter {
+    path: PathBuf,
+    every: usize,
+    /// The tick the last checkpoint was saved at.
+    last_save: usize,
+}
+
+impl CheckpointWriter {
+    /// Starts saving checkpoints of a run that's `ticks` ticks in.
+    fn new(path: PathBuf, every: usize, ticks: usize) -> Self {
+        Self {
+            path,
+            every,
+            last_save: ticks,
+        }
+    }
+
+    /// Returns how many ticks after `ticks` the next checkpoint is due.
+    fn until_save(&self, ticks: usize) -> usize {
+        (self.last_save + self.every).saturating_sub(ticks).max(1)
+    }
+
+    /// Saves a checkpoint of `approximator` if one is due.
+    fn observe(&mut self, approximator: &Approximator) -> Result<(), CirclezError> {
+        if approximator.ticks() < self.last_save + self.every {
+            return Ok(());
+        }
+
+        self.last_save = approximator.ticks();
+        self.save(approximator)
+    }
+
+    /// Saves a checkpoint of `approximator` now.
+    ///
+    /// It's written next to the last one first and then moved over it, so
+    /// interrupting the run never leaves a half-written checkpoint behind.
+    fn save(&self, approximator: &Approximator) -> Result<(), CirclezError> {
+        let json = serde_json::to_string(&approximator.checkpoint())
+            .expect("checkpoints only contain serializable values");
+
+        let mut partial = self.path.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+
+        save_text(&partial, &json)?;
+        std::fs::rename(&partial, &self.path).map_err(|source| CirclezError::Write {
+            path: self.path.clone(),
+            source,
+        })
+    }
+}
+
+/// Collects frames of a run for an animated GIF, keeping at most
+/// `max_frames` of them evenly spread over the run.
+struct GifRecorder {
+    frames: Vec<RgbaImage>,
+    max_frames: usize,
+    delay_ms: u32,
+    /// Ticks between frames.
+    interval: usize,
+    /// The tick the last frame was taken at.
+    last_frame: usize,
+}
+
+impl GifRecorder {
+    fn new(interval: usize, max_frames: usize, delay_ms: u32) -> Self {
+        Self {
+            frames: Vec::new(),
+            max_frames,
+            delay_ms,
+            interval,
+            last_frame: 0,
+        }
+    }
+
+    /// Returns how many ticks after `ticks` the next frame is due.
+    fn until_frame(&self, ticks: usize) -> usize {
+        (self.last_frame + self.interval).saturating_sub(ticks).max(1)
+    }
+
+    /// Takes a frame of the approximation if one is due.
+    fn observe(&mut self, approximator: &Approximator) {
+        if approximator.ticks() < self.last_frame + self.interval {
+            return;
+        }
+
+        self.frames.push(DynamicImage::from(approximator.approx()).into_rgba8());
+        self.last_frame = approximator.ticks();
+
+        // Drop every other frame and slow down, so the frames stay evenly spread
+        if self.frames.len() > self.max_frames {
+            self.frames = std::mem::take(&mut self.frames).into_iter().step_by(2).collect();
+            self.interval *= 2;
+        }
+    }
+
+    /// Encodes the frames, followed by the final `approx`, into a looping
+    /// GIF at `path`.
+    fn save(mut self, path: &Path, approx: &Image) -> Result<(), CirclezError> {
+        self.frames.push(DynamicImage::from(approx).into_rgba8());
+
+        self.encode(path).map_err(|source| CirclezError::SaveImage {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    fn encode(self, path: &Path) -> ImageResult<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut encoder = GifEncoder::new(BufWriter::new(File::create(path)?));
+        encoder.set_repeat(Repeat::Infinite)?;
+
+        let delay = Delay::from_numer_denom_ms(self.delay_ms, 1);
+        encoder.encode_frames(
+            self.frames
+                .into_iter()
+                .map(|frame| Frame::from_parts(frame, 0, 0, delay)),
+        )
+    }
+}
+
+/// Returns where to save a snapshot of a run saving its result to `output`:
+/// next to it, with the current time in seconds since the Unix epoch added
+/// to its name, and a counter if a snapshot was already taken that second.
+fn snapshot_path(output: &Path) -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = output
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|n| {
+            let counter = if n == 1 { String::new() } else { format!("-{}", n) };
+            output.with_file_name(format!("{}_{}{}{}", stem, seconds, counter, extension))
+        })
+        .find(|path| !path.exists())
+        .expect("some counter is free")
+}
+
+/// Where results are saved unless `--output` says otherwise.
+const OUTPUT_DIR: &str = "generated_images";
+
+fn default_output(target_path: &Path) -> PathBuf {
+    Path::new(OUTPUT_DIR).join(output_name(target_path, "jpg"))
+}
+
+/// Returns the file name results for the target at `target_path` are saved
+/// under by default.
+fn output_name(target_path: &Path, extension: &str) -> String {
+    let input_stem = if target_path == Path::new(STDIO) {
+        "stdin".into()
+    } else {
+        target_path.file_stem().unwrap_or_default().to_string_lossy()
+    };
+
+    format!("{}_circlez.{}", input_stem, extension)
+}
+
+/// The path that stands for standard input as the target, or standard output
+/// as the output.
+const STDIO: &str = "-";
+
+/// Loads the target from `path`, or from standard input if it's `-`, in
+/// which case the format is guessed from the contents.
+fn load_target(path: &Path) -> Result<DynamicImage, CirclezError> {
+    if path != Path::new(STDIO) {
+        return load_image(path);
+    }
+
+    let mut bytes = Vec::new();
+
+    let reader = std::io::stdin()
+        .lock()
+        .read_to_end(&mut bytes)
+        .and_then(|_| ImageReader::new(Cursor::new(bytes)).with_guessed_format())
+        .map_err(|err| CirclezError::LoadStdin { source: ImageError::IoError(err) })?;
+
+    check_exr(&reader, "the target from standard input")?;
+    reader.decode().map_err(|source| CirclezError::LoadStdin { source })
+}
+
+fn load_image(path: &Path) -> Result<DynamicImage, CirclezError> {
+    let load_error = |source| CirclezError::LoadImage {
+        path: path.to_owned(),
+        source,
+    };
+
+    let reader = ImageReader::open(path).map_err(|err| load_error(ImageError::IoError(err)))?;
+    check_exr(&reader, &path.display().to_string())?;
+    reader.decode().map_err(load_error)
+}
+
+/// Fails for OpenEXR images without the `exr` feature, which `image` would
+/// only report as a format it doesn't support.
+fn check_exr<R: BufRead + Seek>(reader: &ImageReader<R>, source: &str) -> Result<(), CirclezError> {
+    if !cfg!(feature = "exr") && reader.format() == Some(ImageFormat::OpenExr) {
+        return Err(CirclezError::NoExr(source.to_owned()));
+    }
+
+    Ok(())
+}
+
+/// Reads a checkpoint saved with `--checkpoint` from `path`.
+fn load_checkpoint(path: &Path) -> Result<Checkpoint<ChaCha12Rng>, CirclezError> {
+    let json = std::fs::read_to_string(path).map_err(|source| CirclezError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    serde_json::from_str(&json).map_err(|source| CirclezError::ParseCheckpoint {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Reads the hex colors listed in the file at `path`.
+fn load_palette(path: &Path) -> Result<Vec<Color>, CirclezError> {
+    let text = std::fs::read_to_string(path).map_err(|source| CirclezError::Read {
+        path: path.to_owned(),
+        source,
+    })?;
+    let invalid = |reason| CirclezError::InvalidPalette {
+        path: path.to_owned(),
+        reason,
+    };
+
+    let palette = text
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| parse_color(entry).map_err(|err| invalid(format!("{:?} {}", entry, err))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if palette.is_empty() {
+        return Err(invalid(String::from("no colors listed")));
+    }
+
+    Ok(palette)
+}
+
+/// Returns how to refer to the output `path` in messages.
+fn describe(path: &Path) -> String {
+    if path == Path::new(STDIO) {
+        String::from("standard output")
+    } else {
+        path.display().to_string()
+    }
+}
+
+/// Returns `approx`, drawn as `config` says, as the result is saved:
+/// dithered with `--dither`, and with the pixels no shape was drawn onto
+/// transparent if `--transparent-bg` asks for it.
+fn result_image(args: &Args, config: &Config, approx: &Image) -> DynamicImage {
+    let dithered;
+    let approx = if args.dither {
+        let palette = match config.duotone {
+            Some(duotone) => duotone.to_vec(),
+            None => config.palette.clone(),
+        };
+
+        dithered = circlez::dither(approx, &palette);
+        &dithered
+    } else {
+        approx
+    };
+
+    if args.transparent_bg {
+        DynamicImage::ImageRgba8(approx.transparent_background())
+    } else {
+        DynamicImage::from(approx)
+    }
+}
+
+/// Checks that the result can be saved to `output` with transparency, if
+/// `--transparent-bg` asks for it, before any work is done.
+fn check_transparency(args: &Args, output: &Path) -> Result<(), CirclezError> {
+    if !args.transparent_bg {
+        return Ok(());
+    }
+
+    let format = match args.format {
+        Some(format) => Some(format.into()),
+        None if output == Path::new(STDIO) => Some(ImageFormat::Png),
+        None => ImageFormat::from_path(output).ok(),
+    };
+
+    match format {
+        Some(ImageFormat::Png | ImageFormat::WebP) => Ok(()),
+        _ => Err(CirclezError::NoTransparency(describe(output))),
+    }
+}
+
+fn save(
+    output: &Path,
+    format: Option<OutputFormat>,
+    image: &DynamicImage,
+) -> Result<(), CirclezError> {
+    if output == Path::new(STDIO) {
+        return write_stdout(format, image).map_err(|source| CirclezError::SaveStdout { source });
+    }
+
+    encode(output, format, image).map_err(|source| CirclezError::SaveImage {
+        path: output.to_owned(),
+        source,
+    })
+}
+
+fn encode(output: &Path, format: Option<OutputFormat>, image: &DynamicImage) -> ImageResult<()> {
+    // Pick the encoder before doing any work, so unsupported extensions fail early
+    let format = match format {
+        Some(format) => format.into(),
+        None => ImageFormat::from_path(output)?,
+    };
+
+    // Ensure the directory exists
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Save the image
+    image.save_with_format(output, format)
+}
+
+/// Writes `image` to standard output, as PNG unless `format` says otherwise.
+fn write_stdout(format: Option<OutputFormat>, image: &DynamicImage) -> ImageResult<()> {
+    let format = format.map_or(ImageFormat::Png, ImageFormat::from);
+
+    // Encoders need to seek, which pipes can't
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, format)?;
+
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(bytes.get_ref())?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn save_text(path: &Path, text: &str) -> Result<(), CirclezError> {
+    let write = || {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, text)
+    };
+
+    write().map_err(|source| CirclezError::Write {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use circlez::Primitive;
+    use std::collections::HashSet;
+
+    #[test]
+    fn saved_images_round_trip() {
+        let dir = std::env::temp_dir().join(format!("circlez-save-{}", std::process::id()));
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(37, 23, |x, y| {
+            [x as u8 * 6, y as u8 * 11, 128].into()
+        }));
+
+        for format in OutputFormat::value_variants().iter().copied() {
+            // Picked from the extension, and given explicitly for a path without one
+            let inferred = dir.join("result").with_extension(format.extension());
+            let explicit = dir.join(format!("{}-result", format.extension()));
+            save(&inferred, None, &image).unwrap();
+            save(&explicit, Some(format), &image).unwrap();
+
+            for path in [inferred, explicit] {
+                let reader = ImageReader::open(&path).unwrap().with_guessed_format().unwrap();
+                assert_eq!(reader.format(), Some(format.into()), "{}", path.display());
+
+                let decoded = reader.decode().unwrap();
+                assert_eq!((decoded.width(), decoded.height()), (37, 23), "{}", path.display());
+
+                if matches!(format, OutputFormat::Png | OutputFormat::Bmp) {
+                    assert_eq!(decoded.to_rgb8(), *image.as_rgb8().unwrap());
+                }
+            }
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn undrawn_pixels_keep_the_background() {
+        let dir = std::env::temp_dir().join(format!("circlez-background-{}", std::process::id()));
+        let target = dir.join("target.png");
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::from_fn(40, 30, |x, y| [x as u8 * 6, y as u8 * 8, 60].into())
+            .save(&target)
+            .unwrap();
+
+        let args = Args::parse_from([
+            "circlez",
+            target.to_str().unwrap(),
+            "--headless",
+            "--quiet",
+            "--iterations",
+            "40",
+            "--seed",
+            "1",
+            "--max-radius",
+            "4",
+            "--background",
+            "ffffff",
+        ]);
+        let approximator = approximate(&args, &target, &dir.join("result.png")).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let approx = approximator.approx();
+        let bounds = (approx.width, approx.height);
+        let drawn: HashSet<_> =
+            approximator.shapes().iter().flat_map(|placed| placed.shape.points(bounds)).collect();
+        assert!(!drawn.is_empty());
+
+        for y in 0..approx.height {
+            for x in 0..approx.width {
+                if !drawn.contains(&[x as isize, y as isize]) {
+                    assert_eq!(approx.color_at([x, y]), [255; 3], "at {x},{y}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn undrawn_pixels_are_saved_transparent() {
+        let dir = std::env::temp_dir().join(format!("circlez-transparent-{}", std::process::id()));
+        let (target, output) = (dir.join("target.png"), dir.join("result.png"));
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbImage::from_fn(40, 30, |x, y| [x as u8 * 6, y as u8 * 8, 60].into())
+            .save(&target)
+            .unwrap();
+
+        let args = Args::parse_from([
+            "circlez",
+            target.to_str().unwrap(),
+            "--headless",
+            "--quiet",
+            "--iterations",
+            "40",
+            "--seed",
+            "1",
+            "--max-radius",
+            "4",
+            "--transparent-bg",
+            "--output",
+            output.to_str().unwrap(),
+        ]);
+        let approximator = approximate(&args, &target, &output).unwrap();
+        let saved = ImageReader::open(&output).unwrap().decode().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let (approx, saved) = (approximator.approx(), saved.to_rgba8());
+        let untouched = saved.pixels().filter(|pixel| pixel.0[3] == 0).count();
+        assert!(0 < untouched && untouched < (saved.width() * saved.height()) as usize);
+
+        for (x, y, pixel) in saved.enumerate_pixels() {
+            let alpha = if approx.is_touched([x, y]) { 255 } else { 0 };
+            assert_eq!(pixel.0[3], alpha, "at {x},{y}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! The Z-order (Morton) layout [`Image`](crate::Image) stores its pixels in
+//! with the `morton` feature, where the bits of the x and y coordinates are
+//! interleaved so that pixels close to each other in both directions are
+//! usually close in memory too.
+//!
+//! Only images whose sides are powers of two can be laid out that way, so
+//! the layout covers the smallest such image that fits: the bits both sides
+//! have are interleaved, and the remaining ones of the longer side go on
+//! top. At worst that's four times the pixels, for images just over a power
+//! of two on both sides.
+//!
+//! It isn't faster: shapes are rasterized a row at a time, which rows
+//! already store in order, so the extra work of [`index`] outweighs
+//! the cache misses it saves (see `benches/layout.rs`).
+
+use crate::Point;
+
+/// Returns how many pixels a `width`x`height` image takes up.
+pub(crate) fn len(width: u32, height: u32) -> usize {
+    width.next_power_of_two() as usize * height.next_power_of_two() as usize
+}
+
+/// Returns the position of `[x, y]` among the pixels of a `width`x`height`
+/// image.
+pub(crate) fn index(width: u32, height: u32, [x, y]: Point) -> usize {
+    let (wide, high) = (width.next_power_of_two(), height.next_power_of_two());
+    let bits = wide.min(high).trailing_zeros();
+    let low = (1 << bits) - 1;
+
+    let square = spread(x & low) | spread(y & low) << 1;
+    let rest = if wide > high { x >> bits } else { y >> bits };
+
+    ((rest as u64) << (2 * bits) | square) as usize
+}
+
+/// Moves the bits of `v` to the even bits of the result.
+fn spread(v: u32) -> u64 {
+    let mut v = v as u64;
+
+    v = (v | v << 16) & 0x0000_ffff_0000_ffff;
+    v = (v | v << 8) & 0x00ff_00ff_00ff_00ff;
+    v = (v | v << 4) & 0x0f0f_0f0f_0f0f_0f0f;
+    v = (v | v << 2) & 0x3333_3333_3333_3333;
+    (v | v << 1) & 0x5555_5555_5555_5555
+}
+
+/// Rearranges `rows`, the pixels of a `width`x`height` image row by row with
+/// `channels` bytes each, into Z-order; the padding is left black.
+pub(crate) fn from_rows(width: u32, height: u32, channels: usize, rows: &[u8]) -> Vec<u8> {
+    let mut pixels = vec![0; len(width, height) * channels];
+
+    for (i, pixel) in rows.chunks_exact(channels).enumerate() {
+        let point = [i as u32 % width, i as u32 / width];
+        let offset = index(width, height, point) * channels;
+
+        pixels[offset..offset + channels].copy_from_slice(pixel);
+    }
+
+    pixels
+}
+
+/// Does the opposite of [`from_rows`].
+pub(crate) fn to_rows(width: u32, height: u32, channels: usize, pixels: &[u8]) -> Vec<u8> {
+    let mut rows = Vec::with_capacity(width as usize * height as usize * channels);
+
+    for y in 0..height {
+        for x in 0..width {
+            let offset = index(width, height, [x, y]) * channels;
+            rows.extend_from_slice(&pixels[offset..offset + channels]);
+        }
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_round_trip() {
+        for (width, height) in [(1, 1), (37, 23), (23, 37), (64, 5), (3, 129)] {
+            let rows: Vec<u8> = (0..width * height * 3).map(|i| (i * 31 % 251) as u8).collect();
+            let pixels = from_rows(width, height, 3, &rows);
+
+            assert_eq!(pixels.len(), len(width, height) * 3);
+            assert_eq!(to_rows(width, height, 3, &pixels), rows);
+        }
+    }
+
+    #[test]
+    fn every_pixel_has_its_own_index() {
+        let (width, height) = (37, 23);
+        let mut seen = vec![false; len(width, height)];
+
+        for y in 0..height {
+            for x in 0..width {
+                let index = index(width, height, [x, y]);
+                assert!(!seen[index], "{x},{y} shares index {index}");
+                seen[index] = true;
+            }
+        }
+    }
+}
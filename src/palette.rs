@@ -0,0 +1,180 @@
+use crate::{Color, Image};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+/// Seed for picking the starting centroids, so the same target always gets
+/// the same palette.
+const SEED: u64 = 0x5eed;
+
+/// How many rounds of k-means run at most before giving up on converging.
+const MAX_ROUNDS: usize = 100;
+
+/// How many distinct colors are clustered at most; targets with more have
+/// theirs merged by their top five bits per channel first.
+const MAX_COLORS: usize = 1 << 15;
+
+/// Picks `k` colors representative of `target` by k-means clustering of its
+/// pixels, most common first, to use as a
+/// [`Config::palette`](crate::Config::palette).
+///
+/// Clusters start from k-means++ seeds and are refined until no color
+/// changes cluster. Targets with fewer than `k` distinct colors get all of
+/// them, and the result is always the same for the same target.
+///
+/// Targets with more than 32768 distinct colors are clustered by the
+/// average colors of that many bins of similar colors instead, which keeps
+/// this fast.
+pub fn kmeans_palette(target: &Image, k: usize) -> Vec<Color> {
+    if k == 0 {
+        return Vec::new();
+    }
+
+    // Every distinct color once, weighted by how many pixels have it
+    let mut counts = HashMap::new();
+
+    for y in 0..target.height {
+        for x in 0..target.width {
+            *counts.entry(target.color_at([x, y])).or_insert(0u64) += 1;
+        }
+    }
+
+    let mut colors: Vec<(Color, u64)> = counts.into_iter().collect();
+    colors.sort_unstable();
+
+    if colors.len() <= k {
+        colors.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        return colors.into_iter().map(|(color, _)| color).collect();
+    }
+
+    let (points, weights): (Vec<[f64; 3]>, Vec<f64>) = if colors.len() <= MAX_COLORS {
+        colors
+            .iter()
+            .map(|&(color, count)| (color.map(|c| c as f64), count as f64))
+            .unzip()
+    } else {
+        let mut bins = vec![([0.0; 3], 0.0); MAX_COLORS];
+
+        for &(color, count) in &colors {
+            let [r, g, b] = color.map(|c| (c >> 3) as usize);
+            let (sum, total) = &mut bins[r << 10 | g << 5 | b];
+
+            for (sum, c) in sum.iter_mut().zip(color) {
+                *sum += c as f64 * count as f64;
+            }
+            *total += count as f64;
+        }
+
+        bins.into_iter()
+            .filter(|&(_, total)| total > 0.0)
+            .map(|(sum, total)| (sum.map(|sum| sum / total), total))
+            .unzip()
+    };
+
+    let mut centroids = seed_centroids(&points, &weights, k);
+    let mut assignment = vec![usize::MAX; points.len()];
+
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+
+        for (point, assigned) in points.iter().zip(&mut assignment) {
+            let nearest = nearest(&centroids, point);
+            changed |= *assigned != nearest;
+            *assigned = nearest;
+        }
+
+        if !changed {
+            break;
+        }
+
+        // Move every centroid to the weighted mean of its colors; empty
+        // clusters stay where they are
+        let mut sums = vec![([0.0; 3], 0.0); k];
+
+        for ((point, &weight), &cluster) in points.iter().zip(&weights).zip(&assignment) {
+            let (sum, total) = &mut sums[cluster];
+
+            for (sum, c) in sum.iter_mut().zip(point) {
+                *sum += c * weight;
+            }
+            *total += weight;
+        }
+
+        for (centroid, (sum, total)) in centroids.iter_mut().zip(sums) {
+            if total > 0.0 {
+                *centroid = sum.map(|sum| sum / total);
+            }
+        }
+    }
+
+    let mut sizes = vec![0.0; k];
+
+    for (&weight, &cluster) in weights.iter().zip(&assignment) {
+        sizes[cluster] += weight;
+    }
+
+    let mut palette: Vec<(Color, f64)> = centroids
+        .into_iter()
+        .map(|centroid| centroid.map(|c| c.round().clamp(0.0, 255.0) as u8))
+        .zip(sizes)
+        .collect();
+    palette.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    palette.into_iter().map(|(color, _)| color).collect()
+}
+
+/// Picks `k` starting centroids among `points` with k-means++: each one is
+/// drawn with probability proportional to its weight times its squared
+/// distance from the closest centroid picked so far.
+fn seed_centroids(points: &[[f64; 3]], weights: &[f64], k: usize) -> Vec<[f64; 3]> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut centroids = Vec::with_capacity(k);
+    let mut distances = vec![f64::INFINITY; points.len()];
+
+    while centroids.len() < k {
+        // The first centroid only goes by weight
+        let chances: Vec<f64> = if centroids.is_empty() {
+            weights.to_vec()
+        } else {
+            weights.iter().zip(&distances).map(|(w, d)| w * d).collect()
+        };
+        let total: f64 = chances.iter().sum();
+
+        // Only happens once every distinct color is a centroid
+        if total <= 0.0 {
+            break;
+        }
+
+        let mut pick = rng.random::<f64>() * total;
+        let chosen = chances
+            .iter()
+            .position(|&chance| {
+                pick -= chance;
+                pick < 0.0
+            })
+            .unwrap_or(points.len() - 1);
+
+        centroids.push(points[chosen]);
+
+        for (point, distance) in points.iter().zip(&mut distances) {
+            *distance = distance.min(squared_distance(point, &points[chosen]));
+        }
+    }
+
+    centroids
+}
+
+/// Returns the index of the centroid closest to `point`.
+fn nearest(centroids: &[[f64; 3]], point: &[f64; 3]) -> usize {
+    centroids
+        .iter()
+        .map(|centroid| squared_distance(centroid, point))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+        .expect("there is at least one centroid")
+}
+
+fn squared_distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (0..3).map(|i| (a[i] - b[i]).powi(2)).sum()
+}
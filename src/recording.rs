@@ -0,0 +1,160 @@
+use crate::shape::wide_ring;
+use crate::{Color, Config, Image, Init, PlacedShape, Point, Primitive, Shape};
+use serde::{Deserialize, Serialize};
+
+/// Everything needed to redraw an approximation: the canvas and the shapes
+/// placed on it, in drawing order.
+///
+/// This is what `--shapes-json` writes; get one from
+/// [`Approximator::recording`](crate::Approximator::recording).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub width: u32,
+    pub height: u32,
+    pub grayscale: bool,
+
+    /// The [`Config::opacity`](crate::Config::opacity) shapes were drawn
+    /// with.
+    pub opacity: f32,
+
+    /// The [`Config::antialias`](crate::Config::antialias) shapes were
+    /// drawn with.
+    pub antialias: bool,
+
+    /// The [`Config::linear`](crate::Config::linear) shapes were blended
+    /// with; recordings made before it was saved blended sRGB values.
+    #[serde(default)]
+    pub linear: bool,
+
+    /// The color of the canvas before the first shape; recordings made
+    /// before it was saved started from black.
+    #[serde(default)]
+    pub background: Color,
+
+    pub shapes: Vec<RecordedShape>,
+
+    /// The pixels the target's [mask](Image::set_mask) froze, and their
+    /// colors, which replace whatever the shapes drew there; recordings
+    /// made before it was saved had none.
+    #[serde(default)]
+    pub frozen: Vec<(Point, Color)>,
+}
+
+impl Recording {
+    /// Returns a [`Config`] that draws shapes the way they were recorded.
+    pub fn config(&self) -> Config {
+        Config {
+            opacity: self.opacity,
+            antialias: self.antialias,
+            linear: self.linear,
+            init: Init::Color(self.background),
+            ..Config::default()
+        }
+    }
+
+    /// Redraws the recorded shapes, in order, onto the recorded background,
+    /// then puts back the frozen pixels.
+    ///
+    /// The result has the same pixels as the approximation the recording
+    /// was made from. It doesn't approximate a target, so its
+    /// [`Image::total_loss`] is meaningless.
+    pub fn render(&self) -> Image {
+        let (width, height) = (self.width, self.height);
+        let config = self.config();
+        let mut image = Image::filled(width, height, self.grayscale, self.background);
+        image.set_linear(self.linear);
+
+        for recorded in &self.shapes {
+            let points = recorded.placed.shape.points((width, height));
+            image.paint(recorded.placed.changes(points, &config, width, height));
+        }
+
+        for (pos, color) in self.frozen_on_canvas() {
+            image.set_color(pos, color);
+        }
+
+        image
+    }
+
+    /// Redraws the recorded shapes like [`Recording::render`], onto a canvas
+    /// `factor` times as wide and as tall.
+    ///
+    /// The shapes are rasterized again at the larger size (see
+    /// [`Primitive::scale`]) rather than the pixels scaled up, so edges stay
+    /// sharp; rings and lines get `factor` times thicker, so the picture
+    /// looks the same from afar. Frozen pixels become `factor`x`factor`
+    /// blocks. A factor of `1` is just [`Recording::render`].
+    pub fn render_scaled(&self, factor: u32) -> Image {
+        if factor == 1 {
+            return self.render();
+        }
+
+        let (width, height) = (self.width * factor, self.height * factor);
+        let config = self.config();
+        let mut image = Image::filled(width, height, self.grayscale, self.background);
+        image.set_linear(self.linear);
+
+        for recorded in &self.shapes {
+            let placed = PlacedShape {
+                shape: recorded.placed.shape.scale(factor),
+                ..recorded.placed
+            };
+
+            match placed.shape {
+                // Scaled rings are still a pixel wide, so they're widened here
+                Shape::Circle(circle) if !circle.filled => {
+                    let changes = wide_ring(&circle, factor as isize)
+                        .into_iter()
+                        .filter(|&([x, y], _)| {
+                            x >= 0 && y >= 0 && x < width as isize && y < height as isize
+                        })
+                        .map(|([x, y], coverage)| {
+                            let alpha = if config.antialias {
+                                config.opacity * coverage
+                            } else {
+                                config.opacity
+                            };
+
+                            ([x as u32, y as u32], placed.color, alpha)
+                        });
+
+                    image.paint(changes);
+                }
+
+                _ => {
+                    let points = placed.shape.points((width, height));
+                    image.paint(placed.changes(points, &config, width, height));
+                }
+            }
+        }
+
+        for ([x, y], color) in self.frozen_on_canvas() {
+            for row in y * factor..(y + 1) * factor {
+                for col in x * factor..(x + 1) * factor {
+                    image.set_color([col, row], color);
+                }
+            }
+        }
+
+        image
+    }
+
+    /// Returns the frozen pixels that lie on the canvas, which those of
+    /// recordings read from a file may not.
+    fn frozen_on_canvas(&self) -> impl Iterator<Item = (Point, Color)> + '_ {
+        self.frozen
+            .iter()
+            .copied()
+            .filter(|&([x, y], _)| x < self.width && y < self.height)
+    }
+}
+
+/// A shape of a [`Recording`], along with when it was placed.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedShape {
+    #[serde(flatten)]
+    pub placed: PlacedShape,
+
+    /// The tick the shape was first accepted in, counting from 1.
+    pub tick: usize,
+}
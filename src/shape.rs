@@ -0,0 +1,377 @@
+mod blob;
+mod circle;
+mod dot;
+mod ellipse;
+mod line;
+mod polygon;
+mod rect;
+mod ring;
+mod triangle;
+
+pub use self::blob::Blob;
+pub use self::circle::{generate_circle_points, generate_filled_circle_points, Circle};
+pub use self::dot::Dot;
+pub use self::ellipse::{generate_ellipse_points, Ellipse};
+pub use self::line::{generate_line_points, Line};
+pub use self::polygon::{generate_polygon_points, Polygon};
+pub use self::rect::Rect;
+pub use self::ring::{generate_ring_points, Ring};
+pub use self::triangle::{generate_triangle_points, Triangle};
+
+pub(crate) use self::circle::wide_ring;
+
+use crate::Config;
+use clap::ValueEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A kind of shape [`tick`](crate::tick) can place, in image coordinates.
+///
+/// Each primitive lives in its own module with its rasterizer; [`Shape`]
+/// holds any of them, so that runs can mix them and recordings can store
+/// them.
+pub trait Primitive: Sized {
+    /// Samples a random shape somewhere on a `width`x`height` canvas, with a
+    /// radius within `radii`.
+    fn random(
+        config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self;
+
+    /// Replaces the contents of `points` with every pixel covered by the
+    /// shape, reusing its allocation.
+    ///
+    /// Shapes may be clipped to `bounds` (width, height) while being
+    /// rasterized, or include off-canvas points that callers need to filter
+    /// out.
+    fn points_into(&self, bounds: (u32, u32), points: &mut Vec<[isize; 2]>);
+
+    /// Returns every pixel covered by the shape; see
+    /// [`Primitive::points_into`].
+    fn points(&self, bounds: (u32, u32)) -> Vec<[isize; 2]> {
+        let mut points = Vec::new();
+        self.points_into(bounds, &mut points);
+        points
+    }
+
+    /// Returns the pixel the shape is centered on.
+    fn center(&self) -> [isize; 2];
+
+    /// Returns how far the shape extends from its center, used to decide
+    /// how much its edge contributes to the fill color.
+    fn radius(&self) -> isize;
+
+    /// Returns the smallest `[min_x, min_y, max_x, max_y]` box (inclusive)
+    /// containing every point of the shape.
+    fn bounding_box(&self) -> [isize; 4];
+
+    /// Returns a copy of the shape moved by `offset`.
+    fn translate(&self, offset: [isize; 2]) -> Self;
+
+    /// Returns a copy of the shape mirrored left to right on a canvas
+    /// `width` pixels wide.
+    fn mirror(&self, width: u32) -> Self;
+
+    /// Returns a copy of the shape turned clockwise by `angle` radians about
+    /// the center of a canvas of size `bounds` (width, height).
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self;
+
+    /// Returns a copy of the shape for a canvas `factor` times as wide and
+    /// as tall, covering the same part of the picture, with every pixel
+    /// turned into a `factor`x`factor` block.
+    fn scale(&self, factor: u32) -> Self;
+
+    /// Returns a copy with its position or size nudged a little, keeping
+    /// radii within the configured range.
+    fn perturb(&self, config: &Config, width: u32, height: u32, rng: &mut impl Rng) -> Self;
+
+    /// Returns roughly which fraction of the pixel at `point`, from `0.0` to
+    /// `1.0`, the shape covers.
+    ///
+    /// Only meaningful for pixels returned by [`Primitive::points`].
+    fn coverage(&self, point: [isize; 2]) -> f32;
+}
+
+/// The primitives [`tick`](crate::tick) knows how to place.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ShapeKind {
+    #[default]
+    Circle,
+    Rect,
+    Triangle,
+    Line,
+    Ellipse,
+    Polygon,
+    Blob,
+    Ring,
+    Dot,
+}
+
+/// A single placed primitive, of any kind.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Shape {
+    Circle(Circle),
+    Rect(Rect),
+    Triangle(Triangle),
+    Line(Line),
+    Ellipse(Ellipse),
+    Polygon(Polygon),
+    Blob(Blob),
+    Ring(Ring),
+    Dot(Dot),
+}
+
+impl Shape {
+    /// Returns the corners of a triangle, or of a polygon rounded to the
+    /// nearest pixel (see [`Polygon::vertices`]); other shapes have none.
+    pub fn vertices(&self) -> Vec<[isize; 2]> {
+        match self {
+            Shape::Triangle(triangle) => triangle.vertices.to_vec(),
+            Shape::Polygon(polygon) => polygon.vertices(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Primitive for Shape {
+    /// Samples a random shape of the configured kind (see
+    /// [`Config::pick_shape`]).
+    fn random(
+        config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        match config.pick_shape(rng) {
+            ShapeKind::Circle => Shape::Circle(Circle::random(config, radii, width, height, rng)),
+            ShapeKind::Rect => Shape::Rect(Rect::random(config, radii, width, height, rng)),
+            ShapeKind::Triangle => {
+                Shape::Triangle(Triangle::random(config, radii, width, height, rng))
+            }
+            ShapeKind::Line => Shape::Line(Line::random(config, radii, width, height, rng)),
+            ShapeKind::Ellipse => {
+                Shape::Ellipse(Ellipse::random(config, radii, width, height, rng))
+            }
+            ShapeKind::Polygon => {
+                Shape::Polygon(Polygon::random(config, radii, width, height, rng))
+            }
+            ShapeKind::Blob => Shape::Blob(Blob::random(config, radii, width, height, rng)),
+            ShapeKind::Ring => Shape::Ring(Ring::random(config, radii, width, height, rng)),
+            ShapeKind::Dot => Shape::Dot(Dot::random(config, radii, width, height, rng)),
+        }
+    }
+
+    fn points_into(&self, bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        match self {
+            Shape::Circle(circle) => circle.points_into(bounds, points),
+            Shape::Rect(rect) => rect.points_into(bounds, points),
+            Shape::Triangle(triangle) => triangle.points_into(bounds, points),
+            Shape::Line(line) => line.points_into(bounds, points),
+            Shape::Ellipse(ellipse) => ellipse.points_into(bounds, points),
+            Shape::Polygon(polygon) => polygon.points_into(bounds, points),
+            Shape::Blob(blob) => blob.points_into(bounds, points),
+            Shape::Ring(ring) => ring.points_into(bounds, points),
+            Shape::Dot(dot) => dot.points_into(bounds, points),
+        }
+    }
+
+    fn center(&self) -> [isize; 2] {
+        match self {
+            Shape::Circle(circle) => circle.center(),
+            Shape::Rect(rect) => rect.center(),
+            Shape::Triangle(triangle) => triangle.center(),
+            Shape::Line(line) => line.center(),
+            Shape::Ellipse(ellipse) => ellipse.center(),
+            Shape::Polygon(polygon) => polygon.center(),
+            Shape::Blob(blob) => blob.center(),
+            Shape::Ring(ring) => ring.center(),
+            Shape::Dot(dot) => dot.center(),
+        }
+    }
+
+    fn radius(&self) -> isize {
+        match self {
+            Shape::Circle(circle) => circle.radius(),
+            Shape::Rect(rect) => rect.radius(),
+            Shape::Triangle(triangle) => triangle.radius(),
+            Shape::Line(line) => line.radius(),
+            Shape::Ellipse(ellipse) => ellipse.radius(),
+            Shape::Polygon(polygon) => polygon.radius(),
+            Shape::Blob(blob) => blob.radius(),
+            Shape::Ring(ring) => ring.radius(),
+            Shape::Dot(dot) => dot.radius(),
+        }
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        match self {
+            Shape::Circle(circle) => circle.bounding_box(),
+            Shape::Rect(rect) => rect.bounding_box(),
+            Shape::Triangle(triangle) => triangle.bounding_box(),
+            Shape::Line(line) => line.bounding_box(),
+            Shape::Ellipse(ellipse) => ellipse.bounding_box(),
+            Shape::Polygon(polygon) => polygon.bounding_box(),
+            Shape::Blob(blob) => blob.bounding_box(),
+            Shape::Ring(ring) => ring.bounding_box(),
+            Shape::Dot(dot) => dot.bounding_box(),
+        }
+    }
+
+    fn translate(&self, offset: [isize; 2]) -> Self {
+        match self {
+            Shape::Circle(circle) => Shape::Circle(circle.translate(offset)),
+            Shape::Rect(rect) => Shape::Rect(rect.translate(offset)),
+            Shape::Triangle(triangle) => Shape::Triangle(triangle.translate(offset)),
+            Shape::Line(line) => Shape::Line(line.translate(offset)),
+            Shape::Ellipse(ellipse) => Shape::Ellipse(ellipse.translate(offset)),
+            Shape::Polygon(polygon) => Shape::Polygon(polygon.translate(offset)),
+            Shape::Blob(blob) => Shape::Blob(blob.translate(offset)),
+            Shape::Ring(ring) => Shape::Ring(ring.translate(offset)),
+            Shape::Dot(dot) => Shape::Dot(dot.translate(offset)),
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        match self {
+            Shape::Circle(circle) => Shape::Circle(circle.mirror(width)),
+            Shape::Rect(rect) => Shape::Rect(rect.mirror(width)),
+            Shape::Triangle(triangle) => Shape::Triangle(triangle.mirror(width)),
+            Shape::Line(line) => Shape::Line(line.mirror(width)),
+            Shape::Ellipse(ellipse) => Shape::Ellipse(ellipse.mirror(width)),
+            Shape::Polygon(polygon) => Shape::Polygon(polygon.mirror(width)),
+            Shape::Blob(blob) => Shape::Blob(blob.mirror(width)),
+            Shape::Ring(ring) => Shape::Ring(ring.mirror(width)),
+            Shape::Dot(dot) => Shape::Dot(dot.mirror(width)),
+        }
+    }
+
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self {
+        match self {
+            Shape::Circle(circle) => Shape::Circle(circle.rotate(bounds, angle)),
+            Shape::Rect(rect) => Shape::Rect(rect.rotate(bounds, angle)),
+            Shape::Triangle(triangle) => Shape::Triangle(triangle.rotate(bounds, angle)),
+            Shape::Line(line) => Shape::Line(line.rotate(bounds, angle)),
+            Shape::Ellipse(ellipse) => Shape::Ellipse(ellipse.rotate(bounds, angle)),
+            Shape::Polygon(polygon) => Shape::Polygon(polygon.rotate(bounds, angle)),
+            Shape::Blob(blob) => Shape::Blob(blob.rotate(bounds, angle)),
+            Shape::Ring(ring) => Shape::Ring(ring.rotate(bounds, angle)),
+            Shape::Dot(dot) => Shape::Dot(dot.rotate(bounds, angle)),
+        }
+    }
+
+    fn scale(&self, factor: u32) -> Self {
+        match self {
+            Shape::Circle(circle) => Shape::Circle(circle.scale(factor)),
+            Shape::Rect(rect) => Shape::Rect(rect.scale(factor)),
+            Shape::Triangle(triangle) => Shape::Triangle(triangle.scale(factor)),
+            Shape::Line(line) => Shape::Line(line.scale(factor)),
+            Shape::Ellipse(ellipse) => Shape::Ellipse(ellipse.scale(factor)),
+            Shape::Polygon(polygon) => Shape::Polygon(polygon.scale(factor)),
+            Shape::Blob(blob) => Shape::Blob(blob.scale(factor)),
+            Shape::Ring(ring) => Shape::Ring(ring.scale(factor)),
+            Shape::Dot(dot) => Shape::Dot(dot.scale(factor)),
+        }
+    }
+
+    fn perturb(&self, config: &Config, width: u32, height: u32, rng: &mut impl Rng) -> Self {
+        match self {
+            Shape::Circle(circle) => Shape::Circle(circle.perturb(config, width, height, rng)),
+            Shape::Rect(rect) => Shape::Rect(rect.perturb(config, width, height, rng)),
+            Shape::Triangle(triangle) => {
+                Shape::Triangle(triangle.perturb(config, width, height, rng))
+            }
+            Shape::Line(line) => Shape::Line(line.perturb(config, width, height, rng)),
+            Shape::Ellipse(ellipse) => Shape::Ellipse(ellipse.perturb(config, width, height, rng)),
+            Shape::Polygon(polygon) => Shape::Polygon(polygon.perturb(config, width, height, rng)),
+            Shape::Blob(blob) => Shape::Blob(blob.perturb(config, width, height, rng)),
+            Shape::Ring(ring) => Shape::Ring(ring.perturb(config, width, height, rng)),
+            Shape::Dot(dot) => Shape::Dot(dot.perturb(config, width, height, rng)),
+        }
+    }
+
+    fn coverage(&self, point: [isize; 2]) -> f32 {
+        match self {
+            Shape::Circle(circle) => circle.coverage(point),
+            Shape::Rect(rect) => rect.coverage(point),
+            Shape::Triangle(triangle) => triangle.coverage(point),
+            Shape::Line(line) => line.coverage(point),
+            Shape::Ellipse(ellipse) => ellipse.coverage(point),
+            Shape::Polygon(polygon) => polygon.coverage(point),
+            Shape::Blob(blob) => blob.coverage(point),
+            Shape::Ring(ring) => ring.coverage(point),
+            Shape::Dot(dot) => dot.coverage(point),
+        }
+    }
+}
+
+/// Returns a random pixel on a `width`x`height` canvas.
+fn random_position(width: u32, height: u32, rng: &mut impl Rng) -> [isize; 2] {
+    [
+        rng.random_range(0..width) as isize,
+        rng.random_range(0..height) as isize,
+    ]
+}
+
+/// Returns `point` mirrored left to right on a canvas `width` pixels wide.
+fn mirror_point([x, y]: [isize; 2], width: u32) -> [isize; 2] {
+    [width as isize - 1 - x, y]
+}
+
+/// Returns `point` turned clockwise by `angle` radians about the center of a
+/// canvas of size `bounds`, rounded to the nearest pixel.
+fn rotate_point([x, y]: [isize; 2], (width, height): (u32, u32), angle: f32) -> [isize; 2] {
+    let [center_x, center_y] = [(width as f32 - 1.0) / 2.0, (height as f32 - 1.0) / 2.0];
+    let [dx, dy] = [x as f32 - center_x, y as f32 - center_y];
+    let (sin, cos) = angle.sin_cos();
+
+    [
+        (center_x + dx * cos - dy * sin).round() as isize,
+        (center_y + dx * sin + dy * cos).round() as isize,
+    ]
+}
+
+/// Returns the pixel in the middle of the `factor`x`factor` block `point`
+/// turns into on a canvas `factor` times the size.
+fn scale_point([x, y]: [isize; 2], factor: u32) -> [isize; 2] {
+    let factor = factor as isize;
+    [x * factor + (factor - 1) / 2, y * factor + (factor - 1) / 2]
+}
+
+/// Returns how far [`Primitive::perturb`] moves a shape of the given radius:
+/// relative to its size, but always at least two pixels.
+fn step(radius: isize) -> isize {
+    (radius / 4).max(2)
+}
+
+/// Returns the smallest and largest radius perturbed shapes may have.
+fn radius_limits(config: &Config, width: u32, height: u32) -> (isize, isize) {
+    let radii = config.radius_range(width, height);
+    (*radii.start() as isize, *radii.end() as isize)
+}
+
+/// Moves `value` by up to `step` in either direction.
+fn nudge(value: isize, step: isize, rng: &mut impl Rng) -> isize {
+    value + rng.random_range(0..=2 * step as usize) as isize - step
+}
+
+/// Returns the smallest box containing `points`, grown by `margin` on every
+/// side.
+fn enclose(points: &[[isize; 2]], margin: isize) -> [isize; 4] {
+    points.iter().fold(
+        [isize::MAX, isize::MAX, isize::MIN, isize::MIN],
+        |[min_x, min_y, max_x, max_y], &[x, y]| {
+            [
+                min_x.min(x - margin),
+                min_y.min(y - margin),
+                max_x.max(x + margin),
+                max_y.max(y + margin),
+            ]
+        },
+    )
+}
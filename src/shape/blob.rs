@@ -0,0 +1,120 @@
+use super::circle::clipped_filled_circle_points_into;
+use super::{
+    mirror_point, nudge, radius_limits, random_position, rotate_point, scale_point, step, Primitive,
+};
+use crate::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A soft disk whose opacity falls off as a Gaussian of the distance from
+/// its center, cut off `radius` pixels out.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Blob {
+    pub center: [isize; 2],
+    pub radius: isize,
+    /// Standard deviation of the falloff, in pixels.
+    pub sigma: f32,
+}
+
+impl Primitive for Blob {
+    /// The falloff is [`Config::sigma`] times the radius wide.
+    fn random(
+        config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let center = random_position(width, height, rng);
+        let radius = rng.random_range(*radii.start() as usize..=*radii.end() as usize) as isize;
+
+        Blob {
+            center,
+            radius,
+            sigma: radius.max(1) as f32 * config.sigma,
+        }
+    }
+
+    /// Blobs cover the pixels of a solid disk, clipped to `bounds`.
+    fn points_into(&self, bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        let [x, y] = self.center;
+
+        points.clear();
+        clipped_filled_circle_points_into(x, y, self.radius, bounds, points);
+    }
+
+    fn center(&self) -> [isize; 2] {
+        self.center
+    }
+
+    fn radius(&self) -> isize {
+        self.radius
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        let ([x, y], radius) = (self.center, self.radius);
+        [x - radius, y - radius, x + radius, y + radius]
+    }
+
+    fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        let [x, y] = self.center;
+
+        Blob {
+            center: [x + dx, y + dy],
+            ..*self
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        Blob {
+            center: mirror_point(self.center, width),
+            ..*self
+        }
+    }
+
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self {
+        Blob {
+            center: rotate_point(self.center, bounds, angle),
+            ..*self
+        }
+    }
+
+    fn scale(&self, factor: u32) -> Self {
+        Blob {
+            center: scale_point(self.center, factor),
+            radius: self.radius * factor as isize + (factor as isize - 1) / 2,
+            sigma: self.sigma * factor as f32,
+        }
+    }
+
+    fn perturb(&self, config: &Config, width: u32, height: u32, rng: &mut impl Rng) -> Self {
+        let (min_radius, max_radius) = radius_limits(config, width, height);
+        let step = step(self.radius);
+        let [x, y] = self.center;
+
+        if rng.random_bool(0.5) {
+            Blob {
+                center: [nudge(x, step, rng), nudge(y, step, rng)],
+                ..*self
+            }
+        } else {
+            // The falloff keeps its proportions
+            let radius = nudge(self.radius, step, rng).clamp(min_radius, max_radius);
+
+            Blob {
+                center: self.center,
+                radius,
+                sigma: self.sigma * radius.max(1) as f32 / self.radius.max(1) as f32,
+            }
+        }
+    }
+
+    /// Blobs have no edge; this is how opaque their falloff is at the pixel.
+    fn coverage(&self, [x, y]: [isize; 2]) -> f32 {
+        let [cx, cy] = self.center;
+        let distance = (((x - cx).pow(2) + (y - cy).pow(2)) as f32).sqrt();
+
+        (-distance.powi(2) / (2.0 * self.sigma * self.sigma)).exp().clamp(0.0, 1.0)
+    }
+}
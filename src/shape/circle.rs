@@ -0,0 +1,506 @@
+use super::{
+    mirror_point, nudge, radius_limits, random_position, rotate_point, scale_point, step, Primitive,
+};
+use crate::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+/// A circle, drawn either as a one pixel wide ring or as a solid disk.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Circle {
+    pub center: [isize; 2],
+    pub radius: isize,
+    pub filled: bool,
+}
+
+impl Primitive for Circle {
+    fn random(
+        config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let center = random_position(width, height, rng);
+        let radius = rng.random_range(*radii.start() as usize..=*radii.end() as usize) as isize;
+
+        Circle {
+            center,
+            radius,
+            filled: config.fill,
+        }
+    }
+
+    /// Circles are clipped to `bounds` as they're generated, and don't
+    /// allocate at all once `points` is large enough.
+    fn points_into(&self, bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        let [x, y] = self.center;
+        points.clear();
+
+        if self.filled {
+            clipped_filled_circle_points_into(x, y, self.radius, bounds, points)
+        } else {
+            clipped_circle_points_into(x, y, self.radius, bounds, points)
+        }
+    }
+
+    fn center(&self) -> [isize; 2] {
+        self.center
+    }
+
+    fn radius(&self) -> isize {
+        self.radius
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        let ([x, y], radius) = (self.center, self.radius);
+        [x - radius, y - radius, x + radius, y + radius]
+    }
+
+    fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        let [x, y] = self.center;
+
+        Circle {
+            center: [x + dx, y + dy],
+            ..*self
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        Circle {
+            center: mirror_point(self.center, width),
+            ..*self
+        }
+    }
+
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self {
+        Circle {
+            center: rotate_point(self.center, bounds, angle),
+            ..*self
+        }
+    }
+
+    /// Disks grow to cover the same part of the picture, but rings stay one
+    /// pixel wide, on the scaled up outline.
+    fn scale(&self, factor: u32) -> Self {
+        let factor = factor as isize;
+
+        Circle {
+            center: scale_point(self.center, factor as u32),
+            radius: if self.filled {
+                self.radius * factor + (factor - 1) / 2
+            } else {
+                self.radius * factor
+            },
+            ..*self
+        }
+    }
+
+    fn perturb(&self, config: &Config, width: u32, height: u32, rng: &mut impl Rng) -> Self {
+        let (min_radius, max_radius) = radius_limits(config, width, height);
+        let step = step(self.radius);
+        let [x, y] = self.center;
+
+        if rng.random_bool(0.5) {
+            Circle {
+                center: [nudge(x, step, rng), nudge(y, step, rng)],
+                ..*self
+            }
+        } else {
+            Circle {
+                radius: nudge(self.radius, step, rng).clamp(min_radius, max_radius),
+                ..*self
+            }
+        }
+    }
+
+    /// From the distance between the pixel's center and the ring, or the
+    /// edge of the disk.
+    fn coverage(&self, [x, y]: [isize; 2]) -> f32 {
+        let [cx, cy] = self.center;
+        let distance = (((x - cx).pow(2) + (y - cy).pow(2)) as f32).sqrt();
+
+        let coverage = if self.filled {
+            self.radius as f32 + 0.5 - distance
+        } else {
+            1.0 - (distance - self.radius as f32).abs()
+        };
+
+        coverage.clamp(0.0, 1.0)
+    }
+}
+
+/// Largest radius whose circle [`circle_template`] keeps around; together,
+/// the templates up to it take about 13 MB per thread.
+const MAX_TEMPLATE_RADIUS: isize = 512;
+
+/// A circle of some radius around the origin, shared by every circle like it.
+struct CircleTemplate {
+    /// The outline, as [`midpoint_circle`] draws it.
+    outline: Box<[[isize; 2]]>,
+    /// How far the outline reaches to either side on each row below the
+    /// center, which is all a disk needs.
+    half_widths: Box<[isize]>,
+}
+
+impl CircleTemplate {
+    fn new(r: isize) -> Self {
+        let outline = midpoint_circle(r);
+        let mut half_widths = vec![0; (r.max(-1) + 1) as usize];
+
+        for &[x, y] in &outline {
+            if y >= 0 {
+                half_widths[y as usize] = half_widths[y as usize].max(x.abs());
+            }
+        }
+
+        Self {
+            outline: outline.into(),
+            half_widths: half_widths.into(),
+        }
+    }
+}
+
+thread_local! {
+    /// Circles around the origin, by radius.
+    static CIRCLE_TEMPLATES: RefCell<Vec<Option<Rc<CircleTemplate>>>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// Returns the outline of a circle using the midpoint circle algorithm.
+///
+/// Each pixel is returned exactly once; points are not clipped, so they may
+/// lie outside of the image. The outline only depends on the radius, so
+/// it's only worked out once per radius (on each thread) and then moved
+/// onto the center.
+pub fn generate_circle_points(xc: isize, yc: isize, r: isize) -> Vec<[isize; 2]> {
+    let mut points = Vec::new();
+    circle_points_into(xc, yc, r, &mut points);
+    points
+}
+
+/// Appends the points of [`generate_circle_points`] to `points`.
+pub(super) fn circle_points_into(xc: isize, yc: isize, r: isize, points: &mut Vec<[isize; 2]>) {
+    let template = circle_template(r);
+    points.extend(template.outline.iter().map(|&[x, y]| [xc + x, yc + y]));
+}
+
+/// Appends the points of [`generate_circle_points`] that lie on a
+/// `width`x`height` canvas to `points`, in the same order.
+///
+/// The outline is sorted, so the columns off either side of the canvas
+/// aren't even looked at.
+pub(super) fn clipped_circle_points_into(
+    xc: isize,
+    yc: isize,
+    r: isize,
+    (width, height): (u32, u32),
+    points: &mut Vec<[isize; 2]>,
+) {
+    let template = circle_template(r);
+    let outline = &template.outline;
+
+    let start = outline.partition_point(|&[x, _]| xc + x < 0);
+    let end = outline.partition_point(|&[x, _]| xc + x < width as isize);
+
+    points.extend(
+        outline[start..end]
+            .iter()
+            .map(|&[x, y]| [xc + x, yc + y])
+            .filter(|&[_, y]| y >= 0 && y < height as isize),
+    );
+}
+
+/// Returns the circle of radius `r` around the origin, from the cache if
+/// possible.
+fn circle_template(r: isize) -> Rc<CircleTemplate> {
+    if !(0..=MAX_TEMPLATE_RADIUS).contains(&r) {
+        return Rc::new(CircleTemplate::new(r));
+    }
+
+    CIRCLE_TEMPLATES.with_borrow_mut(|templates| {
+        if templates.len() <= r as usize {
+            templates.resize(r as usize + 1, None);
+        }
+
+        templates[r as usize]
+            .get_or_insert_with(|| Rc::new(CircleTemplate::new(r)))
+            .clone()
+    })
+}
+
+/// Draws the outline of a circle of radius `r` around the origin, sorted.
+fn midpoint_circle(r: isize) -> Vec<[isize; 2]> {
+    let mut points = Vec::new();
+    let mut x = 0;
+    let mut y = r;
+    let mut d = 3 - 2 * r;
+
+    while x <= y {
+        // Add points in all octants
+        let octant_points = [
+            [x, y], [-x, y],
+            [x, -y], [-x, -y],
+            [y, x], [-y, x],
+            [y, -x], [-y, -x],
+        ];
+        points.extend_from_slice(&octant_points);
+
+        if d < 0 {
+            d = d + 4 * x + 6;
+        } else {
+            d = d + 4 * (x - y) + 10;
+            y -= 1;
+        }
+        x += 1;
+    }
+
+    // Octants share pixels where they meet (x == 0 and x == y), which would
+    // otherwise be averaged and scored more than once
+    points.sort_unstable();
+    points.dedup();
+    points
+}
+
+/// Returns every pixel of a solid disk, as horizontal spans between the
+/// symmetric edge points of [`generate_circle_points`].
+///
+/// Each pixel is returned exactly once; points are not clipped.
+pub fn generate_filled_circle_points(xc: isize, yc: isize, r: isize) -> Vec<[isize; 2]> {
+    let mut points = Vec::new();
+    filled_circle_points_into(xc, yc, r, &mut points);
+    points
+}
+
+/// Appends the points of [`generate_filled_circle_points`] to `points`.
+fn filled_circle_points_into(xc: isize, yc: isize, r: isize, points: &mut Vec<[isize; 2]>) {
+    let template = circle_template(r);
+
+    for (dy, &half_width) in template.half_widths.iter().enumerate() {
+        let dy = dy as isize;
+
+        for x in xc - half_width..=xc + half_width {
+            points.push([x, yc + dy]);
+
+            if dy != 0 {
+                points.push([x, yc - dy]);
+            }
+        }
+    }
+}
+
+/// Appends the points of [`generate_filled_circle_points`] that lie on a
+/// `width`x`height` canvas to `points`, in the same order, without visiting
+/// the rest.
+pub(super) fn clipped_filled_circle_points_into(
+    xc: isize,
+    yc: isize,
+    r: isize,
+    (width, height): (u32, u32),
+    points: &mut Vec<[isize; 2]>,
+) {
+    let template = circle_template(r);
+    let (width, height) = (width as isize, height as isize);
+    let on_canvas = |y: isize| y >= 0 && y < height;
+
+    for (dy, &half_width) in template.half_widths.iter().enumerate() {
+        let dy = dy as isize;
+        let below = on_canvas(yc + dy);
+        let above = dy != 0 && on_canvas(yc - dy);
+
+        if !below && !above {
+            continue;
+        }
+
+        for x in (xc - half_width).max(0)..=(xc + half_width).min(width - 1) {
+            if below {
+                points.push([x, yc + dy]);
+            }
+
+            if above {
+                points.push([x, yc - dy]);
+            }
+        }
+    }
+}
+
+/// Appends every pixel of a disk of radius `outer` around `(xc, yc)` that
+/// isn't in the disk of radius `inner` to `points`, each exactly once.
+///
+/// Both disks are those of [`generate_filled_circle_points`], so the ring
+/// between them has no gaps, unlike outlines of neighbouring radii drawn
+/// together; a negative `inner` leaves no hole.
+///
+/// It isn't meant to match [`generate_circle_points`] when `inner` is
+/// `outer - 1`: that annulus has every pixel of the outline of radius
+/// `outer`, but also the corner pixels the outline steps diagonally past.
+pub(super) fn annulus_points_into(
+    xc: isize,
+    yc: isize,
+    outer: isize,
+    inner: isize,
+    points: &mut Vec<[isize; 2]>,
+) {
+    let outer = circle_template(outer);
+    let inner = circle_template(inner);
+
+    for (dy, &half_width) in outer.half_widths.iter().enumerate() {
+        let hole = inner.half_widths.get(dy).copied().unwrap_or(-1);
+        let dy = dy as isize;
+
+        for dx in -half_width..=half_width {
+            if dx.abs() <= hole {
+                continue;
+            }
+
+            points.push([xc + dx, yc + dy]);
+
+            if dy != 0 {
+                points.push([xc + dx, yc - dy]);
+            }
+        }
+    }
+}
+
+/// Returns every pixel of the ring of `circle`, widened to `width` pixels,
+/// along with how much of each pixel it covers.
+///
+/// Rings are only a pixel wide, so this is how they're drawn on scaled up
+/// canvases (see [`Recording::render_scaled`](crate::Recording::render_scaled)),
+/// as the area between two disks of [`generate_filled_circle_points`].
+pub(crate) fn wide_ring(circle: &Circle, width: isize) -> Vec<([isize; 2], f32)> {
+    let ([xc, yc], radius) = (circle.center, circle.radius);
+    let mut points = Vec::new();
+    annulus_points_into(xc, yc, radius + (width - 1) / 2, radius - (width + 1) / 2, &mut points);
+
+    points
+        .into_iter()
+        .map(|[x, y]| {
+            let distance = (((x - xc).pow(2) + (y - yc).pow(2)) as f32).sqrt();
+            let coverage = width as f32 / 2.0 + 0.5 - (distance - radius as f32).abs();
+
+            ([x, y], coverage.clamp(0.0, 1.0))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::generate_ring_points;
+    use std::collections::{BTreeMap, HashSet};
+
+    #[test]
+    fn outlines_have_no_repeated_points() {
+        for r in (0..=40).chain([100, 600]) {
+            let points = generate_circle_points(3, -5, r);
+            let unique: HashSet<_> = points.iter().collect();
+
+            assert_eq!(unique.len(), points.len(), "radius {r}");
+        }
+    }
+
+    #[test]
+    fn cached_circles_match_fresh_ones() {
+        for r in 0..=70 {
+            let fresh = midpoint_circle(r);
+            let outline: Vec<_> = fresh.iter().map(|&[x, y]| [x + 17, y - 4]).collect();
+
+            // A disk spans each row between the ends of the outline on it
+            let mut rows = BTreeMap::new();
+            for &[x, y] in &fresh {
+                let (left, right) = rows.entry(y).or_insert((x, x));
+                (*left, *right) = ((*left).min(x), (*right).max(x));
+            }
+
+            let mut disk: Vec<_> = rows
+                .into_iter()
+                .flat_map(|(y, (left, right))| (left..=right).map(move |x| [x + 17, y - 4]))
+                .collect();
+            disk.sort_unstable();
+
+            // The second time round, the circle comes from the cache
+            for _ in 0..2 {
+                assert_eq!(generate_circle_points(17, -4, r), outline, "radius {r}");
+
+                let mut filled = generate_filled_circle_points(17, -4, r);
+                filled.sort_unstable();
+                assert_eq!(filled, disk, "radius {r}");
+            }
+        }
+    }
+
+    #[test]
+    fn one_pixel_rings_are_outlines() {
+        for r in 0..=70 {
+            assert_eq!(generate_ring_points(5, -3, r, 1), generate_circle_points(5, -3, r));
+        }
+    }
+
+    #[test]
+    fn one_pixel_annuli_only_add_to_outlines() {
+        let mut wider = 0;
+
+        for r in 1..=70 {
+            let outline: HashSet<_> = generate_circle_points(0, 0, r).into_iter().collect();
+            let mut annulus = Vec::new();
+            annulus_points_into(0, 0, r, r - 1, &mut annulus);
+            let annulus: HashSet<_> = annulus.into_iter().collect();
+
+            assert!(annulus.is_superset(&outline), "radius {r}");
+            wider += (annulus.len() > outline.len()) as usize;
+        }
+
+        // So they can't stand in for outlines
+        assert!(wider > 0);
+    }
+
+    #[test]
+    fn wide_rings_have_no_holes() {
+        for r in 0..=40 {
+            for width in 2..=8 {
+                let ring = generate_ring_points(0, 0, r, width);
+                let unique: HashSet<_> = ring.iter().copied().collect();
+                assert_eq!(unique.len(), ring.len(), "radius {r}, width {width}");
+
+                // Everything between the two disks, down to the last pixel
+                let (outer, inner) = (r + (width as isize - 1) / 2, r - (width as isize + 1) / 2);
+                let hole = generate_filled_circle_points(0, 0, inner);
+                let hole: HashSet<_> = hole.into_iter().collect();
+                let mut between = generate_filled_circle_points(0, 0, outer);
+                between.retain(|point| !hole.contains(point));
+                let between: HashSet<_> = between.into_iter().collect();
+                assert_eq!(unique, between, "radius {r}, width {width}");
+            }
+        }
+    }
+
+    #[test]
+    fn clipped_circles_match_filtered_ones() {
+        let (width, height) = (37, 23);
+        let on_canvas = |&[x, y]: &[isize; 2]| x >= 0 && x < width && y >= 0 && y < height;
+
+        for r in [0, 1, 2, 5, 11, 30, 60] {
+            for xc in [-70, -12, -1, 0, 5, 18, 36, 37, 50, 100] {
+                for yc in [-70, -9, 0, 11, 22, 23, 40] {
+                    let bounds = (width as u32, height as u32);
+                    let mut clipped = Vec::new();
+
+                    clipped_circle_points_into(xc, yc, r, bounds, &mut clipped);
+                    let mut filtered = generate_circle_points(xc, yc, r);
+                    filtered.retain(on_canvas);
+                    assert_eq!(clipped, filtered, "radius {r} at {xc},{yc}");
+
+                    clipped.clear();
+                    clipped_filled_circle_points_into(xc, yc, r, bounds, &mut clipped);
+                    let mut filtered = generate_filled_circle_points(xc, yc, r);
+                    filtered.retain(on_canvas);
+                    assert_eq!(clipped, filtered, "radius {r} at {xc},{yc}, filled");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use super::circle::clipped_filled_circle_points_into;
+use super::{mirror_point, nudge, random_position, rotate_point, scale_point, step, Primitive};
+use crate::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A small solid disk of a fixed size, for stippling: only where it goes
+/// and its color vary.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dot {
+    pub center: [isize; 2],
+    pub radius: isize,
+}
+
+impl Primitive for Dot {
+    /// The radius is always [`Config::dot_size`], whatever the range of
+    /// radii.
+    fn random(
+        config: &Config,
+        _radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        Dot {
+            center: random_position(width, height, rng),
+            radius: config.dot_size as isize,
+        }
+    }
+
+    /// Dots cover the pixels of a solid disk, clipped to `bounds`.
+    fn points_into(&self, bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        let [x, y] = self.center;
+
+        points.clear();
+        clipped_filled_circle_points_into(x, y, self.radius, bounds, points);
+    }
+
+    fn center(&self) -> [isize; 2] {
+        self.center
+    }
+
+    fn radius(&self) -> isize {
+        self.radius
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        let ([x, y], radius) = (self.center, self.radius);
+        [x - radius, y - radius, x + radius, y + radius]
+    }
+
+    fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        let [x, y] = self.center;
+
+        Dot {
+            center: [x + dx, y + dy],
+            ..*self
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        Dot {
+            center: mirror_point(self.center, width),
+            ..*self
+        }
+    }
+
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self {
+        Dot {
+            center: rotate_point(self.center, bounds, angle),
+            ..*self
+        }
+    }
+
+    fn scale(&self, factor: u32) -> Self {
+        Dot {
+            center: scale_point(self.center, factor),
+            radius: self.radius * factor as isize + (factor as isize - 1) / 2,
+        }
+    }
+
+    /// Only moves the dot, which keeps its size.
+    fn perturb(&self, _config: &Config, _width: u32, _height: u32, rng: &mut impl Rng) -> Self {
+        let step = step(self.radius);
+        let [x, y] = self.center;
+
+        Dot {
+            center: [nudge(x, step, rng), nudge(y, step, rng)],
+            ..*self
+        }
+    }
+
+    /// From the distance between the pixel's center and the edge of the
+    /// disk.
+    fn coverage(&self, [x, y]: [isize; 2]) -> f32 {
+        let [cx, cy] = self.center;
+        let distance = (((x - cx).pow(2) + (y - cy).pow(2)) as f32).sqrt();
+
+        (self.radius as f32 + 0.5 - distance).clamp(0.0, 1.0)
+    }
+}
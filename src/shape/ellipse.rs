@@ -0,0 +1,231 @@
+use super::{
+    generate_filled_circle_points, mirror_point, nudge, radius_limits, random_position,
+    rotate_point, scale_point, step, Primitive,
+};
+use crate::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A filled, rotated ellipse.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ellipse {
+    pub center: [isize; 2],
+    pub radii: [isize; 2],
+    /// Rotation in radians, clockwise since y points down.
+    pub angle: f32,
+}
+
+impl Primitive for Ellipse {
+    /// Both radii are within `radii`, so ellipses come in random
+    /// proportions.
+    fn random(
+        _config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let (min_radius, max_radius) = (*radii.start() as usize, *radii.end() as usize);
+
+        let center = random_position(width, height, rng);
+        let radii = [
+            rng.random_range(min_radius..=max_radius) as isize,
+            rng.random_range(min_radius..=max_radius) as isize,
+        ];
+        let angle = rng.random_range(0.0..std::f32::consts::PI);
+
+        Ellipse {
+            center,
+            radii,
+            angle,
+        }
+    }
+
+    /// Ellipses are clipped to `bounds`.
+    fn points_into(&self, bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        let ([x, y], [rx, ry]) = (self.center, self.radii);
+
+        points.clear();
+        points.extend(generate_ellipse_points(x, y, rx, ry, self.angle, bounds));
+    }
+
+    fn center(&self) -> [isize; 2] {
+        self.center
+    }
+
+    fn radius(&self) -> isize {
+        self.radii[0].max(self.radii[1])
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        let [x, y] = self.center;
+
+        // Covers every rotation, including the half pixel of slack
+        let r = self.radius() + 1;
+        [x - r, y - r, x + r, y + r]
+    }
+
+    fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        let [x, y] = self.center;
+
+        Ellipse {
+            center: [x + dx, y + dy],
+            ..*self
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        Ellipse {
+            center: mirror_point(self.center, width),
+            angle: std::f32::consts::PI - self.angle,
+            ..*self
+        }
+    }
+
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self {
+        Ellipse {
+            center: rotate_point(self.center, bounds, angle),
+            angle: self.angle + angle,
+            ..*self
+        }
+    }
+
+    fn scale(&self, factor: u32) -> Self {
+        let factor = factor as isize;
+
+        Ellipse {
+            center: scale_point(self.center, factor as u32),
+            radii: self.radii.map(|radius| radius * factor + (factor - 1) / 2),
+            ..*self
+        }
+    }
+
+    /// Moves, resizes or rotates the ellipse.
+    fn perturb(&self, config: &Config, width: u32, height: u32, rng: &mut impl Rng) -> Self {
+        let (min_radius, max_radius) = radius_limits(config, width, height);
+        let step = step(self.radius());
+        let ([x, y], [rx, ry]) = (self.center, self.radii);
+
+        match rng.random_range(0..3) {
+            0 => Ellipse {
+                center: [nudge(x, step, rng), nudge(y, step, rng)],
+                ..*self
+            },
+            1 => Ellipse {
+                radii: [
+                    nudge(rx, step, rng).clamp(min_radius, max_radius),
+                    nudge(ry, step, rng).clamp(min_radius, max_radius),
+                ],
+                ..*self
+            },
+            _ => Ellipse {
+                angle: self.angle + rng.random_range(-0.3..0.3),
+                ..*self
+            },
+        }
+    }
+
+    /// From how far past the edge the pixel's center is, measured along the
+    /// ray from the center.
+    fn coverage(&self, [x, y]: [isize; 2]) -> f32 {
+        let (center, [rx, ry]) = (self.center, self.radii);
+        let (sin, cos) = self.angle.sin_cos();
+        let [px, py] = [(x - center[0]) as f32, (y - center[1]) as f32];
+        let [u, v] = [px * cos + py * sin, -px * sin + py * cos];
+        let scale = ((u / rx as f32).powi(2) + (v / ry as f32).powi(2)).sqrt();
+
+        let outside = if scale == 0.0 {
+            -(rx.min(ry) as f32)
+        } else {
+            let distance = (((x - center[0]).pow(2) + (y - center[1]).pow(2)) as f32).sqrt();
+            distance * (1.0 - 1.0 / scale)
+        };
+
+        (0.5 - outside).clamp(0.0, 1.0)
+    }
+}
+
+/// Returns every in-bounds pixel of a solid ellipse with semi-axes `rx` and
+/// `ry`, rotated by `theta` radians around its center.
+///
+/// Each row is filled between the two solutions of the ellipse's implicit
+/// equation; the semi-axes are widened by half a pixel so the edge matches
+/// the rasterized disks. When `rx == ry` the ellipse is a circle, for which
+/// rotation doesn't matter, so the pixels are exactly those of
+/// [`generate_filled_circle_points`].
+pub fn generate_ellipse_points(
+    cx: isize,
+    cy: isize,
+    rx: isize,
+    ry: isize,
+    theta: f32,
+    bounds: (u32, u32),
+) -> Vec<[isize; 2]> {
+    let (width, height) = (bounds.0 as isize, bounds.1 as isize);
+
+    if rx == ry {
+        let mut points = generate_filled_circle_points(cx, cy, rx);
+        points.retain(|&[x, y]| x >= 0 && y >= 0 && x < width && y < height);
+        return points;
+    }
+
+    let a = rx as f32 + 0.5;
+    let b = ry as f32 + 0.5;
+    let (sin, cos) = theta.sin_cos();
+
+    // Coefficients of `qa * x^2 + qb * x * y + qc * y^2 <= 1`, relative to the center
+    let qa = (cos / a).powi(2) + (sin / b).powi(2);
+    let qb = 2.0 * cos * sin * (1.0 / (a * a) - 1.0 / (b * b));
+    let qc = (sin / a).powi(2) + (cos / b).powi(2);
+
+    let extent_y = (a * a * sin * sin + b * b * cos * cos).sqrt().floor() as isize;
+    let mut points = Vec::new();
+
+    for dy in (-extent_y).max(-cy)..=extent_y.min(height - 1 - cy) {
+        let y = dy as f32;
+        let discriminant = qb * qb * y * y - 4.0 * qa * (qc * y * y - 1.0);
+
+        if discriminant < 0.0 {
+            continue;
+        }
+
+        let root = discriminant.sqrt();
+        let min_dx = ((-qb * y - root) / (2.0 * qa)).ceil() as isize;
+        let max_dx = ((-qb * y + root) / (2.0 * qa)).floor() as isize;
+
+        for x in (cx + min_dx).max(0)..=(cx + max_dx).min(width - 1) {
+            points.push([x, cy + dy]);
+        }
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn round_ellipses_are_clipped_disks() {
+        let (width, height) = (37, 23);
+        let on_canvas = |&[x, y]: &[isize; 2]| x >= 0 && x < width && y >= 0 && y < height;
+
+        for r in 0..=40 {
+            for [cx, cy] in [[18, 11], [0, 0], [-5, 30], [36, 22], [50, -10]] {
+                let mut disk = generate_filled_circle_points(cx, cy, r);
+                disk.retain(on_canvas);
+                let disk: HashSet<_> = disk.into_iter().collect();
+
+                for theta in [0.0, 0.4, 1.0, std::f32::consts::FRAC_PI_2, 2.5] {
+                    let bounds = (width as u32, height as u32);
+                    let ellipse: HashSet<_> =
+                        generate_ellipse_points(cx, cy, r, r, theta, bounds).into_iter().collect();
+
+                    assert_eq!(ellipse, disk, "radius {r} at {cx},{cy} turned by {theta}");
+                }
+            }
+        }
+    }
+}
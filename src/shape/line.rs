@@ -0,0 +1,178 @@
+use super::{
+    enclose, mirror_point, nudge, random_position, rotate_point, scale_point, step, Primitive,
+};
+use crate::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A straight pen stroke, `width` pixels thick.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Line {
+    pub endpoints: [[isize; 2]; 2],
+    pub width: u32,
+}
+
+impl Primitive for Line {
+    /// Endpoints are up to the largest radius away from a random point on
+    /// both axes, and the thickness is [`Config::stroke_width`].
+    fn random(
+        config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let [x, y] = random_position(width, height, rng);
+        let max_radius = *radii.end() as isize;
+
+        let endpoints = [(); 2].map(|_| {
+            [
+                x + rng.random_range(0..=2 * max_radius as usize) as isize - max_radius,
+                y + rng.random_range(0..=2 * max_radius as usize) as isize - max_radius,
+            ]
+        });
+
+        Line {
+            endpoints,
+            width: config.stroke_width,
+        }
+    }
+
+    /// Lines aren't clipped.
+    fn points_into(&self, _bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        let [a, b] = self.endpoints;
+
+        points.clear();
+        points.extend(generate_line_points(a, b, self.width));
+    }
+
+    fn center(&self) -> [isize; 2] {
+        let [a, b] = self.endpoints;
+        [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2]
+    }
+
+    fn radius(&self) -> isize {
+        let [a, b] = self.endpoints;
+        (((b[0] - a[0]).pow(2) + (b[1] - a[1]).pow(2)) as f32).sqrt() as isize / 2
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        enclose(&self.endpoints, self.width as isize)
+    }
+
+    fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        Line {
+            endpoints: self.endpoints.map(|[x, y]| [x + dx, y + dy]),
+            ..*self
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        Line {
+            endpoints: self.endpoints.map(|point| mirror_point(point, width)),
+            ..*self
+        }
+    }
+
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self {
+        Line {
+            endpoints: self.endpoints.map(|point| rotate_point(point, bounds, angle)),
+            ..*self
+        }
+    }
+
+    fn scale(&self, factor: u32) -> Self {
+        Line {
+            endpoints: self.endpoints.map(|point| scale_point(point, factor)),
+            width: self.width * factor,
+        }
+    }
+
+    /// Moves one endpoint.
+    fn perturb(&self, _config: &Config, _width: u32, _height: u32, rng: &mut impl Rng) -> Self {
+        let step = step(self.radius());
+        let mut endpoints = self.endpoints;
+
+        let endpoint = rng.random_range(0..2);
+        let [x, y] = endpoints[endpoint];
+        endpoints[endpoint] = [nudge(x, step, rng), nudge(y, step, rng)];
+
+        Line { endpoints, ..*self }
+    }
+
+    /// From the distance between the pixel's center and the segment.
+    fn coverage(&self, [x, y]: [isize; 2]) -> f32 {
+        let [a, b] = self.endpoints;
+        let [dx, dy] = [(b[0] - a[0]) as f32, (b[1] - a[1]) as f32];
+        let [px, py] = [(x - a[0]) as f32, (y - a[1]) as f32];
+        let length_squared = dx * dx + dy * dy;
+
+        // Distance to the closest point of the segment
+        let t = if length_squared == 0.0 {
+            0.0
+        } else {
+            ((px * dx + py * dy) / length_squared).clamp(0.0, 1.0)
+        };
+        let distance = ((px - t * dx).powi(2) + (py - t * dy).powi(2)).sqrt();
+
+        (self.width as f32 / 2.0 + 0.5 - distance).clamp(0.0, 1.0)
+    }
+}
+
+/// Returns the pixels of a `width` pixels thick line segment from `a` to
+/// `b`; points are not clipped.
+///
+/// The center line is traced with Bresenham's algorithm and every point is
+/// extended along the minor axis, with the span stretched so that the
+/// thickness measured perpendicular to the line stays at `width`. Each pixel
+/// is returned exactly once.
+pub fn generate_line_points(a: [isize; 2], b: [isize; 2], width: u32) -> Vec<[isize; 2]> {
+    let dx = (b[0] - a[0]).abs();
+    let dy = -(b[1] - a[1]).abs();
+    let step_x = if a[0] < b[0] { 1 } else { -1 };
+    let step_y = if a[1] < b[1] { 1 } else { -1 };
+    let x_major = dx >= -dy;
+
+    // Length of the perpendicular span, measured along the minor axis
+    let major = dx.max(-dy);
+    let span = if major == 0 {
+        width as isize
+    } else {
+        let length = ((dx * dx + dy * dy) as f32).sqrt();
+        ((width as f32 * length / major as f32).round() as isize).max(1)
+    };
+    let span = -(span - 1) / 2..=span / 2;
+
+    let mut points = Vec::new();
+    let [mut x, mut y] = a;
+    let mut err = dx + dy;
+
+    loop {
+        for offset in span.clone() {
+            if x_major {
+                points.push([x, y + offset]);
+            } else {
+                points.push([x + offset, y]);
+            }
+        }
+
+        if [x, y] == b {
+            break;
+        }
+
+        let e2 = 2 * err;
+
+        if e2 >= dy {
+            err += dy;
+            x += step_x;
+        }
+
+        if e2 <= dx {
+            err += dx;
+            y += step_y;
+        }
+    }
+
+    points
+}
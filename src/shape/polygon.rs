@@ -0,0 +1,324 @@
+use super::{
+    enclose, mirror_point, nudge, radius_limits, random_position, rotate_point, scale_point, step,
+    Primitive,
+};
+use crate::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A filled regular polygon, with its corners `radius` pixels from the
+/// center.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Polygon {
+    pub center: [isize; 2],
+    pub radius: isize,
+    pub sides: u32,
+    /// Rotation in radians, clockwise since y points down.
+    pub angle: f32,
+}
+
+impl Polygon {
+    /// Returns the corners, rounded to the nearest pixel.
+    ///
+    /// Rounding can leave a polygon with many sides slightly concave, so its
+    /// corners are those of the convex hull of the rounded ones.
+    pub fn vertices(&self) -> Vec<[isize; 2]> {
+        let [x, y] = self.center;
+        let (radius, sides) = (self.radius, self.sides);
+
+        convex_hull(
+            (0..sides)
+                .map(|i| {
+                    let (sin, cos) =
+                        (self.angle + std::f32::consts::TAU * i as f32 / sides as f32).sin_cos();
+
+                    [
+                        x + (radius as f32 * cos).round() as isize,
+                        y + (radius as f32 * sin).round() as isize,
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Primitive for Polygon {
+    /// Polygons get [`Config::sides`] corners, at least 3.
+    fn random(
+        config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let center = random_position(width, height, rng);
+        let radius = rng.random_range(*radii.start() as usize..=*radii.end() as usize) as isize;
+        let sides = config.sides.max(3);
+
+        // Turning by one corner gives the same polygon
+        let angle = rng.random_range(0.0..std::f32::consts::TAU / sides as f32);
+
+        Polygon {
+            center,
+            radius,
+            sides,
+            angle,
+        }
+    }
+
+    /// Polygons are clipped to `bounds`.
+    fn points_into(&self, bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        points.clear();
+        points.extend(generate_polygon_points(&self.vertices(), bounds));
+    }
+
+    fn center(&self) -> [isize; 2] {
+        self.center
+    }
+
+    fn radius(&self) -> isize {
+        self.radius
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        enclose(&self.vertices(), 0)
+    }
+
+    fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        let [x, y] = self.center;
+
+        Polygon {
+            center: [x + dx, y + dy],
+            ..*self
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        Polygon {
+            center: mirror_point(self.center, width),
+            angle: std::f32::consts::PI - self.angle,
+            ..*self
+        }
+    }
+
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self {
+        Polygon {
+            center: rotate_point(self.center, bounds, angle),
+            angle: self.angle + angle,
+            ..*self
+        }
+    }
+
+    fn scale(&self, factor: u32) -> Self {
+        Polygon {
+            center: scale_point(self.center, factor),
+            radius: self.radius * factor as isize,
+            ..*self
+        }
+    }
+
+    /// Moves, resizes or rotates the polygon.
+    fn perturb(&self, config: &Config, width: u32, height: u32, rng: &mut impl Rng) -> Self {
+        let (min_radius, max_radius) = radius_limits(config, width, height);
+        let step = step(self.radius);
+        let [x, y] = self.center;
+
+        match rng.random_range(0..3) {
+            0 => Polygon {
+                center: [nudge(x, step, rng), nudge(y, step, rng)],
+                ..*self
+            },
+            1 => Polygon {
+                radius: nudge(self.radius, step, rng).clamp(min_radius, max_radius),
+                ..*self
+            },
+            _ => Polygon {
+                angle: self.angle + rng.random_range(-0.3..0.3),
+                ..*self
+            },
+        }
+    }
+
+    fn coverage(&self, point: [isize; 2]) -> f32 {
+        edge_coverage(&self.vertices(), point)
+    }
+}
+
+/// Returns roughly which fraction of the pixel at `[x, y]` the convex
+/// polygon with the given corners covers, from the signed distance between
+/// the pixel's center and its nearest edge.
+pub(super) fn edge_coverage(vertices: &[[isize; 2]], [x, y]: [isize; 2]) -> f32 {
+    let vertices: Vec<_> = vertices.iter().map(|&[x, y]| [x as f32, y as f32]).collect();
+    let edges = || (0..vertices.len()).map(|i| (vertices[i], vertices[(i + 1) % vertices.len()]));
+    let p = [x as f32, y as f32];
+
+    // Signed distance to the nearest edge, positive inside
+    let cross = |p0: [f32; 2], p1: [f32; 2], p: [f32; 2]| {
+        (p1[0] - p0[0]) * (p[1] - p0[1]) - (p1[1] - p0[1]) * (p[0] - p0[0])
+    };
+    let winding = edges()
+        .map(|(p0, p1)| cross(vertices[0], p0, p1))
+        .sum::<f32>()
+        .signum();
+    let edge_distance = |(p0, p1): ([f32; 2], [f32; 2])| {
+        let length = ((p1[0] - p0[0]).powi(2) + (p1[1] - p0[1]).powi(2)).sqrt();
+
+        // Corners that rounded onto each other don't make an edge
+        if length == 0.0 {
+            return f32::INFINITY;
+        }
+
+        winding * cross(p0, p1, p) / length
+    };
+
+    let inside = edges().map(edge_distance).fold(f32::INFINITY, f32::min);
+    (inside + 0.5).clamp(0.0, 1.0)
+}
+
+/// Returns every pixel inside the convex polygon with the given corners,
+/// clipped to `bounds` (width, height).
+///
+/// This is the test [`generate_triangle_points`](super::generate_triangle_points)
+/// does, with one edge function per side, so three corners cover exactly the
+/// same pixels as a triangle. Polygons without area cover no pixels.
+pub fn generate_polygon_points(vertices: &[[isize; 2]], bounds: (u32, u32)) -> Vec<[isize; 2]> {
+    // Twice the signed area of the triangle `p`, `q`, `r`
+    let edge = |p: [isize; 2], q: [isize; 2], r: [isize; 2]| {
+        (q[0] - p[0]) * (r[1] - p[1]) - (q[1] - p[1]) * (r[0] - p[0])
+    };
+
+    let edges: Vec<_> = (0..vertices.len())
+        .map(|i| (vertices[i], vertices[(i + 1) % vertices.len()]))
+        .collect();
+
+    // Twice the signed area of the whole polygon, as a fan of triangles
+    let area: isize = edges.iter().map(|&(p, q)| edge(vertices[0], p, q)).sum();
+
+    if area == 0 {
+        return Vec::new();
+    }
+
+    let [min_x, min_y, max_x, max_y] = enclose(vertices, 0);
+    let (min_x, min_y) = (min_x.max(0), min_y.max(0));
+    let (max_x, max_y) = (max_x.min(bounds.0 as isize - 1), max_y.min(bounds.1 as isize - 1));
+
+    let mut points = Vec::new();
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let p = [x, y];
+
+            // Inside when every edge function agrees with the winding order
+            if edges.iter().all(|&(a, b)| edge(a, b, p) * area.signum() >= 0) {
+                points.push(p);
+            }
+        }
+    }
+
+    points
+}
+
+
+/// Returns the corners of the convex hull of `points`, in order, leaving out
+/// any that lie on an edge.
+fn convex_hull(mut points: Vec<[isize; 2]>) -> Vec<[isize; 2]> {
+    points.sort_unstable();
+    points.dedup();
+
+    if points.len() < 3 {
+        return points;
+    }
+
+    // Whether `o`, `a`, `b` turn counterclockwise (with y pointing up)
+    let turns = |o: [isize; 2], a: [isize; 2], b: [isize; 2]| {
+        (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0]) > 0
+    };
+
+    // Andrew's monotone chain
+    let mut hull: Vec<[isize; 2]> = Vec::with_capacity(points.len() + 1);
+
+    // The lower hull, left to right
+    for &point in &points {
+        while hull.len() >= 2 && !turns(hull[hull.len() - 2], hull[hull.len() - 1], point) {
+            hull.pop();
+        }
+        hull.push(point);
+    }
+
+    // The upper hull, right to left, stacked onto the lower one
+    let lower = hull.len() + 1;
+
+    for &point in points.iter().rev().skip(1) {
+        while hull.len() >= lower && !turns(hull[hull.len() - 2], hull[hull.len() - 1], point) {
+            hull.pop();
+        }
+        hull.push(point);
+    }
+
+    // The upper hull ends where the lower one started
+    hull.pop();
+    hull
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::{generate_filled_circle_points, Triangle};
+    use std::collections::HashSet;
+
+    /// Returns the pixels of `polygon` on a `bounds` canvas.
+    fn polygon_points(polygon: Polygon, bounds: (u32, u32)) -> Vec<[isize; 2]> {
+        let mut points = Vec::new();
+        polygon.points_into(bounds, &mut points);
+        points
+    }
+
+    #[test]
+    fn three_sided_polygons_are_triangles() {
+        let bounds = (37, 23);
+
+        for radius in [2, 5, 9, 20] {
+            for angle in [0.0, 0.3, 1.0, 2.0] {
+                for center in [[18, 11], [0, 0], [30, 20]] {
+                    let polygon = Polygon {
+                        center,
+                        radius,
+                        sides: 3,
+                        angle,
+                    };
+                    let vertices = polygon.vertices().try_into().unwrap();
+
+                    let mut from_polygon = polygon_points(polygon, bounds);
+                    let mut from_triangle = Vec::new();
+                    Triangle { vertices }.points_into(bounds, &mut from_triangle);
+
+                    from_polygon.sort_unstable();
+                    from_triangle.sort_unstable();
+                    assert_eq!(from_polygon, from_triangle, "{polygon:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn polygons_with_many_sides_are_nearly_disks() {
+        for (radius, overlap) in [(5, 0.95), (20, 0.97), (60, 0.99)] {
+            let polygon = Polygon {
+                center: [70, 70],
+                radius,
+                sides: 1024,
+                angle: 0.0,
+            };
+
+            let polygon: HashSet<_> = polygon_points(polygon, (140, 140)).into_iter().collect();
+            let disk = generate_filled_circle_points(70, 70, radius);
+            let disk: HashSet<_> = disk.into_iter().collect();
+
+            // Intersection over union
+            let both = polygon.intersection(&disk).count() as f32;
+            let either = polygon.union(&disk).count() as f32;
+            assert!(both / either > overlap, "radius {radius}: {}", both / either);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use super::{nudge, random_position, step, Primitive};
+use crate::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// An axis-aligned rectangle.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub top_left: [isize; 2],
+    pub size: [isize; 2],
+}
+
+impl Primitive for Rect {
+    /// Sides are up to twice the largest radius long.
+    fn random(
+        _config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let (min_radius, max_radius) = (*radii.start() as usize, *radii.end() as usize);
+
+        let top_left = random_position(width, height, rng);
+        let size = [
+            rng.random_range(2 * min_radius - 1..=2 * max_radius) as isize,
+            rng.random_range(2 * min_radius - 1..=2 * max_radius) as isize,
+        ];
+
+        Rect { top_left, size }
+    }
+
+    /// Rectangles aren't clipped.
+    fn points_into(&self, _bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        let ([x0, y0], [width, height]) = (self.top_left, self.size);
+
+        points.clear();
+        points.extend((y0..y0 + height).flat_map(|y| (x0..x0 + width).map(move |x| [x, y])));
+    }
+
+    fn center(&self) -> [isize; 2] {
+        let ([x, y], [width, height]) = (self.top_left, self.size);
+        [x + width / 2, y + height / 2]
+    }
+
+    fn radius(&self) -> isize {
+        self.size[0].max(self.size[1]) / 2
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        let ([x, y], [width, height]) = (self.top_left, self.size);
+        [x, y, x + width - 1, y + height - 1]
+    }
+
+    fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        let [x, y] = self.top_left;
+
+        Rect {
+            top_left: [x + dx, y + dy],
+            ..*self
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        let ([x, y], [w, h]) = (self.top_left, self.size);
+
+        Rect {
+            top_left: [width as isize - x - w, y],
+            size: [w, h],
+        }
+    }
+
+    /// Rectangles stay axis-aligned, so they're turned by the closest
+    /// multiple of a quarter turn.
+    fn rotate(&self, (width, height): (u32, u32), angle: f32) -> Self {
+        let ([x, y], [w, h]) = (self.top_left, self.size);
+
+        // Twice the offset of the center from the canvas center, which is
+        // always a whole number of pixels
+        let [dx, dy] = [2 * x + w - width as isize, 2 * y + h - height as isize];
+        let ([dx, dy], [w, h]) = match (angle / std::f32::consts::FRAC_PI_2).round() as isize & 3 {
+            0 => ([dx, dy], [w, h]),
+            1 => ([-dy, dx], [h, w]),
+            2 => ([-dx, -dy], [w, h]),
+            _ => ([dy, -dx], [h, w]),
+        };
+
+        Rect {
+            top_left: [
+                (dx + width as isize - w).div_euclid(2),
+                (dy + height as isize - h).div_euclid(2),
+            ],
+            size: [w, h],
+        }
+    }
+
+    fn scale(&self, factor: u32) -> Self {
+        let factor = factor as isize;
+
+        Rect {
+            top_left: self.top_left.map(|coordinate| coordinate * factor),
+            size: self.size.map(|side| side * factor),
+        }
+    }
+
+    fn perturb(&self, _config: &Config, _width: u32, _height: u32, rng: &mut impl Rng) -> Self {
+        let step = step(self.radius());
+        let ([x, y], [w, h]) = (self.top_left, self.size);
+
+        if rng.random_bool(0.5) {
+            Rect {
+                top_left: [nudge(x, step, rng), nudge(y, step, rng)],
+                ..*self
+            }
+        } else {
+            Rect {
+                size: [nudge(w, step, rng).max(1), nudge(h, step, rng).max(1)],
+                ..*self
+            }
+        }
+    }
+
+    /// Rectangles are aligned with the pixel grid, so they cover their
+    /// pixels completely.
+    fn coverage(&self, _point: [isize; 2]) -> f32 {
+        1.0
+    }
+}
@@ -0,0 +1,145 @@
+use super::circle::{annulus_points_into, circle_points_into};
+use super::{
+    mirror_point, nudge, radius_limits, random_position, rotate_point, scale_point, step, Primitive,
+};
+use crate::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A circle outline `width` pixels thick, centered on the circle of
+/// `radius`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ring {
+    pub center: [isize; 2],
+    pub radius: isize,
+    pub width: u32,
+}
+
+impl Primitive for Ring {
+    /// The thickness is [`Config::stroke_width`].
+    fn random(
+        config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let center = random_position(width, height, rng);
+        let radius = rng.random_range(*radii.start() as usize..=*radii.end() as usize) as isize;
+
+        Ring {
+            center,
+            radius,
+            width: config.stroke_width,
+        }
+    }
+
+    /// Rings aren't clipped.
+    fn points_into(&self, _bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        let [x, y] = self.center;
+        points.clear();
+        ring_points_into(x, y, self.radius, self.width, points);
+    }
+
+    fn center(&self) -> [isize; 2] {
+        self.center
+    }
+
+    fn radius(&self) -> isize {
+        self.radius
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        let [x, y] = self.center;
+        let outer = self.radius + (self.width as isize - 1) / 2;
+        [x - outer, y - outer, x + outer, y + outer]
+    }
+
+    fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        let [x, y] = self.center;
+
+        Ring {
+            center: [x + dx, y + dy],
+            ..*self
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        Ring {
+            center: mirror_point(self.center, width),
+            ..*self
+        }
+    }
+
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self {
+        Ring {
+            center: rotate_point(self.center, bounds, angle),
+            ..*self
+        }
+    }
+
+    fn scale(&self, factor: u32) -> Self {
+        Ring {
+            center: scale_point(self.center, factor),
+            radius: self.radius * factor as isize,
+            width: self.width * factor,
+        }
+    }
+
+    fn perturb(&self, config: &Config, width: u32, height: u32, rng: &mut impl Rng) -> Self {
+        let (min_radius, max_radius) = radius_limits(config, width, height);
+        let step = step(self.radius);
+        let [x, y] = self.center;
+
+        if rng.random_bool(0.5) {
+            Ring {
+                center: [nudge(x, step, rng), nudge(y, step, rng)],
+                ..*self
+            }
+        } else {
+            Ring {
+                radius: nudge(self.radius, step, rng).clamp(min_radius, max_radius),
+                ..*self
+            }
+        }
+    }
+
+    /// From the distance between the pixel's center and the circle the ring
+    /// is centered on.
+    fn coverage(&self, [x, y]: [isize; 2]) -> f32 {
+        let [cx, cy] = self.center;
+        let distance = (((x - cx).pow(2) + (y - cy).pow(2)) as f32).sqrt();
+
+        (self.width as f32 / 2.0 + 0.5 - (distance - self.radius as f32).abs()).clamp(0.0, 1.0)
+    }
+}
+
+/// Returns every pixel of a ring `width` pixels thick around the circle of
+/// radius `r` centered on `(xc, yc)`.
+///
+/// One pixel wide rings are the outline of [`generate_circle_points`],
+/// which is thinner than the one pixel wide annulus; thicker ones are the pixels of the disk reaching `(width - 1) / 2` pixels
+/// past the circle that aren't in the disk ending `(width + 1) / 2` pixels
+/// inside it (both as [`generate_filled_circle_points`] draws them), so
+/// they have no holes. Each pixel is returned exactly once; points are not
+/// clipped.
+///
+/// [`generate_circle_points`]: super::generate_circle_points
+/// [`generate_filled_circle_points`]: super::generate_filled_circle_points
+pub fn generate_ring_points(xc: isize, yc: isize, r: isize, width: u32) -> Vec<[isize; 2]> {
+    let mut points = Vec::new();
+    ring_points_into(xc, yc, r, width, &mut points);
+    points
+}
+
+/// Appends the points of [`generate_ring_points`] to `points`.
+fn ring_points_into(xc: isize, yc: isize, r: isize, width: u32, points: &mut Vec<[isize; 2]>) {
+    let width = width as isize;
+
+    if width <= 1 {
+        circle_points_into(xc, yc, r, points)
+    } else {
+        annulus_points_into(xc, yc, r + (width - 1) / 2, r - (width + 1) / 2, points)
+    }
+}
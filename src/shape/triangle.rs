@@ -0,0 +1,180 @@
+use super::polygon::edge_coverage;
+use super::{
+    enclose, mirror_point, nudge, random_position, rotate_point, scale_point, step, Primitive,
+};
+use crate::Config;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// A filled triangle, with its corners in either winding order.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Triangle {
+    pub vertices: [[isize; 2]; 3],
+}
+
+impl Primitive for Triangle {
+    /// Corners are up to the largest radius away from a random point on
+    /// both axes.
+    fn random(
+        _config: &Config,
+        radii: RangeInclusive<u32>,
+        width: u32,
+        height: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let [x, y] = random_position(width, height, rng);
+        let max_radius = *radii.end() as isize;
+
+        let vertices = [(); 3].map(|_| {
+            [
+                x + rng.random_range(0..=2 * max_radius as usize) as isize - max_radius,
+                y + rng.random_range(0..=2 * max_radius as usize) as isize - max_radius,
+            ]
+        });
+
+        Triangle { vertices }
+    }
+
+    /// Triangles are clipped to `bounds`.
+    fn points_into(&self, bounds: (u32, u32), points: &mut Vec<[isize; 2]>) {
+        let [a, b, c] = self.vertices;
+
+        points.clear();
+        points.extend(generate_triangle_points(a, b, c, bounds));
+    }
+
+    fn center(&self) -> [isize; 2] {
+        let [x, y] = self
+            .vertices
+            .into_iter()
+            .fold([0, 0], |acc, [x, y]| [acc[0] + x, acc[1] + y]);
+
+        [x / 3, y / 3]
+    }
+
+    fn radius(&self) -> isize {
+        let [cx, cy] = self.center();
+
+        self.vertices
+            .into_iter()
+            .map(|[x, y]| (((x - cx).pow(2) + (y - cy).pow(2)) as f32).sqrt() as isize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn bounding_box(&self) -> [isize; 4] {
+        enclose(&self.vertices, 0)
+    }
+
+    fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        Triangle {
+            vertices: self.vertices.map(|[x, y]| [x + dx, y + dy]),
+        }
+    }
+
+    fn mirror(&self, width: u32) -> Self {
+        Triangle {
+            vertices: self.vertices.map(|point| mirror_point(point, width)),
+        }
+    }
+
+    fn rotate(&self, bounds: (u32, u32), angle: f32) -> Self {
+        Triangle {
+            vertices: self.vertices.map(|point| rotate_point(point, bounds, angle)),
+        }
+    }
+
+    fn scale(&self, factor: u32) -> Self {
+        Triangle {
+            vertices: self.vertices.map(|point| scale_point(point, factor)),
+        }
+    }
+
+    /// Moves one corner.
+    fn perturb(&self, _config: &Config, _width: u32, _height: u32, rng: &mut impl Rng) -> Self {
+        let step = step(self.radius());
+        let mut vertices = self.vertices;
+
+        let vertex = rng.random_range(0..3);
+        let [x, y] = vertices[vertex];
+        vertices[vertex] = [nudge(x, step, rng), nudge(y, step, rng)];
+
+        Triangle { vertices }
+    }
+
+    fn coverage(&self, point: [isize; 2]) -> f32 {
+        edge_coverage(&self.vertices, point)
+    }
+}
+
+/// Returns every pixel inside the triangle `abc`, clipped to `bounds`
+/// (width, height).
+///
+/// Pixels are tested against the three edge functions within the triangle's
+/// bounding box; either winding order works. Degenerate (collinear)
+/// triangles cover no pixels.
+pub fn generate_triangle_points(
+    a: [isize; 2],
+    b: [isize; 2],
+    c: [isize; 2],
+    bounds: (u32, u32),
+) -> Vec<[isize; 2]> {
+    // Twice the signed area of the triangle `p`, `q`, `r`
+    let edge = |p: [isize; 2], q: [isize; 2], r: [isize; 2]| {
+        (q[0] - p[0]) * (r[1] - p[1]) - (q[1] - p[1]) * (r[0] - p[0])
+    };
+
+    let area = edge(a, b, c);
+
+    if area == 0 {
+        return Vec::new();
+    }
+
+    let min_x = a[0].min(b[0]).min(c[0]).max(0);
+    let min_y = a[1].min(b[1]).min(c[1]).max(0);
+    let max_x = a[0].max(b[0]).max(c[0]).min(bounds.0 as isize - 1);
+    let max_y = a[1].max(b[1]).max(c[1]).min(bounds.1 as isize - 1);
+
+    let mut points = Vec::new();
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let p = [x, y];
+            let w = [edge(b, c, p), edge(c, a, p), edge(a, b, p)];
+
+            // Inside when every edge function agrees with the winding order
+            if w.iter().all(|&w| w * area.signum() >= 0) {
+                points.push(p);
+            }
+        }
+    }
+
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collinear_triangles_have_no_points() {
+        let bounds = (37, 23);
+
+        for [a, b, c] in [
+            [[5, 5], [5, 5], [5, 5]],
+            [[0, 4], [10, 4], [30, 4]],
+            [[-10, -10], [10, 10], [40, 40]],
+            [[3, 30], [3, -30], [3, 0]],
+            // On lines that never reach the canvas
+            [[-50, -20], [-30, -10], [-10, 0]],
+            [[40, 30], [400, 300], [4000, 3000]],
+            [[-1000, 23], [1000, 23], [0, 23]],
+        ] {
+            for [a, b, c] in [[a, b, c], [c, b, a], [b, c, a]] {
+                let points = generate_triangle_points(a, b, c, bounds);
+                assert!(points.is_empty(), "{a:?} {b:?} {c:?}: {points:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// How many of the latest shape centers new shapes keep their distance
+/// from.
+const REMEMBERED: usize = 64;
+
+/// The centers of the most recently drawn shapes, for turning away new ones
+/// that would clump on top of them (see
+/// [`Config::min_spacing`](crate::Config::min_spacing)).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct RecentCenters {
+    /// Oldest first.
+    centers: VecDeque<[isize; 2]>,
+}
+
+impl RecentCenters {
+    /// Remembers `center`, forgetting the oldest center if there are too
+    /// many.
+    pub(crate) fn push(&mut self, center: [isize; 2]) {
+        if self.centers.len() == REMEMBERED {
+            self.centers.pop_front();
+        }
+
+        self.centers.push_back(center);
+    }
+
+    /// Returns whether any remembered center is closer than `spacing` to
+    /// `center`.
+    pub(crate) fn crowds(&self, [x, y]: [isize; 2], spacing: f32) -> bool {
+        let limit = spacing as f64 * spacing as f64;
+
+        self.centers.iter().any(|&[other_x, other_y]| {
+            let [dx, dy] = [(x - other_x) as f64, (y - other_y) as f64];
+            dx * dx + dy * dy < limit
+        })
+    }
+
+    /// Returns a copy with every center moved by `offset`.
+    pub(crate) fn translate(&self, [dx, dy]: [isize; 2]) -> Self {
+        Self {
+            centers: self.centers.iter().map(|&[x, y]| [x + dx, y + dy]).collect(),
+        }
+    }
+}
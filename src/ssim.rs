@@ -0,0 +1,210 @@
+use crate::{Color, Image, Loss, Point};
+
+/// Side length of the square windows SSIM is computed over, in pixels;
+/// images smaller than this use a window as large as they are.
+const WINDOW: u32 = 7;
+
+/// Stabilizes the luminance term for dark windows.
+const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+
+/// Stabilizes the contrast and structure terms for flat windows.
+const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Returns the structural similarity between two images of the same size,
+/// averaged over every 7x7 window of their luma; `1.0` means identical, and
+/// lower values mean the local means, contrasts or structures differ.
+///
+/// # Panics
+///
+/// Panics if the images aren't the same size.
+pub fn ssim(a: &Image, b: &Image) -> f32 {
+    assert_eq!(
+        (a.width, a.height),
+        (b.width, b.height),
+        "images must be the same size"
+    );
+
+    let size = window(a);
+    let region = [0, 0, a.width, a.height];
+    let windows = ((a.width - size + 1) * (a.height - size + 1)) as f64;
+
+    (ssim_sum(&luma(a, region), &luma(b, region), region, size) / windows) as f32
+}
+
+/// One minus the [`ssim`] of every window, summed, so a perfect
+/// approximation has a loss of zero.
+///
+/// Every change needs the SSIM of all the windows overlapping it, each about
+/// 50 times the work of a per-pixel loss, so ticks are several times slower
+/// than with [`SquaredError`](crate::SquaredError). Only the windows around
+/// the changed pixels are recomputed, which keeps that independent of the
+/// image size. The importance map is ignored.
+///
+/// [`Loss::pixel`] is the squared difference in luma, which is only used to
+/// decide where the approximation is furthest off.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ssim;
+
+impl Loss for Ssim {
+    fn pixel(&self, a: Color, b: Color) -> f32 {
+        (luminance(a) - luminance(b)).powi(2) as f32
+    }
+
+    fn total(&self, target: &Image, approx: &Image) -> f64 {
+        let size = window(target);
+        let region = [0, 0, target.width, target.height];
+        let windows = ((target.width - size + 1) * (target.height - size + 1)) as f64;
+
+        windows - ssim_sum(&luma(target, region), &luma(approx, region), region, size)
+    }
+
+    fn delta(&self, target: &Image, approx: &Image, changes: &[(Point, Color)]) -> f64 {
+        let Some(region) = affected_region(target, changes) else {
+            return 0.0;
+        };
+
+        let size = window(target);
+        let [x0, y0, width, _] = region;
+
+        let target = luma(target, region);
+        let before = luma(approx, region);
+        let mut after = before.clone();
+
+        for &([x, y], color) in changes {
+            after[((y - y0) * width + x - x0) as usize] = luminance(color);
+        }
+
+        ssim_sum(&target, &before, region, size) - ssim_sum(&target, &after, region, size)
+    }
+}
+
+/// Returns the side length of the windows used for `image`.
+fn window(image: &Image) -> u32 {
+    WINDOW.min(image.width).min(image.height)
+}
+
+/// Returns the region, as `[x, y, width, height]`, covering every window
+/// that overlaps one of the changed pixels, or `None` if there are none.
+fn affected_region(image: &Image, changes: &[(Point, Color)]) -> Option<[u32; 4]> {
+    let size = window(image);
+
+    let (mut min, mut max) = ([u32::MAX; 2], [0; 2]);
+
+    for &(pos, _) in changes {
+        for i in 0..2 {
+            min[i] = min[i].min(pos[i]);
+            max[i] = max[i].max(pos[i]);
+        }
+    }
+
+    if changes.is_empty() {
+        return None;
+    }
+
+    // The first and last window corners that still reach the changes
+    let limits = [image.width - size, image.height - size];
+    let first = [0, 1].map(|i| min[i].saturating_sub(size - 1).min(limits[i]));
+    let last = [0, 1].map(|i| max[i].min(limits[i]));
+
+    Some([
+        first[0],
+        first[1],
+        last[0] - first[0] + size,
+        last[1] - first[1] + size,
+    ])
+}
+
+/// Returns the luma of every pixel of `image` inside `region`, in row-major
+/// order.
+fn luma(image: &Image, [x0, y0, width, height]: [u32; 4]) -> Vec<f64> {
+    (y0..y0 + height)
+        .flat_map(|y| (x0..x0 + width).map(move |x| [x, y]))
+        .map(|pos| luminance(image.color_at(pos)))
+        .collect()
+}
+
+/// Returns the luma of a color, from `0.0` to `255.0`.
+fn luminance([r, g, b]: Color) -> f64 {
+    0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64
+}
+
+/// Sums the SSIM of every `size`x`size` window lying inside `region`, given
+/// the luma of both images over it.
+fn ssim_sum(a: &[f64], b: &[f64], [_, _, width, height]: [u32; 4], size: u32) -> f64 {
+    let (width, height, size) = (width as usize, height as usize, size as usize);
+
+    // Summed-area tables of a, b, a², b² and ab, with a row and column of
+    // zeros in front
+    let stride = width + 1;
+    let mut table = vec![[0.0f64; 5]; stride * (height + 1)];
+
+    for y in 0..height {
+        let mut row = [0.0; 5];
+
+        for x in 0..width {
+            let (a, b) = (a[y * width + x], b[y * width + x]);
+            let values = [a, b, a * a, b * b, a * b];
+
+            for i in 0..5 {
+                row[i] += values[i];
+                table[(y + 1) * stride + x + 1][i] = table[y * stride + x + 1][i] + row[i];
+            }
+        }
+    }
+
+    let n = (size * size) as f64;
+    let mut total = 0.0;
+
+    for y in 0..=height - size {
+        for x in 0..=width - size {
+            let [top_left, top_right] = [table[y * stride + x], table[y * stride + x + size]];
+            let [bottom_left, bottom_right] = [
+                table[(y + size) * stride + x],
+                table[(y + size) * stride + x + size],
+            ];
+
+            let sum: [f64; 5] = std::array::from_fn(|i| {
+                (bottom_right[i] - bottom_left[i] - top_right[i] + top_left[i]) / n
+            });
+            let [mean_a, mean_b, square_a, square_b, product] = sum;
+
+            let variance_a = square_a - mean_a * mean_a;
+            let variance_b = square_b - mean_b * mean_b;
+            let covariance = product - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2));
+        }
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    /// Returns a 32x24 image of diagonal stripes, moved `shift` pixels to
+    /// the right.
+    fn stripes(shift: u32) -> Image {
+        Image::from(RgbImage::from_fn(32, 24, |x, y| {
+            let level = ((x + 64 - shift + y) % 8 * 32) as u8;
+            [level, level / 2, 255 - level].into()
+        }))
+    }
+
+    #[test]
+    fn identical_images_are_fully_similar() {
+        assert!((ssim(&stripes(0), &stripes(0)) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn shifted_images_are_less_similar() {
+        let one = ssim(&stripes(0), &stripes(1));
+        let four = ssim(&stripes(0), &stripes(4));
+
+        assert!(one < 0.9, "shifted by one pixel: {one}");
+        assert!(four < one, "shifted by four pixels: {four}");
+    }
+}
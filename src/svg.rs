@@ -0,0 +1,158 @@
+use crate::{Blob, Circle, Color, Config, Dot, Ellipse, Image, Line, PlacedShape, Rect, Ring, Shape};
+use std::fmt::Write;
+
+/// How many pieces a blob's radial gradient is split into.
+const BLOB_STOPS: usize = 8;
+
+/// Renders `shapes`, drawn in order over the background
+/// [`Config::background`] gives `target`, as an SVG document the size of
+/// `target`.
+///
+/// Pixel `[x, y]` covers the unit square from `(x, y)` to `(x + 1, y + 1)`,
+/// so shapes are positioned through pixel centers. Without antialiasing,
+/// disks and ellipses are widened by half a pixel, the way the rasterizer
+/// includes every pixel whose center they cover. Grayscale targets get gray
+/// shapes, and blobs fade out through radial gradients.
+pub fn svg(target: &Image, shapes: &[PlacedShape], config: &Config) -> String {
+    let (width, height) = (target.width, target.height);
+    let widen = if config.antialias { 0.0 } else { 0.5 };
+
+    let mut svg = String::new();
+
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    )
+    .unwrap();
+    let background = hex(config.background(target));
+    writeln!(svg, r#"<rect width="{width}" height="{height}" fill="{background}"/>"#).unwrap();
+
+    for (i, placed) in shapes.iter().enumerate() {
+        let color = hex(target.quantize(placed.color));
+
+        // Translucent shapes blend with what's underneath, like in the raster
+        let opacity = if config.opacity < 1.0 {
+            format!(r#" opacity="{}""#, config.opacity)
+        } else {
+            String::new()
+        };
+
+        let element = match placed.shape {
+            Shape::Circle(Circle {
+                center: [x, y],
+                radius,
+                filled: true,
+            }) => format!(
+                r#"<circle cx="{}" cy="{}" r="{}" fill="{color}"{opacity}/>"#,
+                x as f32 + 0.5,
+                y as f32 + 0.5,
+                radius as f32 + widen
+            ),
+
+            Shape::Circle(Circle {
+                center: [x, y],
+                radius,
+                filled: false,
+            }) => format!(
+                r#"<circle cx="{}" cy="{}" r="{radius}" fill="none" stroke="{color}"{opacity}/>"#,
+                x as f32 + 0.5,
+                y as f32 + 0.5
+            ),
+
+            Shape::Dot(Dot {
+                center: [x, y],
+                radius,
+            }) => format!(
+                r#"<circle cx="{}" cy="{}" r="{}" fill="{color}"{opacity}/>"#,
+                x as f32 + 0.5,
+                y as f32 + 0.5,
+                radius as f32 + widen
+            ),
+
+            Shape::Ring(Ring {
+                center: [x, y],
+                radius,
+                width,
+            }) => format!(
+                r#"<circle cx="{}" cy="{}" r="{radius}" fill="none" stroke="{color}" stroke-width="{width}"{opacity}/>"#,
+                x as f32 + 0.5,
+                y as f32 + 0.5
+            ),
+
+            Shape::Rect(Rect {
+                top_left: [x, y],
+                size: [w, h],
+            }) => format!(r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" fill="{color}"{opacity}/>"#),
+
+            Shape::Triangle(_) | Shape::Polygon(_) => {
+                let points = placed
+                    .shape
+                    .vertices()
+                    .into_iter()
+                    .map(|[x, y]| format!("{},{}", x as f32 + 0.5, y as f32 + 0.5))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                format!(r#"<polygon points="{points}" fill="{color}"{opacity}/>"#)
+            }
+
+            Shape::Line(Line {
+                endpoints: [a, b],
+                width,
+            }) => format!(
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{color}" stroke-width="{width}"{opacity}/>"#,
+                a[0] as f32 + 0.5,
+                a[1] as f32 + 0.5,
+                b[0] as f32 + 0.5,
+                b[1] as f32 + 0.5
+            ),
+
+            Shape::Ellipse(Ellipse {
+                center: [x, y],
+                radii: [rx, ry],
+                angle,
+            }) => {
+                let [cx, cy] = [x as f32 + 0.5, y as f32 + 0.5];
+
+                format!(
+                    r#"<ellipse cx="{cx}" cy="{cy}" rx="{}" ry="{}" transform="rotate({} {cx} {cy})" fill="{color}"{opacity}/>"#,
+                    rx as f32 + widen,
+                    ry as f32 + widen,
+                    angle.to_degrees()
+                )
+            }
+
+            Shape::Blob(Blob {
+                center: [x, y],
+                radius,
+                sigma,
+            }) => {
+                let [cx, cy] = [x as f32 + 0.5, y as f32 + 0.5];
+                let r = radius as f32 + 0.5;
+
+                // The Gaussian falloff, sampled along the radius
+                let stops: String = (0..=BLOB_STOPS)
+                    .map(|stop| {
+                        let offset = stop as f32 / BLOB_STOPS as f32;
+                        let falloff = (-(offset * r).powi(2) / (2.0 * sigma * sigma)).exp();
+
+                        format!(r#"<stop offset="{offset}" stop-color="{color}" stop-opacity="{falloff:.3}"/>"#)
+                    })
+                    .collect();
+
+                format!(
+                    r#"<radialGradient id="blob{i}" gradientUnits="userSpaceOnUse" cx="{cx}" cy="{cy}" r="{r}">{stops}</radialGradient><circle cx="{cx}" cy="{cy}" r="{r}" fill="url(#blob{i})"{opacity}/>"#
+                )
+            }
+        };
+
+        writeln!(svg, "{}", element).unwrap();
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn hex([r, g, b]: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
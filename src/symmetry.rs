@@ -0,0 +1,37 @@
+use crate::{Primitive, Shape};
+use std::f32::consts::TAU;
+
+/// Symmetric copies every shape is drawn with, about the center of the
+/// canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Symmetry {
+    /// Shapes are drawn once.
+    #[default]
+    None,
+    /// Shapes are mirrored left to right.
+    Mirror,
+    /// Shapes are repeated this many times around the center, evenly
+    /// spaced.
+    Radial(u32),
+}
+
+impl Symmetry {
+    /// Returns how many times each shape is drawn, itself included.
+    pub fn count(self) -> u32 {
+        match self {
+            Symmetry::None => 1,
+            Symmetry::Mirror => 2,
+            Symmetry::Radial(copies) => copies.max(1),
+        }
+    }
+
+    /// Returns the copies of `shape` on a canvas of size `bounds` (width,
+    /// height), not including `shape` itself. They may fall partly or
+    /// entirely off the canvas.
+    pub fn copies(self, shape: Shape, bounds: (u32, u32)) -> impl Iterator<Item = Shape> {
+        (1..self.count()).map(move |i| match self {
+            Symmetry::Mirror => shape.mirror(bounds.0),
+            _ => shape.rotate(bounds, TAU * i as f32 / self.count() as f32),
+        })
+    }
+}
@@ -0,0 +1,187 @@
+//! Bindings for running approximations in the browser, built with the `wasm`
+//! feature for `wasm32-unknown-unknown` and loaded through `wasm-bindgen`.
+//!
+//! Images go in and come out as raw RGBA bytes, row by row, the layout of
+//! `ImageData` on a canvas. Settings are a JSON object of [`Config`] fields
+//! in camel case, like `{"shape": "rect", "fill": true}`: `shape`, `fill`,
+//! `strokeWidth`, `dotSize`, `sides`, `antialias`, `opacity`, `linear`,
+//! `minRadius`, `maxRadius`, `refine` and `errorGuided`.
+
+use crate::{flatten, Approximator, CirclezError, Config, Image, ShapeKind};
+use clap::ValueEnum;
+use image::{DynamicImage, RgbaImage};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+/// The [`Config`] fields that can be set from JavaScript, in camel case;
+/// missing fields keep their defaults.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+struct Settings {
+    /// One of the `--shape` names, like `"circle"` or `"rect"`.
+    shape: Option<String>,
+    fill: bool,
+    stroke_width: u32,
+    dot_size: u32,
+    sides: u32,
+    antialias: bool,
+    opacity: f32,
+    linear: bool,
+    min_radius: u32,
+    max_radius: Option<u32>,
+    refine: bool,
+    error_guided: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let config = Config::default();
+
+        Self {
+            shape: None,
+            fill: config.fill,
+            stroke_width: config.stroke_width,
+            dot_size: config.dot_size,
+            sides: config.sides,
+            antialias: config.antialias,
+            opacity: config.opacity,
+            linear: config.linear,
+            min_radius: config.min_radius,
+            max_radius: config.max_radius,
+            refine: config.refine,
+            error_guided: config.error_guided,
+        }
+    }
+}
+
+impl Settings {
+    /// Parses `json`, an empty string meaning the defaults.
+    fn parse(json: &str) -> Result<Self, JsError> {
+        if json.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        serde_json::from_str(json).map_err(|err| JsError::new(&format!("Invalid settings: {err}")))
+    }
+
+    fn config(self) -> Result<Config, JsError> {
+        let shape = match &self.shape {
+            Some(name) => ShapeKind::from_str(name, true)
+                .map_err(|_| JsError::new(&format!("Invalid settings: unknown shape {name:?}")))?,
+            None => ShapeKind::default(),
+        };
+
+        Ok(Config {
+            shape,
+            fill: self.fill,
+            stroke_width: self.stroke_width,
+            dot_size: self.dot_size,
+            sides: self.sides,
+            antialias: self.antialias,
+            opacity: self.opacity,
+            linear: self.linear,
+            min_radius: self.min_radius,
+            max_radius: self.max_radius,
+            refine: self.refine,
+            error_guided: self.error_guided,
+            ..Config::default()
+        })
+    }
+}
+
+/// Turns `rgba` into a target, flattening transparency onto black like the
+/// command line does.
+fn target(rgba: &[u8], width: u32, height: u32) -> Result<Image, JsError> {
+    let image = RgbaImage::from_raw(width, height, rgba.to_vec()).ok_or_else(|| {
+        JsError::new(&format!(
+            "expected {} bytes for a {width}x{height} image, got {}",
+            width as usize * height as usize * 4,
+            rgba.len()
+        ))
+    })?;
+
+    Ok(Image::from(flatten(&image, [0, 0, 0])))
+}
+
+/// Returns the RGBA bytes of `image`, fully opaque.
+fn rgba(image: &Image) -> Vec<u8> {
+    DynamicImage::from(image).into_rgba8().into_raw()
+}
+
+/// Approximates the `width`x`height` image `rgba` with `iterations` ticks,
+/// returning the result as RGBA bytes of the same size.
+///
+/// The same seed and settings always give the same result.
+#[wasm_bindgen]
+pub fn approximate(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    settings: &str,
+    iterations: usize,
+    seed: u32,
+) -> Result<Vec<u8>, JsError> {
+    let mut approximation = Approximation::new(rgba, width, height, settings, seed)?;
+    approximation.step(iterations);
+
+    Ok(approximation.pixels())
+}
+
+/// An approximation in progress, for rendering it as it improves: call
+/// [`Approximation::step`] every animation frame and draw
+/// [`Approximation::pixels`].
+#[wasm_bindgen]
+pub struct Approximation {
+    approximator: Approximator<StdRng>,
+}
+
+#[wasm_bindgen]
+impl Approximation {
+    /// Starts approximating the `width`x`height` image `rgba`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+        settings: &str,
+        seed: u32,
+    ) -> Result<Approximation, JsError> {
+        let target = target(rgba, width, height)?;
+        let config = Settings::parse(settings)?.config()?;
+
+        config
+            .validate(width, height)
+            .map_err(CirclezError::InvalidRadius)?;
+
+        Ok(Self {
+            approximator: Approximator::new(target, config, StdRng::seed_from_u64(seed as u64)),
+        })
+    }
+
+    /// Runs `ticks` more ticks, returning whether any of them drew a shape.
+    pub fn step(&mut self, ticks: usize) -> bool {
+        self.approximator.run(ticks)
+    }
+
+    /// Returns the approximation so far as RGBA bytes.
+    pub fn pixels(&self) -> Vec<u8> {
+        rgba(self.approximator.approx())
+    }
+
+    /// Returns how many ticks have run.
+    pub fn ticks(&self) -> usize {
+        self.approximator.ticks()
+    }
+
+    /// Returns how many shapes have been drawn.
+    pub fn shapes(&self) -> usize {
+        self.approximator.shapes().len()
+    }
+
+    /// Returns the loss against the target.
+    pub fn loss(&self) -> f64 {
+        self.approximator.approx().total_loss()
+    }
+}
@@ -0,0 +1,38 @@
+//! Runs the binary with `--output -` and checks that standard output carries
+//! the image and nothing else.
+
+#![cfg(feature = "native")]
+
+use image::{ImageFormat, ImageReader, RgbImage};
+use std::io::Cursor;
+use std::process::Command;
+
+#[test]
+fn stdout_only_carries_the_image() {
+    let dir = std::env::temp_dir().join(format!("circlez-stdout-{}", std::process::id()));
+    let target = dir.join("target.png");
+    std::fs::create_dir_all(&dir).unwrap();
+    RgbImage::from_fn(40, 30, |x, y| [x as u8 * 6, y as u8 * 8, 128].into()).save(&target).unwrap();
+
+    // Debug messages go to standard error too
+    for verbose in [&[][..], &["--verbose"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_circlez"))
+            .arg(&target)
+            .args(["--headless", "--iterations", "200", "--seed", "1", "--output", "-"])
+            .args(verbose)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+        // Nothing before the PNG signature or after the closing chunk
+        let stdout = output.stdout;
+        assert!(stdout.starts_with(b"\x89PNG\r\n\x1a\n"), "{verbose:?}");
+        assert!(stdout.ends_with(b"IEND\xae\x42\x60\x82"), "{verbose:?}");
+
+        let image = ImageReader::with_format(Cursor::new(&stdout), ImageFormat::Png);
+        let image = image.decode().unwrap();
+        assert_eq!((image.width(), image.height()), (40, 30));
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}